@@ -0,0 +1,144 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::scan::sort_audio_files;
+use crate::{scan_audio_files_with, watch_opened_directory, AudioFile};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ScanProgress {
+    scan_id: u64,
+    directory: String,
+    // 今回情報が揃ったファイル（前回までの通知に含まれたものは入らない）
+    files: Vec<AudioFile>,
+    scanned: usize,
+    total: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ScanComplete {
+    scan_id: u64,
+    directory: String,
+    // 全てのファイル（キャンセルした場合はそれまでに揃ったものだけ）
+    files: Vec<AudioFile>,
+    cancelled: bool,
+    error: Option<String>,
+}
+
+// バックグラウンドで実行中のスキャン（IDごとのキャンセルフラグ）
+pub struct ScanState {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, Arc<AtomicBool>>>,
+}
+
+impl ScanState {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+fn run_scan(
+    app: &AppHandle,
+    scan_id: u64,
+    directory: &str,
+    max_depth: Option<usize>,
+    sort_by: &str,
+    descending: bool,
+    cancel: &AtomicBool,
+) -> Result<Vec<AudioFile>, String> {
+    let mut audio_files =
+        scan_audio_files_with(directory, max_depth, app, cancel, |files, scanned, total| {
+            let _ = app.emit(
+                "scan-progress",
+                ScanProgress {
+                    scan_id,
+                    directory: directory.to_string(),
+                    files: files.to_vec(),
+                    scanned,
+                    total,
+                },
+            );
+        })?;
+    watch_opened_directory(app, directory, max_depth);
+    sort_audio_files(&mut audio_files, sort_by, descending)?;
+    Ok(audio_files)
+}
+
+// get_audio_filesと同じスキャンをバックグラウンドで行い、スキャンIDをすぐに返す
+// 進捗はscan-progress、結果はscan-completeで通知する
+#[tauri::command]
+pub fn start_scan(
+    directory: String,
+    max_depth: Option<usize>,
+    sort_by: Option<String>,
+    descending: Option<bool>,
+    state: tauri::State<ScanState>,
+    app: AppHandle,
+) -> Result<u64, String> {
+    if !Path::new(&directory).is_dir() {
+        return Err("Invalid directory".to_string());
+    }
+
+    let scans = state.inner();
+    let scan_id = scans.next_id.fetch_add(1, Ordering::Relaxed);
+    let cancel = Arc::new(AtomicBool::new(false));
+    scans.active.lock().unwrap().insert(scan_id, cancel.clone());
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = run_scan(
+            &app,
+            scan_id,
+            &directory,
+            max_depth,
+            sort_by.as_deref().unwrap_or("name"),
+            descending.unwrap_or(false),
+            &cancel,
+        );
+        app.state::<ScanState>()
+            .active
+            .lock()
+            .unwrap()
+            .remove(&scan_id);
+
+        let (files, error) = match result {
+            Ok(files) => (files, None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+        let _ = app.emit(
+            "scan-complete",
+            ScanComplete {
+                scan_id,
+                directory,
+                files,
+                cancelled: cancel.load(Ordering::Relaxed),
+                error,
+            },
+        );
+    });
+
+    Ok(scan_id)
+}
+
+// scan_idを省略した場合は実行中の全てのスキャンを止める
+#[tauri::command]
+pub fn cancel_scan(scan_id: Option<u64>, state: tauri::State<ScanState>) -> Result<(), String> {
+    let active = state.inner().active.lock().unwrap();
+    match scan_id {
+        Some(scan_id) => active
+            .get(&scan_id)
+            .ok_or("Scan is not running")?
+            .store(true, Ordering::Relaxed),
+        None => {
+            for cancel in active.values() {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use rodio::buffer::SamplesBuffer;
+use std::collections::{HashMap, VecDeque};
+
+// 既定の上限（サンプル数。f32なので約256MB）
+pub const DEFAULT_CACHE_SAMPLES: usize = 64 * 1024 * 1024;
+
+struct CachedSamples {
+    buffer: SamplesBuffer,
+    len: usize,
+}
+
+// デコード済みのサンプルのキャッシュ（パスごと）
+// 合計サンプル数が上限を超えたら、最後に使ってから最も時間が経ったものから捨てる
+pub struct SampleCache {
+    entries: HashMap<String, CachedSamples>,
+    // 使った順（末尾が最新）
+    order: VecDeque<String>,
+    total_samples: usize,
+    max_samples: usize,
+}
+
+impl SampleCache {
+    pub fn new(max_samples: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            total_samples: 0,
+            max_samples,
+        }
+    }
+
+    fn touch(&mut self, path: &str) {
+        if let Some(index) = self.order.iter().position(|cached| cached == path) {
+            if let Some(cached) = self.order.remove(index) {
+                self.order.push_back(cached);
+            }
+        }
+    }
+
+    // 再生用に複製を返す（使ったものとして最新にする）
+    pub fn get(&mut self, path: &str) -> Option<SamplesBuffer> {
+        let buffer = self.entries.get(path)?.buffer.clone();
+        self.touch(path);
+        Some(buffer)
+    }
+
+    // 上限より大きいファイルは入れずにfalseを返す
+    pub fn insert(
+        &mut self,
+        path: &str,
+        channels: rodio::ChannelCount,
+        sample_rate: rodio::SampleRate,
+        samples: Vec<rodio::Sample>,
+    ) -> bool {
+        let len = samples.len();
+        if len > self.max_samples {
+            return false;
+        }
+
+        self.remove(path);
+        self.entries.insert(
+            path.to_string(),
+            CachedSamples {
+                buffer: SamplesBuffer::new(channels, sample_rate, samples),
+                len,
+            },
+        );
+        self.order.push_back(path.to_string());
+        self.total_samples += len;
+        self.evict();
+        true
+    }
+
+    pub fn remove(&mut self, path: &str) -> bool {
+        let Some(cached) = self.entries.remove(path) else {
+            return false;
+        };
+        self.total_samples -= cached.len;
+        self.order.retain(|cached| cached != path);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.total_samples = 0;
+    }
+
+    pub fn set_max_samples(&mut self, max_samples: usize) {
+        self.max_samples = max_samples;
+        self.evict();
+    }
+
+    pub fn max_samples(&self) -> usize {
+        self.max_samples
+    }
+
+    fn evict(&mut self) {
+        while self.total_samples > self.max_samples {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(cached) = self.entries.remove(&oldest) {
+                self.total_samples -= cached.len;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(cache: &mut SampleCache, path: &str, len: usize) -> bool {
+        cache.insert(path, 1, 8000, vec![0.0; len])
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = SampleCache::new(300);
+        assert!(insert(&mut cache, "a", 100));
+        assert!(insert(&mut cache, "b", 100));
+        assert!(insert(&mut cache, "c", 100));
+
+        // aを使ったので、上限を超えたときはbが先に捨てられる
+        assert!(cache.get("a").is_some());
+        assert!(insert(&mut cache, "d", 100));
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+        assert!(cache.get("d").is_some());
+        assert_eq!(cache.total_samples, 300);
+    }
+
+    #[test]
+    fn rejects_oversized_and_shrinks_on_new_limit() {
+        let mut cache = SampleCache::new(300);
+        assert!(!insert(&mut cache, "huge", 301));
+        assert!(cache.get("huge").is_none());
+
+        // 同じパスを入れ直しても二重に数えない
+        assert!(insert(&mut cache, "a", 200));
+        assert!(insert(&mut cache, "a", 100));
+        assert!(insert(&mut cache, "b", 150));
+        assert_eq!(cache.total_samples, 250);
+
+        cache.set_max_samples(150);
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some());
+        assert_eq!(cache.total_samples, 150);
+
+        assert!(cache.remove("b"));
+        assert!(!cache.remove("b"));
+        assert_eq!(cache.total_samples, 0);
+    }
+}
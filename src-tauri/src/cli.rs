@@ -0,0 +1,176 @@
+use std::sync::atomic::AtomicBool;
+use std::thread;
+use std::time::Duration;
+
+use crate::player::AudioPlayer;
+use crate::scan::{scan_directory, ScanOptions};
+
+// 再生の終了を確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub const USAGE: &str = "使い方:
+  sound-pad                          ウィンドウを開いて起動する
+  sound-pad play <file> [--volume V] ファイルを再生し、終わるまで待つ（Vは0.0〜2.0）
+  sound-pad list <dir> [--depth N]   音声ファイルのパスと長さ（秒）をタブ区切りで表示する
+                                     （Nはフォルダの深さ。0で無制限、省略時は1）
+  sound-pad serve                    ウィンドウを隠して起動し、保存したリモート操作・OSCの待ち受けを再開する";
+
+// ウィンドウを開かずに実行するコマンド
+pub enum CliCommand {
+    Play { path: String, volume: Option<f32> },
+    List { directory: String, max_depth: usize },
+    Serve,
+    Help,
+}
+
+fn option_value<T: std::str::FromStr>(args: &[String], name: &str) -> Result<Option<T>, String> {
+    let Some(position) = args.iter().position(|arg| arg == name) else {
+        return Ok(None);
+    };
+    let value = args
+        .get(position + 1)
+        .ok_or_else(|| format!("{} の値がありません", name))?;
+    value
+        .parse()
+        .map(Some)
+        .map_err(|_| format!("{} の値が正しくありません: {}", name, value))
+}
+
+// 最初の引数がコマンド名でなければNone（通常どおりウィンドウを開く）
+// OSやtauriが渡す引数でウィンドウが開かなくならないよう、知らない引数は無視する
+pub fn parse(args: &[String]) -> Result<Option<CliCommand>, String> {
+    let Some(command) = args.first() else {
+        return Ok(None);
+    };
+    let rest = &args[1..];
+    let command = match command.as_str() {
+        "play" => CliCommand::Play {
+            path: rest
+                .first()
+                .cloned()
+                .ok_or("再生するファイルを指定してください")?,
+            volume: option_value(rest, "--volume")?,
+        },
+        "list" => CliCommand::List {
+            directory: rest.first().cloned().ok_or("フォルダを指定してください")?,
+            max_depth: option_value(rest, "--depth")?.unwrap_or(1),
+        },
+        "serve" => CliCommand::Serve,
+        "help" | "--help" | "-h" => CliCommand::Help,
+        _ => return Ok(None),
+    };
+    Ok(Some(command))
+}
+
+fn play(path: &str, volume: Option<f32>) -> Result<(), String> {
+    let player = AudioPlayer::new();
+    player.open_stream()?;
+    if let Some(volume) = volume {
+        player.set_volume(volume)?;
+    }
+    player.play(path)?;
+    while player.is_playing() {
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+// アプリと同じ方法でスキャンする（設定やキャッシュは使わず、既定の拡張子のファイルが対象）
+fn list(directory: &str, max_depth: usize) -> Result<(), String> {
+    let audio_files = scan_directory(
+        directory,
+        Some(max_depth),
+        &ScanOptions::uncached(),
+        &AtomicBool::new(false),
+        |_, _, _| {},
+    )?;
+
+    for file in audio_files {
+        let duration = file
+            .duration_seconds
+            .map(|seconds| format!("{:.2}", seconds))
+            .unwrap_or_default();
+        println!("{}\t{}", file.path, duration);
+    }
+    Ok(())
+}
+
+// serve以外のコマンドを実行し、終了コードを返す
+pub fn execute(command: CliCommand) -> i32 {
+    let result = match command {
+        CliCommand::Play { path, volume } => play(&path, volume),
+        CliCommand::List {
+            directory,
+            max_depth,
+        } => list(&directory, max_depth),
+        CliCommand::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        CliCommand::Serve => Ok(()),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_play_with_volume() {
+        let command = parse(&args(&["play", "a.wav", "--volume", "0.5"])).unwrap();
+        assert!(matches!(
+            command,
+            Some(CliCommand::Play { path, volume: Some(volume) }) if path == "a.wav" && volume == 0.5
+        ));
+    }
+
+    #[test]
+    fn parses_list_with_default_depth() {
+        let command = parse(&args(&["list", "/sounds"])).unwrap();
+        assert!(matches!(
+            command,
+            Some(CliCommand::List { directory, max_depth: 1 }) if directory == "/sounds"
+        ));
+        let command = parse(&args(&["list", "/sounds", "--depth", "0"])).unwrap();
+        assert!(matches!(
+            command,
+            Some(CliCommand::List { max_depth: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn parses_serve_and_help() {
+        assert!(matches!(
+            parse(&args(&["serve"])),
+            Ok(Some(CliCommand::Serve))
+        ));
+        for help in ["help", "--help", "-h"] {
+            assert!(matches!(parse(&args(&[help])), Ok(Some(CliCommand::Help))));
+        }
+    }
+
+    #[test]
+    fn unknown_arguments_open_the_window() {
+        assert!(matches!(parse(&[]), Ok(None)));
+        assert!(matches!(parse(&args(&["-psn_0_12345"])), Ok(None)));
+    }
+
+    #[test]
+    fn rejects_missing_or_invalid_values() {
+        assert!(parse(&args(&["play"])).is_err());
+        assert!(parse(&args(&["list"])).is_err());
+        assert!(parse(&args(&["play", "a.wav", "--volume"])).is_err());
+        assert!(parse(&args(&["list", "/sounds", "--depth", "deep"])).is_err());
+    }
+}
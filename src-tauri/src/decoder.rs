@@ -0,0 +1,256 @@
+use rodio::source::SeekError;
+use rodio::Source;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Duration;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::{Hint, ProbeResult};
+use symphonia::core::units::Time;
+
+// symphoniaで直接デコードするrodioのSource
+// rodioのDecoderはMP4/AACのデマルチプレクサを正しく選べないことがあるため、m4a/aacはこちらを使う
+pub struct SymphoniaSource {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    samples: Vec<f32>,
+    position: usize,
+    channels: u16,
+    sample_rate: u32,
+    total_duration: Option<Duration>,
+}
+
+impl SymphoniaSource {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let probed =
+            crate::scan::probe_format(path).map_err(|e| format!("デコーダーエラー: {}", e))?;
+        Self::from_probed(probed)
+    }
+
+    // メモリ上のデータから開く（拡張子のヒントがないため内容から形式を判別する）
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, String> {
+        let mss = MediaSourceStream::new(Box::new(Cursor::new(data)), Default::default());
+        let probed = symphonia::default::get_probe()
+            .format(
+                &Hint::new(),
+                mss,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .map_err(|e| format!("デコーダーエラー: {}", e))?;
+        Self::from_probed(probed)
+    }
+
+    fn from_probed(probed: ProbeResult) -> Result<Self, String> {
+        let format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or("デコーダーエラー: 再生できるトラックがありません")?;
+        let track_id = track.id;
+        let params = track.codec_params.clone();
+
+        let decoder = symphonia::default::get_codecs()
+            .make(&params, &DecoderOptions::default())
+            .map_err(|e| format!("デコーダーエラー: {}", e))?;
+
+        let total_duration = match (params.n_frames, params.sample_rate) {
+            (Some(n_frames), Some(sample_rate)) if sample_rate > 0 => {
+                Some(Duration::from_secs_f64(n_frames as f64 / sample_rate as f64))
+            }
+            _ => None,
+        };
+
+        let mut source = Self {
+            format,
+            decoder,
+            track_id,
+            samples: Vec::new(),
+            position: 0,
+            channels: params.channels.map(|c| c.count() as u16).unwrap_or(2),
+            sample_rate: params.sample_rate.unwrap_or(44100),
+            total_duration,
+        };
+
+        // 最初のパケットをデコードして、チャンネル数とサンプルレートを確定させる
+        source.decode_next_packet();
+        Ok(source)
+    }
+
+    // 次のパケットをデコードしてバッファを入れ替える（終端ではfalse）
+    fn decode_next_packet(&mut self) -> bool {
+        self.samples.clear();
+        self.position = 0;
+
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(_) => return false,
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    if decoded.frames() == 0 {
+                        continue;
+                    }
+                    let spec = *decoded.spec();
+                    let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+                    buffer.copy_interleaved_ref(decoded);
+
+                    self.samples.extend_from_slice(buffer.samples());
+                    self.channels = spec.channels.count() as u16;
+                    self.sample_rate = spec.rate;
+                    return true;
+                }
+                // 壊れたパケットは読み飛ばす
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(_) => return false,
+            }
+        }
+    }
+
+    fn skip_samples(&mut self, mut count: usize) {
+        while count > 0 {
+            let remaining = self.samples.len() - self.position;
+            if remaining > count {
+                self.position += count;
+                return;
+            }
+            count -= remaining;
+            if !self.decode_next_packet() {
+                return;
+            }
+        }
+    }
+}
+
+impl Iterator for SymphoniaSource {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = *self.samples.get(self.position)?;
+        self.position += 1;
+
+        // バッファを使い切ったら次のパケットを先読みしておく
+        if self.position >= self.samples.len() {
+            self.decode_next_packet();
+        }
+        Some(sample)
+    }
+}
+
+impl Source for SymphoniaSource {
+    fn current_span_len(&self) -> Option<usize> {
+        Some(self.samples.len() - self.position)
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let time = Time::new(pos.as_secs(), pos.subsec_nanos() as f64 / 1_000_000_000.0);
+        let seeked = self
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::Time {
+                    time,
+                    track_id: Some(self.track_id),
+                },
+            )
+            .map_err(|_| SeekError::NotSupported {
+                underlying_source: std::any::type_name::<Self>(),
+            })?;
+
+        self.decoder.reset();
+        self.decode_next_packet();
+
+        // シーク先のパケット先頭から目的の位置までを読み飛ばす
+        let skip_frames = seeked.required_ts.saturating_sub(seeked.actual_ts) as usize;
+        self.skip_samples(skip_frames * self.channels as usize);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, write_wav};
+
+    // frames個のフレームの全チャンネルに同じ値を書き出す（値は100フレームごとに繰り返す）
+    fn write_fixture(
+        name: &str,
+        channels: u16,
+        sample_rate: u32,
+        frames: usize,
+    ) -> std::path::PathBuf {
+        let samples = (0..frames).flat_map(|frame| {
+            let sample = ((frame % 100) as i16 - 50) * 300;
+            std::iter::repeat_n(sample, channels as usize)
+        });
+        write_wav(name, channels, sample_rate, samples)
+    }
+
+    #[test]
+    fn decodes_wav_with_format_and_length() {
+        let path = write_fixture("decoder", 2, 22050, 4410);
+        let source = SymphoniaSource::open(&path).unwrap();
+
+        assert_eq!(source.channels(), 2);
+        assert_eq!(source.sample_rate(), 22050);
+        assert_eq!(source.total_duration(), Some(Duration::from_millis(200)));
+
+        let samples: Vec<f32> = source.collect();
+        assert_eq!(samples.len(), 4410 * 2);
+        assert!((samples[0] - (-50.0 * 300.0 / 32768.0)).abs() < 1e-4);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn decodes_aac_in_mp4() {
+        // 無音のAAC-LC（44.1kHz・モノラル、1024フレームのパケット20個）をMP4に入れたもの
+        let source = SymphoniaSource::open(&fixture("silence.m4a")).unwrap();
+
+        assert_eq!(source.channels(), 1);
+        assert_eq!(source.sample_rate(), 44100);
+        assert_eq!(
+            source.total_duration(),
+            Some(Duration::from_secs_f64(20480.0 / 44100.0))
+        );
+
+        let samples: Vec<f32> = source.collect();
+        assert_eq!(samples.len(), 20480);
+        assert!(samples.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn seeks_to_exact_frame() {
+        let path = write_fixture("decoder-seek", 1, 8000, 8000);
+        let mut source = SymphoniaSource::open(&path).unwrap();
+
+        source.try_seek(Duration::from_millis(500)).unwrap();
+        assert_eq!(source.count(), 4000);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
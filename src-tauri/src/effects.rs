@@ -0,0 +1,359 @@
+use rodio::source::SeekError;
+use rodio::Source;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// ループ再生用のSource
+// enabledがtrueの間は終端（またはループ終了位置）でループ開始位置へシークして再生を続ける
+// フラグは再生中でも切り替えられ、falseにすると次に終端へ達した時点で終了する
+// remaining_repeatsが残っている間は、フラグがfalseでも1回ずつ減らしながらループ開始位置へ戻る
+// crossfadeを指定すると、ループの終わりの手前からループ開始位置の音を重ねて徐々に入れ替える
+pub struct LoopingSource<S> {
+    inner: S,
+    enabled: Arc<AtomicBool>,
+    remaining_repeats: Arc<AtomicU32>,
+    loop_start: Duration,
+    loop_end: Option<Duration>,
+    // ファイル先頭からのサンプル数（全チャンネル合計）
+    played_samples: u64,
+    crossfade: Duration,
+    // 1周目に記録したループ開始位置からの音（クロスフェードで重ねる）
+    head: Vec<rodio::Sample>,
+}
+
+impl<S: Source> LoopingSource<S> {
+    pub fn new(
+        inner: S,
+        enabled: Arc<AtomicBool>,
+        remaining_repeats: Arc<AtomicU32>,
+        loop_start: Duration,
+        loop_end: Option<Duration>,
+    ) -> Self {
+        Self {
+            inner,
+            enabled,
+            remaining_repeats,
+            loop_start,
+            loop_end,
+            played_samples: 0,
+            crossfade: Duration::ZERO,
+            head: Vec::new(),
+        }
+    }
+
+    pub fn with_crossfade(mut self, crossfade: Duration) -> Self {
+        self.crossfade = crossfade;
+        self
+    }
+
+    fn samples_at(&self, pos: Duration) -> u64 {
+        (pos.as_secs_f64() * self.inner.sample_rate() as f64) as u64 * self.inner.channels() as u64
+    }
+
+    // ループの終わりの位置（分からなければNone）
+    fn end_samples(&self) -> Option<u64> {
+        self.loop_end
+            .or_else(|| self.inner.total_duration())
+            .map(|end| self.samples_at(end))
+    }
+
+    // クロスフェードで重ねるサンプル数（ループ区間の半分まで。終わりが分からなければ0）
+    fn fade_samples(&self) -> u64 {
+        let Some(end) = self.end_samples() else {
+            return 0;
+        };
+        let channels = self.inner.channels().max(1) as u64;
+        let start = self.samples_at(self.loop_start);
+        self.samples_at(self.crossfade)
+            .min(end.saturating_sub(start) / 2 / channels * channels)
+    }
+
+    fn fade_ready(&self) -> bool {
+        let fade = self.fade_samples();
+        fade > 0 && self.head.len() as u64 >= fade
+    }
+
+    fn crossfade(&mut self, position: u64, sample: rodio::Sample, looping: bool) -> rodio::Sample {
+        let fade = self.fade_samples();
+        if fade == 0 {
+            return sample;
+        }
+
+        // ループ開始位置から途切れずに再生している間だけ記録する
+        let start = self.samples_at(self.loop_start);
+        if position >= start
+            && position < start + fade
+            && self.head.len() as u64 == position - start
+        {
+            self.head.push(sample);
+        }
+
+        let Some(end) = self.end_samples() else {
+            return sample;
+        };
+        if !looping || !self.fade_ready() || position + fade < end || position >= end {
+            return sample;
+        }
+        let offset = position + fade - end;
+        let t = offset as f32 / fade as f32;
+        sample * (1.0 - t) + self.head[offset as usize] * t
+    }
+
+    fn is_looping(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed) || self.remaining_repeats.load(Ordering::Relaxed) > 0
+    }
+
+    // ループ開始位置へ戻る（シークできないSourceではfalse）
+    // クロスフェードで開始位置からの音を鳴らし終えている場合は、その続きへ戻る
+    fn restart(&mut self) -> bool {
+        let skipped = if self.fade_ready() {
+            self.fade_samples()
+        } else {
+            0
+        };
+        let frames = skipped / self.inner.channels().max(1) as u64;
+        let target = self.loop_start
+            + Duration::from_secs_f64(frames as f64 / self.inner.sample_rate() as f64);
+        if self.inner.try_seek(target).is_err() {
+            return false;
+        }
+        self.played_samples = self.samples_at(self.loop_start) + skipped;
+        if !self.enabled.load(Ordering::Relaxed) {
+            let _ = self
+                .remaining_repeats
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+        }
+        true
+    }
+}
+
+impl<S: Source> Iterator for LoopingSource<S> {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let looping = self.is_looping();
+
+        if looping {
+            if let Some(loop_end) = self.loop_end {
+                if self.played_samples >= self.samples_at(loop_end) && !self.restart() {
+                    return None;
+                }
+            }
+        }
+
+        let sample = match self.inner.next() {
+            Some(sample) => sample,
+            None if looping && self.restart() => self.inner.next()?,
+            None => return None,
+        };
+        let position = self.played_samples;
+        self.played_samples += 1;
+        Some(self.crossfade(position, sample, looping))
+    }
+}
+
+impl<S: Source> Source for LoopingSource<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        if self.is_looping() {
+            None
+        } else {
+            self.inner.total_duration()
+        }
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.played_samples = self.samples_at(pos);
+        Ok(())
+    }
+}
+
+// 指定位置から再生するSource
+// シーク位置は開始位置からの相対時間として扱うため、区間再生でも再生位置とシーク先がずれない
+pub struct OffsetSource<S> {
+    inner: S,
+    offset: Duration,
+}
+
+impl<S: Source> OffsetSource<S> {
+    pub fn new(mut inner: S, offset: Duration) -> Result<Self, SeekError> {
+        if !offset.is_zero() {
+            inner.try_seek(offset)?;
+        }
+        Ok(Self { inner, offset })
+    }
+}
+
+impl<S: Source> Iterator for OffsetSource<S> {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<S: Source> Source for OffsetSource<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner
+            .total_duration()
+            .map(|duration| duration.saturating_sub(self.offset))
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(self.offset + pos)
+    }
+}
+
+// 左右の音量バランスを変えるSource（panは-1.0で左のみ、1.0で右のみ）
+// モノラルは左右に複製してステレオとして出力する
+pub struct StereoBalance<S> {
+    inner: S,
+    left_gain: f32,
+    right_gain: f32,
+    // モノラルを複製したときに次に返す右チャンネルのサンプル
+    pending_right: Option<rodio::Sample>,
+    // 次のサンプルのチャンネル番号
+    channel: rodio::ChannelCount,
+}
+
+impl<S: Source> StereoBalance<S> {
+    pub fn new(inner: S, pan: f32) -> Self {
+        let pan = pan.clamp(-1.0, 1.0);
+        Self {
+            inner,
+            left_gain: (1.0 - pan).min(1.0),
+            right_gain: (1.0 + pan).min(1.0),
+            pending_right: None,
+            channel: 0,
+        }
+    }
+}
+
+impl<S: Source> Iterator for StereoBalance<S> {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(sample) = self.pending_right.take() {
+            return Some(sample);
+        }
+
+        let sample = self.inner.next()?;
+        let channels = self.inner.channels();
+        if channels == 1 {
+            self.pending_right = Some(sample * self.right_gain);
+            return Some(sample * self.left_gain);
+        }
+
+        // 3チャンネル以上の場合は先頭の2チャンネルだけを左右として扱う
+        let gain = match self.channel {
+            0 => self.left_gain,
+            1 => self.right_gain,
+            _ => 1.0,
+        };
+        self.channel = (self.channel + 1) % channels;
+        Some(sample * gain)
+    }
+}
+
+impl<S: Source> Source for StereoBalance<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        let pending = usize::from(self.pending_right.is_some());
+        if self.inner.channels() == 1 {
+            self.inner.current_span_len().map(|len| len * 2 + pending)
+        } else {
+            self.inner.current_span_len()
+        }
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.inner.channels().max(2)
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.pending_right = None;
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+// 全チャンネルを平均して1チャンネルにするSource
+pub struct MonoDownmix<S> {
+    inner: S,
+}
+
+impl<S: Source> MonoDownmix<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S: Source> Iterator for MonoDownmix<S> {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let channels = self.inner.channels();
+        let mut sum = self.inner.next()?;
+        for _ in 1..channels {
+            // 途中で終わった場合は足りないチャンネルを無音として扱う
+            sum += self.inner.next().unwrap_or(0.0);
+        }
+        Some(sum / channels.max(1) as f32)
+    }
+}
+
+impl<S: Source> Source for MonoDownmix<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        let channels = self.inner.channels().max(1) as usize;
+        self.inner.current_span_len().map(|len| len / channels)
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        1
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
@@ -0,0 +1,300 @@
+use rodio::Source;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+use crate::decoder::SymphoniaSource;
+use crate::scan::get_audio_duration;
+use crate::silence::{self, TrimBounds};
+use crate::ConflictStrategy;
+
+// この間隔（サンプル数、全チャンネル合計）ごとに進捗を通知する
+const PROGRESS_INTERVAL: u64 = 1 << 20;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ConvertProgress {
+    // 何番目のファイルか（0始まり）
+    index: usize,
+    total: usize,
+    source: String,
+    // 書き出したパス（スキップ・失敗した場合はNone）
+    destination: Option<String>,
+    // trim_silenceを指定した場合に書き出した区間
+    trimmed: Option<TrimBounds>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ExportProgress {
+    source: String,
+    destination: String,
+    // 0.0〜1.0（長さが分からないファイルはNone）
+    progress: Option<f64>,
+}
+
+// ソースをデコードして16bit PCMのWAVとして書き出す（サンプルレートとチャンネル数は元のまま）
+// rangeを指定した場合は、その区間（開始・終了秒）だけを書き出す
+fn write_wav(
+    source: &str,
+    destination: &Path,
+    range: Option<(f64, f64)>,
+    app: &AppHandle,
+) -> Result<(), String> {
+    let mut decoded = SymphoniaSource::open(Path::new(source))?;
+    let spec = hound::WavSpec {
+        channels: decoded.channels(),
+        sample_rate: decoded.sample_rate(),
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let samples_at =
+        |seconds: f64| (seconds * spec.sample_rate as f64) as u64 * spec.channels as u64;
+    let (total_samples, limit) = match range {
+        Some((start, end)) => {
+            decoded
+                .try_seek(Duration::from_secs_f64(start))
+                .map_err(|e| format!("シークできませんでした: {}", e))?;
+            let samples = samples_at(end - start);
+            (Some(samples), Some(samples))
+        }
+        None => (
+            decoded
+                .total_duration()
+                .map(|d| samples_at(d.as_secs_f64())),
+            None,
+        ),
+    };
+
+    let mut writer = hound::WavWriter::create(destination, spec).map_err(|e| e.to_string())?;
+    let emit_progress = |written: u64| {
+        let _ = app.emit(
+            "export-progress",
+            ExportProgress {
+                source: source.to_string(),
+                destination: destination.to_string_lossy().to_string(),
+                progress: total_samples
+                    .filter(|total| *total > 0)
+                    .map(|total| (written as f64 / total as f64).min(1.0)),
+            },
+        );
+    };
+
+    let mut written: u64 = 0;
+    for sample in decoded.take(limit.map_or(usize::MAX, |limit| limit as usize)) {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        writer.write_sample(sample).map_err(|e| e.to_string())?;
+        written += 1;
+        if written.is_multiple_of(PROGRESS_INTERVAL) {
+            emit_progress(written);
+        }
+    }
+
+    writer.finalize().map_err(|e| e.to_string())?;
+    emit_progress(total_samples.unwrap_or(written));
+    Ok(())
+}
+
+// 書き出し先のフォルダがなければ作成する
+fn prepare_destination(source: &str, destination: &str) -> Result<PathBuf, String> {
+    let dest_path = Path::new(destination).to_path_buf();
+    if dest_path == Path::new(source) {
+        return Err("書き出し先が元のファイルと同じです".to_string());
+    }
+    if let Some(parent) = dest_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        if !parent.exists() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(dest_path)
+}
+
+// trimがtrueなら、range（省略時はファイル全体）から先頭・末尾の無音を除いた区間を返す
+fn trimmed_range(
+    source: &str,
+    range: Option<(f64, f64)>,
+    trim: bool,
+) -> Result<Option<TrimBounds>, String> {
+    if !trim {
+        return Ok(None);
+    }
+    silence::trim_bounds(Path::new(source), range).map(Some)
+}
+
+// デコードに時間がかかるため、ブロッキングスレッドで実行する
+// 途中で失敗した場合は書きかけのファイルを消す。無音を除いた場合はその区間を返す
+async fn export(
+    source: String,
+    dest_path: PathBuf,
+    range: Option<(f64, f64)>,
+    trim: bool,
+    app: AppHandle,
+) -> Result<Option<TrimBounds>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let trimmed = trimmed_range(&source, range, trim)?;
+        let range = trimmed.map(|bounds| bounds.range()).or(range);
+        write_wav(&source, &dest_path, range, &app).inspect_err(|_| {
+            let _ = fs::remove_file(&dest_path);
+        })?;
+        Ok(trimmed)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// trim_silenceがtrueなら先頭・末尾の無音を除いて書き出し、書き出した区間を返す
+#[tauri::command]
+pub async fn export_to_wav(
+    source: String,
+    destination: String,
+    trim_silence: Option<bool>,
+    app: AppHandle,
+) -> Result<Option<TrimBounds>, String> {
+    let dest_path = prepare_destination(&source, &destination)?;
+    export(source, dest_path, None, trim_silence.unwrap_or(false), app).await
+}
+
+// pathのstart〜end秒だけを切り出してWAVで書き出す（進捗はexport_to_wavと同じexport-progress）
+// trim_silenceはexport_to_wavと同じで、start〜endの中の無音を除く
+#[tauri::command]
+pub async fn export_clip(
+    path: String,
+    start: f64,
+    end: f64,
+    dest: String,
+    trim_silence: Option<bool>,
+    app: AppHandle,
+) -> Result<Option<TrimBounds>, String> {
+    let duration = get_audio_duration(Path::new(&path));
+    let valid = start >= 0.0 && start < end && duration.is_none_or(|duration| end <= duration);
+    if !valid {
+        return Err(
+            "Invalid range: start must be before end, and end must not exceed the duration"
+                .to_string(),
+        );
+    }
+
+    let dest_path = prepare_destination(&path, &dest)?;
+    let trim = trim_silence.unwrap_or(false);
+    export(path, dest_path, Some((start, end)), trim, app).await
+}
+
+// ソースをデコードして16bitのFLACとして書き出す（サンプルレートとチャンネル数は元のまま）
+// エンコーダーが全体を受け取る形式のため、デコードした全サンプルをメモリに置く
+// rangeはwrite_wavと同じ
+fn write_flac(source: &str, destination: &Path, range: Option<(f64, f64)>) -> Result<(), String> {
+    let mut decoded = SymphoniaSource::open(Path::new(source))?;
+    let channels = decoded.channels() as usize;
+    let sample_rate = decoded.sample_rate() as usize;
+    let limit = match range {
+        Some((start, end)) => {
+            decoded
+                .try_seek(Duration::from_secs_f64(start))
+                .map_err(|e| format!("シークできませんでした: {}", e))?;
+            ((end - start) * sample_rate as f64) as usize * channels
+        }
+        None => usize::MAX,
+    };
+    let mut samples: Vec<i32> = decoded
+        .take(limit)
+        .map(|sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i32)
+        .collect();
+    // 最後の1フレームに満たないサンプルは捨てる
+    samples.truncate(samples.len() / channels.max(1) * channels);
+
+    let config = flacenc::config::Encoder::default()
+        .into_verified()
+        .map_err(|(_, e)| e.to_string())?;
+    let flac_source = flacenc::source::MemSource::from_samples(&samples, channels, 16, sample_rate);
+    let stream = flacenc::encode_with_fixed_block_size(&config, flac_source, config.block_size)
+        .map_err(|e| e.to_string())?;
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    stream.write(&mut sink).map_err(|e| e.to_string())?;
+    fs::write(destination, sink.as_slice()).map_err(|e| e.to_string())
+}
+
+// 1ファイルを変換し、書き出したパスと無音を除いた区間を返す
+// （同名のファイルがありスキップした場合はNone）
+fn convert_file(
+    source: &str,
+    target_format: &str,
+    dest_dir: &Path,
+    strategy: ConflictStrategy,
+    trim: bool,
+    app: &AppHandle,
+) -> Result<Option<(PathBuf, Option<TrimBounds>)>, String> {
+    let stem = Path::new(source).file_stem().ok_or("Invalid file name")?;
+    let dest = dest_dir.join(stem).with_extension(target_format);
+    let Some(dest) = strategy.resolve(dest) else {
+        return Ok(None);
+    };
+    if dest == Path::new(source) {
+        return Err("書き出し先が元のファイルと同じです".to_string());
+    }
+
+    let trimmed = trimmed_range(source, None, trim)?;
+    let range = trimmed.map(|bounds| bounds.range());
+    let result = match target_format {
+        "wav" => write_wav(source, &dest, range, app),
+        _ => write_flac(source, &dest, range),
+    };
+    result.inspect_err(|_| {
+        let _ = fs::remove_file(&dest);
+    })?;
+    Ok(Some((dest, trimmed)))
+}
+
+// pathsをtarget_format（"wav"・"flac"）に変換してdest_dirへ書き出す
+// 1ファイル終わるごとにconvert-progressを通知し、失敗したファイルは飛ばして続ける
+// conflictはcopy_filesと同じ（省略時は"rename"）。戻り値は書き出したパス
+// trim_silenceがtrueなら先頭・末尾の無音を除き、除いた区間はconvert-progressで通知する
+#[tauri::command]
+pub async fn convert_files(
+    paths: Vec<String>,
+    target_format: String,
+    dest_dir: String,
+    conflict: Option<String>,
+    trim_silence: Option<bool>,
+    app: AppHandle,
+) -> Result<Vec<String>, String> {
+    let target_format = target_format.trim().trim_start_matches('.').to_lowercase();
+    if !matches!(target_format.as_str(), "wav" | "flac") {
+        return Err(format!("Unsupported format: {}", target_format));
+    }
+    let strategy = ConflictStrategy::parse(conflict.as_deref())?;
+    let trim = trim_silence.unwrap_or(false);
+    let dest_path = Path::new(&dest_dir).to_path_buf();
+    if !dest_path.exists() {
+        fs::create_dir_all(&dest_path).map_err(|e| e.to_string())?;
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let total = paths.len();
+        let mut converted = Vec::new();
+        for (index, source) in paths.into_iter().enumerate() {
+            let (converted_file, error) =
+                match convert_file(&source, &target_format, &dest_path, strategy, trim, &app) {
+                    Ok(converted_file) => (converted_file, None),
+                    Err(e) => (None, Some(e)),
+                };
+            let trimmed = converted_file.as_ref().and_then(|(_, trimmed)| *trimmed);
+            let destination = converted_file.map(|(dest, _)| dest.to_string_lossy().to_string());
+            converted.extend(destination.clone());
+            let _ = app.emit(
+                "convert-progress",
+                ConvertProgress {
+                    index,
+                    total,
+                    source,
+                    destination,
+                    trimmed,
+                    error,
+                },
+            );
+        }
+        Ok(converted)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
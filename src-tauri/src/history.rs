@@ -0,0 +1,524 @@
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+use crate::get_app_data_file_path;
+use crate::library::open_library;
+
+// 履歴に残す最大件数（超えた分は古いものから捨てる）
+const MAX_HISTORY: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayOutcome {
+    // 最後まで再生した
+    Completed,
+    // 途中で止めた（別のファイルの再生で止まった場合も含む）
+    Stopped,
+}
+
+impl PlayOutcome {
+    // library.dbに保存する値（JSONと同じ表記）
+    fn as_str(self) -> &'static str {
+        match self {
+            PlayOutcome::Completed => "completed",
+            PlayOutcome::Stopped => "stopped",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "completed" => Some(PlayOutcome::Completed),
+            "stopped" => Some(PlayOutcome::Stopped),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    path: String,
+    // 再生した日時（UNIX時間のミリ秒）
+    played_at: u64,
+    // 再生中、または結果が分からない場合はNone
+    #[serde(default)]
+    outcome: Option<PlayOutcome>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SoundStats {
+    plays: u32,
+    completed: u32,
+    stopped: u32,
+    last_played_at: u64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SoundPlayCount {
+    path: String,
+    #[serde(flatten)]
+    stats: SoundStats,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DayPlayCount {
+    // UTCの日付（YYYY-MM-DD）
+    date: String,
+    plays: u32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PlayStatsSummary {
+    // 再生回数の多い順（全てのファイル）
+    most_played: Vec<SoundPlayCount>,
+    // 日付の古い順
+    per_day: Vec<DayPlayCount>,
+}
+
+// 以前の保存形式（history.json、新しいものが先頭）。library.dbへ取り込むときだけ読む
+#[derive(Debug, Deserialize)]
+struct History {
+    entries: VecDeque<HistoryEntry>,
+}
+
+// 以前の保存形式（play_stats.json）。library.dbへ取り込むときだけ読む
+#[derive(Debug, Deserialize)]
+struct PlayStats {
+    sounds: HashMap<String, SoundStats>,
+    // UTCの日付ごとの再生回数
+    days: BTreeMap<String, u32>,
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+// history.json・play_stats.jsonの内容をlibrary.dbに書き込む（ファイルがなければ何もしない）
+// 壊れていて読めない場合は、再生と同じく新しく作り直したものとして扱う
+pub fn import_json(connection: &Connection, app: &AppHandle) -> Result<(), String> {
+    let history_path = get_app_data_file_path(app, "history.json")?;
+    if let Some(history) = read_json::<History>(&history_path).unwrap_or_default() {
+        // 古いものから追加して、新しいものほど大きいIDにする
+        for entry in history.entries.iter().rev() {
+            connection
+                .execute(
+                    "INSERT INTO history (path, played_at, outcome) VALUES (?1, ?2, ?3)",
+                    params![
+                        entry.path,
+                        entry.played_at as i64,
+                        entry.outcome.map(PlayOutcome::as_str)
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    let stats_path = get_app_data_file_path(app, "play_stats.json")?;
+    if let Some(stats) = read_json::<PlayStats>(&stats_path).unwrap_or_default() {
+        for (path, sound) in stats.sounds {
+            connection
+                .execute(
+                    "INSERT OR REPLACE INTO play_stats \
+                     (path, plays, completed, stopped, last_played_at) \
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        path,
+                        sound.plays,
+                        sound.completed,
+                        sound.stopped,
+                        sound.last_played_at as i64
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+        }
+        for (date, plays) in stats.days {
+            connection
+                .execute(
+                    "INSERT OR REPLACE INTO play_days (date, plays) VALUES (?1, ?2)",
+                    params![date, plays],
+                )
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+// UNIX時間のミリ秒をUTCの日付（YYYY-MM-DD）にする
+fn utc_date(millis: u64) -> String {
+    // 1970-01-01からの日数を、3月始まりの暦で年・月・日に直す
+    let days = (millis / 86_400_000) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// 履歴に追加し、再生回数を数える
+// 直前と同じファイルを続けて再生した場合、履歴は日時だけ更新する（回数は数える）
+fn insert_play(connection: &mut Connection, path: &str, played_at: u64) -> Result<(), String> {
+    let tx = connection.transaction().map_err(|e| e.to_string())?;
+    let latest: Option<(i64, String)> = tx
+        .query_row(
+            "SELECT id, path FROM history ORDER BY id DESC LIMIT 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    match latest {
+        Some((id, latest_path)) if latest_path == path => {
+            tx.execute(
+                "UPDATE history SET played_at = ?2, outcome = NULL WHERE id = ?1",
+                params![id, played_at as i64],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        _ => {
+            tx.execute(
+                "INSERT INTO history (path, played_at) VALUES (?1, ?2)",
+                params![path, played_at as i64],
+            )
+            .map_err(|e| e.to_string())?;
+            tx.execute(
+                "DELETE FROM history WHERE id NOT IN \
+                 (SELECT id FROM history ORDER BY id DESC LIMIT ?1)",
+                params![MAX_HISTORY as i64],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    tx.execute(
+        "INSERT INTO play_stats (path, plays, last_played_at) VALUES (?1, 1, ?2) \
+         ON CONFLICT (path) DO UPDATE SET plays = plays + 1, last_played_at = ?2",
+        params![path, played_at as i64],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO play_days (date, plays) VALUES (?1, 1) \
+         ON CONFLICT (date) DO UPDATE SET plays = plays + 1",
+        params![utc_date(played_at)],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+// そのファイルの最新の履歴が結果未定なら結果を設定して数える
+// 同じ再生について2回目以降の記録は無視する
+fn set_outcome(
+    connection: &mut Connection,
+    path: &str,
+    outcome: PlayOutcome,
+) -> Result<(), String> {
+    let tx = connection.transaction().map_err(|e| e.to_string())?;
+    let updated = tx
+        .execute(
+            "UPDATE history SET outcome = ?2 WHERE id = \
+             (SELECT id FROM history WHERE path = ?1 ORDER BY id DESC LIMIT 1) \
+             AND outcome IS NULL",
+            params![path, outcome.as_str()],
+        )
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Ok(());
+    }
+
+    // play_statsの列名は保存する値と同じ
+    let column = outcome.as_str();
+    tx.execute(
+        &format!(
+            "UPDATE play_stats SET {0} = {0} + 1 WHERE path = ?1",
+            column
+        ),
+        params![path],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+enum HistoryEvent {
+    Played { path: String, played_at: u64 },
+    Finished { path: String, outcome: PlayOutcome },
+}
+
+// 履歴の書き込みを再生とは別のスレッドで行う（書き込みは呼び出した順に行われる）
+pub struct HistoryRecorder {
+    sender: Mutex<Option<mpsc::Sender<HistoryEvent>>>,
+}
+
+impl HistoryRecorder {
+    pub fn new() -> Self {
+        Self {
+            sender: Mutex::new(None),
+        }
+    }
+
+    // 書き込み用のスレッドは最初の記録で起動する
+    fn send(&self, app: &AppHandle, event: HistoryEvent) {
+        let mut sender = self.sender.lock().unwrap();
+        let sender = sender.get_or_insert_with(|| spawn_writer(app.clone()));
+        if sender.send(event).is_err() {
+            eprintln!("再生履歴を保存できませんでした: 書き込み用のスレッドが終了しています");
+        }
+    }
+}
+
+fn spawn_writer(app: AppHandle) -> mpsc::Sender<HistoryEvent> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut connection = None;
+        for event in receiver {
+            // 開けなかった場合は、次の記録でもう一度開き直す
+            if connection.is_none() {
+                match open_library(&app) {
+                    Ok(opened) => connection = Some(opened),
+                    Err(e) => {
+                        eprintln!("再生履歴を保存できませんでした: {}", e);
+                        continue;
+                    }
+                }
+            }
+            let Some(connection) = connection.as_mut() else {
+                continue;
+            };
+
+            let result = match event {
+                HistoryEvent::Played { path, played_at } => {
+                    insert_play(connection, &path, played_at)
+                }
+                HistoryEvent::Finished { path, outcome } => set_outcome(connection, &path, outcome),
+            };
+            if let Err(e) = result {
+                eprintln!("再生履歴を保存できませんでした: {}", e);
+            }
+        }
+    });
+    sender
+}
+
+// 再生に成功したファイルを履歴に追加し、再生回数を数える（書き込みは後で行われる）
+pub fn record(app: &AppHandle, path: &str) {
+    let played_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default();
+    app.state::<HistoryRecorder>().send(
+        app,
+        HistoryEvent::Played {
+            path: path.to_string(),
+            played_at,
+        },
+    );
+}
+
+// recordで記録した再生が、最後まで再生されたか途中で止められたかを記録する
+pub fn record_outcome(app: &AppHandle, path: &str, outcome: PlayOutcome) {
+    app.state::<HistoryRecorder>().send(
+        app,
+        HistoryEvent::Finished {
+            path: path.to_string(),
+            outcome,
+        },
+    );
+}
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<HistoryEntry> {
+    let outcome: Option<String> = row.get(2)?;
+    Ok(HistoryEntry {
+        path: row.get(0)?,
+        played_at: row.get::<_, i64>(1)? as u64,
+        outcome: outcome.as_deref().and_then(PlayOutcome::parse),
+    })
+}
+
+// 新しい順にlimit件まで返す
+#[tauri::command]
+pub fn get_history(limit: usize, app: AppHandle) -> Result<Vec<HistoryEntry>, String> {
+    let connection = open_library(&app)?;
+    let mut statement = connection
+        .prepare("SELECT path, played_at, outcome FROM history ORDER BY id DESC LIMIT ?1")
+        .map_err(|e| e.to_string())?;
+    let entries = statement
+        .query_map(params![limit as i64], entry_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+    Ok(entries)
+}
+
+// 最近再生したファイル（get_historyと同じ内容で、最後まで再生したかどうかも含む）
+#[tauri::command]
+pub fn get_recent_plays(limit: usize, app: AppHandle) -> Result<Vec<HistoryEntry>, String> {
+    get_history(limit, app)
+}
+
+#[tauri::command]
+pub fn get_play_stats(app: AppHandle) -> Result<PlayStatsSummary, String> {
+    let connection = open_library(&app)?;
+
+    let mut statement = connection
+        .prepare(
+            "SELECT path, plays, completed, stopped, last_played_at FROM play_stats \
+             ORDER BY plays DESC, path",
+        )
+        .map_err(|e| e.to_string())?;
+    let most_played = statement
+        .query_map([], |row| {
+            Ok(SoundPlayCount {
+                path: row.get(0)?,
+                stats: SoundStats {
+                    plays: row.get(1)?,
+                    completed: row.get(2)?,
+                    stopped: row.get(3)?,
+                    last_played_at: row.get::<_, i64>(4)? as u64,
+                },
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut statement = connection
+        .prepare("SELECT date, plays FROM play_days ORDER BY date")
+        .map_err(|e| e.to_string())?;
+    let per_day = statement
+        .query_map([], |row| {
+            Ok(DayPlayCount {
+                date: row.get(0)?,
+                plays: row.get(1)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(PlayStatsSummary {
+        most_played,
+        per_day,
+    })
+}
+
+#[tauri::command]
+pub fn clear_history(app: AppHandle) -> Result<(), String> {
+    let connection = open_library(&app)?;
+    connection
+        .execute("DELETE FROM history", [])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY_MILLIS: u64 = 86_400_000;
+
+    fn memory_library() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(crate::library::SCHEMA).unwrap();
+        connection
+    }
+
+    fn history(connection: &Connection) -> Vec<(String, Option<String>)> {
+        let mut statement = connection
+            .prepare("SELECT path, outcome FROM history ORDER BY id DESC")
+            .unwrap();
+        statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn utc_date_handles_epoch_and_leap_years() {
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(DAY_MILLIS - 1), "1970-01-01");
+        assert_eq!(utc_date(10_956 * DAY_MILLIS), "1999-12-31");
+        // 2000年は400で割り切れるため閏年
+        assert_eq!(utc_date(11_016 * DAY_MILLIS), "2000-02-29");
+        assert_eq!(utc_date(11_017 * DAY_MILLIS), "2000-03-01");
+        assert_eq!(utc_date(19_782 * DAY_MILLIS), "2024-02-29");
+        // 2100年は100で割り切れるため閏年ではない
+        assert_eq!(utc_date(47_540 * DAY_MILLIS), "2100-02-28");
+        assert_eq!(utc_date(47_541 * DAY_MILLIS), "2100-03-01");
+    }
+
+    #[test]
+    fn repeated_play_updates_latest_entry() {
+        let mut connection = memory_library();
+        insert_play(&mut connection, "a.wav", 1_000).unwrap();
+        set_outcome(&mut connection, "a.wav", PlayOutcome::Stopped).unwrap();
+        insert_play(&mut connection, "a.wav", 2_000).unwrap();
+        insert_play(&mut connection, "b.wav", 3_000).unwrap();
+
+        assert_eq!(
+            history(&connection),
+            [("b.wav".to_string(), None), ("a.wav".to_string(), None)]
+        );
+        let (plays, stopped): (u32, u32) = connection
+            .query_row(
+                "SELECT plays, stopped FROM play_stats WHERE path = 'a.wav'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((plays, stopped), (2, 1));
+    }
+
+    #[test]
+    fn outcome_is_recorded_once_per_play() {
+        let mut connection = memory_library();
+        insert_play(&mut connection, "a.wav", 1_000).unwrap();
+        set_outcome(&mut connection, "a.wav", PlayOutcome::Completed).unwrap();
+        set_outcome(&mut connection, "a.wav", PlayOutcome::Stopped).unwrap();
+
+        assert_eq!(
+            history(&connection),
+            [("a.wav".to_string(), Some("completed".to_string()))]
+        );
+        let (completed, stopped): (u32, u32) = connection
+            .query_row(
+                "SELECT completed, stopped FROM play_stats WHERE path = 'a.wav'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((completed, stopped), (1, 0));
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let mut connection = memory_library();
+        for index in 0..MAX_HISTORY + 5 {
+            insert_play(&mut connection, &format!("{}.wav", index), index as u64).unwrap();
+        }
+
+        let entries = history(&connection);
+        assert_eq!(entries.len(), MAX_HISTORY);
+        assert_eq!(entries[0].0, format!("{}.wav", MAX_HISTORY + 4));
+    }
+}
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+use crate::player::AudioPlayer;
+use crate::{emit_audio_error, emit_now_playing, get_app_data_file_path, spawn_voice_monitor};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HotkeyBinding {
+    accelerator: String,
+    path: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HotkeyTriggered {
+    accelerator: String,
+    path: String,
+    // 同時再生のID（stop_soundで止められる）
+    id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Hotkeys {
+    bindings: Vec<HotkeyBinding>,
+}
+
+impl Hotkeys {
+    fn new() -> Self {
+        Self {
+            bindings: Vec::new(),
+        }
+    }
+
+    fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let mut file = File::create(path).map_err(|e| e.to_string())?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // 表記揺れ（"ctrl+a" と "Ctrl+A" など）があっても同じキーとして探す
+    fn position(&self, shortcut: &Shortcut) -> Option<usize> {
+        self.bindings.iter().position(|binding| {
+            parse_accelerator(&binding.accelerator).is_ok_and(|bound| bound == *shortcut)
+        })
+    }
+}
+
+fn get_hotkeys_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_app_data_file_path(app, "hotkeys.json")
+}
+
+fn parse_accelerator(accelerator: &str) -> Result<Shortcut, String> {
+    accelerator
+        .parse::<Shortcut>()
+        .map_err(|e| format!("Invalid accelerator \"{}\": {}", accelerator, e))
+}
+
+// 他の音を止めないよう、同時再生で鳴らす
+fn trigger(app: &AppHandle, accelerator: &str, path: &str) {
+    let player = app.state::<AudioPlayer>();
+    match player.inner().play_polyphonic(path) {
+        Ok(id) => {
+            emit_now_playing(app, player.inner());
+            spawn_voice_monitor(player.inner().clone(), app.clone(), id.clone());
+            let _ = app.emit(
+                "hotkey-triggered",
+                HotkeyTriggered {
+                    accelerator: accelerator.to_string(),
+                    path: path.to_string(),
+                    id,
+                },
+            );
+        }
+        Err(e) => {
+            eprintln!("ホットキーの再生に失敗しました ({}): {}", accelerator, e);
+            emit_audio_error(app, path, &e);
+        }
+    }
+}
+
+fn register(app: &AppHandle, binding: &HotkeyBinding) -> Result<(), String> {
+    let shortcut = parse_accelerator(&binding.accelerator)?;
+    let accelerator = binding.accelerator.clone();
+    let path = binding.path.clone();
+
+    app.global_shortcut()
+        .on_shortcut(shortcut, move |app, _shortcut, event| {
+            // 押しっぱなしで連打にならないよう、押した瞬間だけ鳴らす
+            if event.state() == ShortcutState::Pressed {
+                trigger(app, &accelerator, &path);
+            }
+        })
+        .map_err(|e| format!("Failed to register {}: {}", binding.accelerator, e))
+}
+
+// 起動時に保存済みのホットキーを登録し直す（登録できないものはログだけ出して続行）
+pub fn register_saved_hotkeys(app: &AppHandle) {
+    let hotkeys = match get_hotkeys_file_path(app).and_then(|path| Hotkeys::load(&path)) {
+        Ok(hotkeys) => hotkeys,
+        Err(e) => {
+            eprintln!("ホットキーの読み込みに失敗しました: {}", e);
+            return;
+        }
+    };
+
+    for binding in &hotkeys.bindings {
+        if let Err(e) = register(app, binding) {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+// システム全体で有効なショートカットにファイルを割り当てる
+#[tauri::command]
+pub fn bind_hotkey(path: String, accelerator: String, app: AppHandle) -> Result<(), String> {
+    if !Path::new(&path).is_file() {
+        return Err("File does not exist".to_string());
+    }
+
+    let shortcut = parse_accelerator(&accelerator)?;
+    let hotkeys_path = get_hotkeys_file_path(&app)?;
+    let mut hotkeys = Hotkeys::load(&hotkeys_path)?;
+
+    if let Some(index) = hotkeys.position(&shortcut) {
+        return Err(format!(
+            "{} is already bound to {}",
+            accelerator, hotkeys.bindings[index].path
+        ));
+    }
+    if app.global_shortcut().is_registered(shortcut) {
+        return Err(format!("{} is already in use", accelerator));
+    }
+
+    let binding = HotkeyBinding { accelerator, path };
+    register(&app, &binding)?;
+
+    hotkeys.bindings.push(binding);
+    hotkeys.save(&hotkeys_path)
+}
+
+#[tauri::command]
+pub fn unbind_hotkey(accelerator: String, app: AppHandle) -> Result<(), String> {
+    let shortcut = parse_accelerator(&accelerator)?;
+    let hotkeys_path = get_hotkeys_file_path(&app)?;
+    let mut hotkeys = Hotkeys::load(&hotkeys_path)?;
+
+    let index = hotkeys
+        .position(&shortcut)
+        .ok_or_else(|| format!("{} is not bound", accelerator))?;
+    hotkeys.bindings.remove(index);
+
+    if app.global_shortcut().is_registered(shortcut) {
+        app.global_shortcut()
+            .unregister(shortcut)
+            .map_err(|e| e.to_string())?;
+    }
+
+    hotkeys.save(&hotkeys_path)
+}
+
+// bind_hotkeyと同じ（引数名はfile_path・shortcut）
+#[tauri::command]
+pub fn register_hotkey(file_path: String, shortcut: String, app: AppHandle) -> Result<(), String> {
+    bind_hotkey(file_path, shortcut, app)
+}
+
+// unbind_hotkeyと同じ
+#[tauri::command]
+pub fn unregister_hotkey(shortcut: String, app: AppHandle) -> Result<(), String> {
+    unbind_hotkey(shortcut, app)
+}
+
+#[tauri::command]
+pub fn get_hotkeys(app: AppHandle) -> Result<Vec<HotkeyBinding>, String> {
+    let hotkeys_path = get_hotkeys_file_path(&app)?;
+    Ok(Hotkeys::load(&hotkeys_path)?.bindings)
+}
@@ -1,289 +1,2321 @@
-use rodio::{Decoder, OutputStream, OutputStreamBuilder, Sink};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use rand::seq::IndexedRandom;
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::Write;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
 use std::thread;
 use std::time::Duration;
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
+use symphonia::core::meta::MetadataRevision;
 use tauri::{AppHandle, Emitter, Manager};
-use walkdir::WalkDir;
+
+mod background_scan;
+mod cache;
+mod cli;
+mod decoder;
+mod effects;
+mod export;
+mod history;
+mod hotkeys;
+mod library;
+mod loudness;
+mod midi;
+mod mirror;
+mod osc;
+mod player;
+mod profiles;
+mod recorder;
+mod remote;
+mod scan;
+mod silence;
+mod tags;
+#[cfg(test)]
+mod test_support;
+mod tts;
+mod watcher;
+mod waveform;
+
+use background_scan::ScanState;
+use cli::CliCommand;
+use history::{HistoryRecorder, PlayOutcome};
+use loudness::LoudnessState;
+use midi::MidiState;
+use osc::{OscSettings, OscState};
+use player::{fade_out_sinks, parse_playback_id, playback_id, spawn_envelope, AudioPlayer};
+use recorder::Recorder;
+use remote::{RemoteServer, RemoteSettings};
+use scan::{
+    get_audio_duration, probe_format, scan_directory, sort_audio_files, ProbeCacheState,
+    ScanOptions, AUDIO_EXTENSIONS,
+};
+use watcher::DirectoryWatcher;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AudioFile {
     name: String,
     path: String,
     duration_seconds: Option<f64>,
+    // スキャンしたフォルダ（複数フォルダをまとめて読み込んだ場合のグループ分けに使う）
+    source_directory: String,
+    // 選択したフォルダからの相対フォルダ（直下のファイルは空文字）
+    subdirectory: String,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    // 更新日時（UNIXエポックからのミリ秒）
+    modified_at: Option<u64>,
+    size_bytes: Option<u64>,
+    sample_rate: Option<u32>,
+    channels: Option<u16>,
+    // 平均ビットレート（kbps）
+    bitrate_kbps: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct LoopPoints {
+    start_seconds: f64,
+    end_seconds: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AudioInfo {
+    duration_seconds: Option<f64>,
+    loop_points: Option<LoopPoints>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ActiveSound {
+    path: String,
+    position_seconds: f64,
+    paused: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct NowPlaying {
+    active: Vec<ActiveSound>,
+    master_volume: f32,
+    // 別のウィンドウでもキューの表示を揃えられるよう、キューの内容も送る
+    queue: PlayQueue,
+}
+
+// 一時停止・再開ボタンの表示用
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaybackState {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+// 再生キュー（indexは再生中の曲、Noneならキューは再生していない）
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct PlayQueue {
+    items: Vec<String>,
+    index: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AudioError {
+    path: String,
+    message: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct LoopState {
+    looping: bool,
+    // 残りの繰り返し回数
+    remaining_repeats: u32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct QueueAdvanced {
+    index: usize,
+    path: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CoverArt {
+    media_type: String,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AudioProgress {
+    path: String,
+    position_seconds: f64,
+    duration_seconds: Option<f64>,
+    // 位置と長さはファイル上の秒数なので、実際の残り時間は (duration - position) / speed になる
+    speed: f32,
+}
+
+// move_filesの結果（skipped・failedは移動元のパス）
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct MoveResult {
+    moved: Vec<String>,
+    // 移動先に同名のファイルがあり、conflictが"skip"だったもの
+    skipped: Vec<String>,
+    failed: Vec<FileFailure>,
+}
+
+// batch_renameの結果（skipped・failedは元のパスと理由）
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct RenameResult {
+    renamed: Vec<String>,
+    skipped: Vec<FileFailure>,
+    failed: Vec<FileFailure>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FileFailure {
+    path: String,
+    message: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct LatencyReport {
+    runs: u32,
+    average_ms: f64,
+    min_ms: f64,
+    max_ms: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MultiPadMember {
+    path: String,
+    #[serde(default)]
+    volume: Option<f32>,
+    #[serde(default)]
+    offset_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MultiPad {
+    name: String,
+    members: Vec<MultiPadMember>,
+}
+
+// 単発再生のオプション
+#[derive(Debug, Clone, Default)]
+struct PlayOptions {
+    looping: bool,
+    // ループ区間（Noneならファイル全体）
+    loop_region: Option<LoopPoints>,
+    fade_in: Option<Duration>,
+    // 最後まで再生した後に繰り返す回数（ループ再生とは別に数える）
+    repeats: u32,
+    // ファイルごとの音量補正（全体の音量に掛けてSinkに設定する。Noneは1.0）
+    gain: Option<f32>,
+    // 再生中の音量の変化（開始時の音量に使う。再生中の更新はspawn_envelopeで行う）
+    envelope: Vec<EnvelopePoint>,
+}
+
+// play_audioのオプション（JavaScriptからは1つのオブジェクトとして渡す）
+#[derive(Debug, Clone, Default, Deserialize)]
+struct PlayAudioOptions {
+    normalize: Option<bool>,
+    fade_in_millis: Option<u64>,
+    mode: Option<PlayMode>,
+    start_secs: Option<f64>,
+    end_secs: Option<f64>,
+    // お気に入りに保存したキューポイントの名前
+    cue: Option<String>,
+    skip_silence: Option<bool>,
+    // パッドごとの音量（0.0〜2.0。お気に入りの音量・正規化のゲインにさらに掛ける）
+    volume: Option<f32>,
+    // trueなら止めるまで繰り返す（overlapでは使わない）
+    looping: Option<bool>,
+}
+
+// パッドを押したときの動作
+// restart: 鳴っている音を止めて最初から（従来通り）
+// overlap: 鳴っている音はそのままで、同時再生として重ねる
+// toggle: 同じファイルが鳴っていれば止める（鳴っていなければrestartと同じ）
+// hold: restartと同じように鳴らし、release_padで止める（キーを離したときに呼ぶ）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PlayMode {
+    #[default]
+    Restart,
+    Overlap,
+    Toggle,
+    Hold,
+}
+
+// 再生状態の変化をフロントエンドに通知
+fn emit_now_playing(app: &AppHandle, player: &AudioPlayer) {
+    let _ = app.emit("now-playing-changed", player.now_playing());
+}
+
+// 埋め込みのアートワークを読み取る（スキャン時には読まず、必要な時だけ取得する）
+#[tauri::command]
+fn get_cover_art(path: String) -> Result<Option<CoverArt>, String> {
+    read_cover_art(Path::new(&path))
+}
+
+// アートワークをそのまま<img>のsrcに使える data URL で返す（埋め込みがなければNone）
+#[tauri::command]
+fn get_cover_art_data_url(path: String) -> Result<Option<String>, String> {
+    Ok(read_cover_art(Path::new(&path))?.map(|cover_art| {
+        format!(
+            "data:{};base64,{}",
+            cover_art.media_type,
+            BASE64_STANDARD.encode(&cover_art.data)
+        )
+    }))
+}
+
+fn read_cover_art(path: &Path) -> Result<Option<CoverArt>, String> {
+    let mut probed = probe_format(path)?;
+
+    let to_cover_art = |revision: &MetadataRevision| {
+        revision.visuals().first().map(|visual| CoverArt {
+            media_type: visual.media_type.clone(),
+            data: visual.data.to_vec(),
+        })
+    };
+
+    // コンテナ内のメタデータを優先し、なければ先頭のID3タグなどを見る
+    if let Some(cover_art) = probed.format.metadata().current().and_then(to_cover_art) {
+        return Ok(Some(cover_art));
+    }
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(cover_art) = metadata.current().and_then(to_cover_art) {
+            return Ok(Some(cover_art));
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// WAVファイルのsmplチャンクから埋め込みループポイントを読み取る
+// bextチャンクはタイムコードのみでループ情報を持たないため参照しない
+fn read_wav_loop_points(path: &Path) -> Option<LoopPoints> {
+    let mut file = File::open(path).ok()?;
+
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header).ok()?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return None;
+    }
+
+    let mut sample_rate: Option<u32> = None;
+    let mut loop_frames: Option<(u32, u32)> = None;
+
+    // チャンクを順に走査（dataチャンクは読み込まずにスキップ）
+    loop {
+        let mut chunk_header = [0u8; 8];
+        if file.read_exact(&mut chunk_header).is_err() {
+            break;
+        }
+        let size = read_u32_le(&chunk_header, 4)?;
+        // チャンクは2バイト境界にパディングされる
+        let padded_size = size as i64 + (size as i64 & 1);
+
+        match &chunk_header[0..4] {
+            b"fmt " if size >= 8 => {
+                let mut fmt = [0u8; 8];
+                file.read_exact(&mut fmt).ok()?;
+                sample_rate = read_u32_le(&fmt, 4);
+                file.seek(SeekFrom::Current(padded_size - 8)).ok()?;
+            }
+            // 不正に大きいsmplチャンクは無視する
+            b"smpl" if (36..=64 * 1024).contains(&size) => {
+                let mut data = vec![0u8; size as usize];
+                file.read_exact(&mut data).ok()?;
+                file.seek(SeekFrom::Current(padded_size - size as i64)).ok()?;
+
+                let num_loops = read_u32_le(&data, 28)?;
+                if num_loops > 0 {
+                    // 最初のループのみ使用（cue_id, type, start, end, fraction, play_count）
+                    let start = read_u32_le(&data, 36 + 8)?;
+                    let end = read_u32_le(&data, 36 + 12)?;
+                    if end > start {
+                        loop_frames = Some((start, end));
+                    }
+                }
+            }
+            _ => {
+                file.seek(SeekFrom::Current(padded_size)).ok()?;
+            }
+        }
+
+        if sample_rate.is_some() && loop_frames.is_some() {
+            break;
+        }
+    }
+
+    let sample_rate = sample_rate.filter(|rate| *rate > 0)? as f64;
+    let (start, end) = loop_frames?;
+
+    // smplのend位置はループに含まれるフレームを指す
+    Some(LoopPoints {
+        start_seconds: start as f64 / sample_rate,
+        end_seconds: (end as f64 + 1.0) / sample_rate,
+    })
+}
+
+#[tauri::command]
+fn get_audio_info(path: String) -> Result<AudioInfo, String> {
+    let path = Path::new(&path);
+    if !path.is_file() {
+        return Err("File not found".to_string());
+    }
+
+    Ok(AudioInfo {
+        duration_seconds: get_audio_duration(path),
+        loop_points: read_wav_loop_points(path),
+    })
+}
+
+// プローブ結果のキャッシュの保存先
+fn probe_cache_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_app_data_file_path(app, "duration_cache.json")
+}
+
+// 設定された同時プローブ数（設定がなければNone）
+fn scan_concurrency(app: &AppHandle) -> Option<usize> {
+    let settings_path = get_settings_file_path(app).ok()?;
+    Settings::load(&settings_path).ok()?.scan_concurrency
+}
+
+// 既定の拡張子に、設定で追加した拡張子を加えたもの（小文字・ドットなし）
+fn audio_extensions(app: &AppHandle) -> Vec<String> {
+    let mut extensions: Vec<String> = AUDIO_EXTENSIONS.iter().map(|ext| ext.to_string()).collect();
+    let extra = get_settings_file_path(app)
+        .and_then(|path| Settings::load(&path))
+        .map(|settings| settings.extra_extensions)
+        .unwrap_or_default();
+    for ext in extra {
+        if !extensions.contains(&ext) {
+            extensions.push(ext);
+        }
+    }
+    extensions
+}
+
+// max_depth: 省略時は1（選択したフォルダ直下のみ）、0はサブフォルダを無制限に探索
+fn scan_audio_files(directory: &str, max_depth: Option<usize>, app: &AppHandle) -> Result<Vec<AudioFile>, String> {
+    scan_audio_files_with(directory, max_depth, app, &AtomicBool::new(false), |_, _, _| {})
+}
+
+fn scan_audio_files_with(
+    directory: &str,
+    max_depth: Option<usize>,
+    app: &AppHandle,
+    cancel: &AtomicBool,
+    on_progress: impl FnMut(&[AudioFile], usize, usize),
+) -> Result<Vec<AudioFile>, String> {
+    let options = ScanOptions::cached(
+        audio_extensions(app),
+        scan_concurrency(app),
+        app.state::<ProbeCacheState>().inner(),
+        probe_cache_path(app)?,
+    );
+    scan_directory(directory, max_depth, &options, cancel, on_progress)
+}
+
+// 開いたフォルダを監視し、ファイルの追加・削除をlibrary-changedで通知する
+// サブフォルダを探索した場合はサブフォルダも監視する
+fn watch_opened_directory(app: &AppHandle, directory: &str, max_depth: Option<usize>) {
+    let recursive = max_depth.unwrap_or(1) != 1;
+    let watcher = app.state::<DirectoryWatcher>();
+    if let Err(e) = watcher.inner().ensure_watching(directory, recursive, app) {
+        eprintln!("フォルダを監視できませんでした ({}): {}", directory, e);
+    }
+}
+
+// 結果をまとめて返す（進捗を受け取りたい場合はstart_scanを使う）
+#[tauri::command]
+async fn get_audio_files(
+    directory: String,
+    max_depth: Option<usize>,
+    sort_by: Option<String>,
+    descending: Option<bool>,
+    app: AppHandle,
+) -> Result<Vec<AudioFile>, String> {
+    // プローブに時間がかかってもUIを止めないよう、ブロッキングスレッドで実行する
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut audio_files = scan_audio_files(&directory, max_depth, &app)?;
+        watch_opened_directory(&app, &directory, max_depth);
+
+        // 既定はこれまで通りファイル名の昇順
+        sort_audio_files(
+            &mut audio_files,
+            sort_by.as_deref().unwrap_or("name"),
+            descending.unwrap_or(false),
+        )?;
+        Ok(audio_files)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// 複数のフォルダをまとめてスキャンする（存在しないフォルダは飛ばす）
+// フォルダが重なっている場合、同じファイルは最初に見つかったフォルダのものだけを返す
+// sort_byを省略した場合はdirectoriesの順に、各フォルダ内はファイル名順で並ぶ
+#[tauri::command]
+async fn get_audio_files_multi(
+    directories: Vec<String>,
+    max_depth: Option<usize>,
+    sort_by: Option<String>,
+    descending: Option<bool>,
+    app: AppHandle,
+) -> Result<Vec<AudioFile>, String> {
+    // get_audio_filesと同じく、UIを止めないようブロッキングスレッドでスキャンする
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut audio_files = Vec::new();
+        let mut seen = HashSet::new();
+
+        for directory in &directories {
+            let files = match scan_audio_files(directory, max_depth, &app) {
+                Ok(files) => files,
+                Err(e) => {
+                    eprintln!("フォルダをスキャンできませんでした ({}): {}", directory, e);
+                    continue;
+                }
+            };
+
+            for file in files {
+                let key = fs::canonicalize(&file.path).unwrap_or_else(|_| PathBuf::from(&file.path));
+                if seen.insert(key) {
+                    audio_files.push(file);
+                }
+            }
+        }
+
+        if let Some(sort_by) = sort_by.as_deref() {
+            sort_audio_files(&mut audio_files, sort_by, descending.unwrap_or(false))?;
+        }
+        Ok(audio_files)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// 検索用に小文字化し、連続する空白を1つにまとめる
+fn normalize_search_text(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+// ファイル名・タイトル・アーティストのいずれかにqueryを含むファイルだけを返す（queryが空なら全て）
+fn filter_audio_files(audio_files: Vec<AudioFile>, query: &str) -> Vec<AudioFile> {
+    let query = normalize_search_text(query);
+    if query.is_empty() {
+        return audio_files;
+    }
+
+    audio_files
+        .into_iter()
+        .filter(|file| {
+            [Some(&file.name), file.title.as_ref(), file.artist.as_ref()]
+                .into_iter()
+                .flatten()
+                .any(|text| normalize_search_text(text).contains(&query))
+        })
+        .collect()
+}
+
+#[tauri::command]
+async fn search_audio_files(
+    directory: String,
+    query: String,
+    max_depth: Option<usize>,
+    app: AppHandle,
+) -> Result<Vec<AudioFile>, String> {
+    // スキャンはget_audio_filesと同じくブロッキングスレッドで行う
+    tauri::async_runtime::spawn_blocking(move || {
+        let audio_files = scan_audio_files(&directory, max_depth, &app)?;
+        Ok(filter_audio_files(audio_files, &query))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// ファイルの内容のハッシュ（デコードはせず、バイト列をそのまま読む）
+fn hash_file(path: &Path) -> Result<blake3::Hash, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut file, &mut hasher).map_err(|e| e.to_string())?;
+    Ok(hasher.finalize())
+}
+
+// 内容が同じファイルをまとめる（2つ以上あるものだけ返す）
+// サイズが同じファイルだけをハッシュで比べるため、重複がなければほとんど読み込まない
+fn group_duplicates(audio_files: Vec<AudioFile>) -> Vec<Vec<String>> {
+    let mut by_size: HashMap<u64, Vec<String>> = HashMap::new();
+    for file in audio_files {
+        match fs::metadata(&file.path) {
+            Ok(metadata) => by_size.entry(metadata.len()).or_default().push(file.path),
+            Err(e) => eprintln!("ファイルを読み込めませんでした ({}): {}", file.path, e),
+        }
+    }
+
+    let mut groups = Vec::new();
+    for paths in by_size.into_values().filter(|paths| paths.len() > 1) {
+        let mut by_hash: HashMap<blake3::Hash, Vec<String>> = HashMap::new();
+        for path in paths {
+            match hash_file(Path::new(&path)) {
+                Ok(hash) => by_hash.entry(hash).or_default().push(path),
+                Err(e) => eprintln!("ファイルを読み込めませんでした ({}): {}", path, e),
+            }
+        }
+        groups.extend(by_hash.into_values().filter(|paths| paths.len() > 1));
+    }
+
+    // 結果の順番が毎回変わらないよう、グループ内・グループ間をパス順に並べる
+    for group in &mut groups {
+        group.sort();
+    }
+    groups.sort();
+    groups
+}
+
+// フォルダ内で内容が完全に同じファイルのグループを返す（探索する深さはget_audio_filesと同じ）
+#[tauri::command]
+async fn find_duplicates(
+    directory: String,
+    max_depth: Option<usize>,
+    app: AppHandle,
+) -> Result<Vec<Vec<String>>, String> {
+    // ファイル全体を読むため、ブロッキングスレッドで実行する
+    tauri::async_runtime::spawn_blocking(move || {
+        let audio_files = scan_audio_files(&directory, max_depth, &app)?;
+        Ok(group_duplicates(audio_files))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// スキャン時に同時にプローブするファイル数の上限を設定する（Noneで上限なし＝CPUのスレッド数）
+#[tauri::command]
+fn set_scan_concurrency(limit: Option<usize>, app: AppHandle) -> Result<(), String> {
+    if limit == Some(0) {
+        return Err("Concurrency limit must be at least 1".to_string());
+    }
+
+    let settings_path = get_settings_file_path(&app)?;
+    let mut settings = Settings::load(&settings_path).unwrap_or_default();
+    settings.scan_concurrency = limit;
+    settings.save(&settings_path)
+}
+
+#[tauri::command]
+fn get_scan_concurrency(app: AppHandle) -> Option<usize> {
+    scan_concurrency(&app)
+}
+
+fn normalize_loudness(app: &AppHandle) -> bool {
+    get_settings_file_path(app)
+        .and_then(|path| Settings::load(&path))
+        .is_ok_and(|settings| settings.normalize_loudness)
+}
+
+// play_audioでnormalizeを省略したときに、解析したラウドネスで音量を揃えるかを設定する
+#[tauri::command]
+fn set_normalize_loudness(enabled: bool, app: AppHandle) -> Result<(), String> {
+    let settings_path = get_settings_file_path(&app)?;
+    let mut settings = Settings::load(&settings_path).unwrap_or_default();
+    settings.normalize_loudness = enabled;
+    settings.save(&settings_path)
+}
+
+#[tauri::command]
+fn get_normalize_loudness(app: AppHandle) -> bool {
+    normalize_loudness(&app)
+}
+
+// 既定の拡張子の他にスキャンする拡張子を設定する（"mka" でも ".MKA" でもよい）
+#[tauri::command]
+fn set_extra_extensions(extensions: Vec<String>, app: AppHandle) -> Result<(), String> {
+    let mut extra: Vec<String> = Vec::new();
+    for ext in extensions {
+        let ext = ext.trim().trim_start_matches('.').to_lowercase();
+        if !ext.is_empty() && !extra.contains(&ext) {
+            extra.push(ext);
+        }
+    }
+
+    let settings_path = get_settings_file_path(&app)?;
+    let mut settings = Settings::load(&settings_path).unwrap_or_default();
+    settings.extra_extensions = extra;
+    settings.save(&settings_path)
+}
+
+// スキャンの対象になる全ての拡張子を返す
+#[tauri::command]
+fn get_audio_extensions(app: AppHandle) -> Vec<String> {
+    audio_extensions(&app)
+}
+
+// キュー再生中、残り時間がクロスフェードの長さを切ったら、今の曲をフェードアウトさせながら
+// 次の曲をフェードインで始める。始めた場合はtrue
+fn try_crossfade(player: &AudioPlayer, app: &AppHandle, label: &str, play_id: u64) -> bool {
+    let crossfade_millis = player.get_crossfade();
+    if crossfade_millis == 0 || !player.is_playing() {
+        return false;
+    }
+    let Some(index) = player.queue_index() else {
+        return false;
+    };
+    if player.queue_index_after(index).is_none() {
+        return false;
+    }
+    let (Some(position), Some(duration)) = (player.get_position(), player.get_duration()) else {
+        return false;
+    };
+
+    // 残り時間は実際の経過時間（速度で割る）で比べる
+    let remaining_seconds = (duration - position).max(0.0) / player.get_speed() as f64;
+    let remaining_millis = (remaining_seconds * 1000.0) as u64;
+    if remaining_millis > crossfade_millis {
+        return false;
+    }
+
+    let Some(sink) = player.detach_sink(play_id) else {
+        return false;
+    };
+    // 重なる長さは残り時間に合わせ、今の曲の終わりでフェードアウトが終わるようにする
+    let overlap = remaining_millis.max(10);
+    fade_out_sinks(vec![sink], overlap);
+
+    let _ = app.emit("audio-finished", label.to_string());
+    advance_queue(player, app, Some(Duration::from_millis(overlap)));
+    true
+}
+
+// 単発再生の終了と再生位置をバックグラウンドスレッドで監視
+// labelは再生中のファイルパス（メモリ再生の場合は識別子）、play_idは再生開始時に返された番号
+fn spawn_finish_monitor(player: AudioPlayer, app_handle: AppHandle, label: String, play_id: u64) {
+    thread::spawn(move || {
+        let mut label = label;
+        let mut tick: u32 = 0;
+
+        // Sinkが存在し、再生が完了するまで待つ
+        loop {
+            thread::sleep(Duration::from_millis(100));
+            tick += 1;
+
+            let is_empty = player.is_sink_empty();
+
+            // 後ろに追加したキューの曲に切り替わったら、同じSinkのまま監視を続ける
+            if let Some((index, path)) = player.take_gapless_transition(play_id) {
+                let _ = app_handle.emit("audio-finished", label.clone());
+                emit_now_playing(&app_handle, &player);
+                let _ = app_handle.emit(
+                    "queue-advanced",
+                    QueueAdvanced {
+                        index,
+                        path: path.clone(),
+                    },
+                );
+                label = path;
+                queue_gapless_next(&player, &app_handle, play_id);
+                continue;
+            }
+
+            if !is_empty && try_crossfade(&player, &app_handle, &label, play_id) {
+                break;
+            }
+
+            // 約200msごとに再生位置を通知（別の再生に切り替わった古いスレッドは送信しない）
+            if !is_empty && tick.is_multiple_of(2) && player.is_current_play(play_id) {
+                if let Some(position_seconds) = player.get_position() {
+                    let _ = app_handle.emit(
+                        "audio-progress",
+                        AudioProgress {
+                            path: label.clone(),
+                            position_seconds,
+                            duration_seconds: player.get_duration(),
+                            speed: player.get_speed(),
+                        },
+                    );
+                }
+            }
+
+            // Sinkが空になったら再生終了（一時停止中・ループ中のSinkは空にならない）
+            // 末尾へのシークで空になった場合も、このスレッドが一度だけ通知する
+            if is_empty {
+                // 停止された・別の再生に切り替わった場合はイベントを送信しない
+                if player.finish_play(play_id) {
+                    history::record_outcome(&app_handle, &label, PlayOutcome::Completed);
+                    let _ = app_handle.emit("audio-finished", label.clone());
+                    emit_now_playing(&app_handle, &player);
+                    advance_queue(&player, &app_handle, None);
+                }
+                break;
+            }
+        }
+    });
+}
+
+// ファイルを開けない・デコードできない場合にUIへ通知する
+// ホットキーやキューの自動送りなど、コマンドの戻り値を受け取れない再生でも失敗が伝わる
+fn emit_audio_error(app: &AppHandle, path: &str, message: &str) {
+    let _ = app.emit(
+        "audio-error",
+        AudioError {
+            path: path.to_string(),
+            message: message.to_string(),
+        },
+    );
+}
+
+// キューのindex番目を再生し、終了を監視する
+fn play_queue_entry(
+    player: &AudioPlayer,
+    app: &AppHandle,
+    index: usize,
+    fade_in: Option<Duration>,
+) -> Result<(), String> {
+    let path = player.queue_item(index).ok_or("Queue index is out of range")?;
+    let play_id = player
+        .play_queue_index(index, &path, fade_in)
+        .inspect_err(|e| emit_audio_error(app, &path, e))?;
+
+    emit_now_playing(app, player);
+    let _ = app.emit(
+        "queue-advanced",
+        QueueAdvanced {
+            index,
+            path: path.clone(),
+        },
+    );
+
+    spawn_finish_monitor(player.clone(), app.clone(), path, play_id);
+    queue_gapless_next(player, app, play_id);
+
+    Ok(())
+}
+
+// クロスフェードしない場合は、次の曲を再生中のSinkの後ろに追加しておき、曲間を空けずに続けて再生する
+// 開けない曲は飛ばして、その次の曲を追加する
+fn queue_gapless_next(player: &AudioPlayer, app: &AppHandle, play_id: u64) {
+    if player.get_crossfade() > 0 {
+        return;
+    }
+    let Some(mut index) = player.queue_index() else {
+        return;
+    };
+
+    while let Some(next) = player.queue_index_after(index) {
+        let Some(path) = player.queue_item(next) else {
+            return;
+        };
+        match player.append_queue_index(next, &path, play_id) {
+            Ok(()) => return,
+            Err(e) => {
+                eprintln!("キューの曲を再生できませんでした ({}): {}", next, e);
+                emit_audio_error(app, &path, &e);
+            }
+        }
+        index = next;
+    }
+}
+
+// キュー再生中の曲が終わったら次の曲へ進む（再生できない曲は飛ばす）
+// fade_inはクロスフェードで次の曲を重ねて始める場合のフェードインの長さ
+fn advance_queue(player: &AudioPlayer, app: &AppHandle, fade_in: Option<Duration>) {
+    let Some(mut index) = player.queue_index() else {
+        return;
+    };
+
+    while let Some(next) = player.queue_index_after(index) {
+        match play_queue_entry(player, app, next, fade_in) {
+            Ok(()) => return,
+            Err(e) => eprintln!("キューの曲を再生できませんでした ({}): {}", next, e),
+        }
+        index = next;
+    }
+
+    player.finish_queue();
+    emit_now_playing(app, player);
+}
+
+#[tauri::command]
+fn set_queue(paths: Vec<String>, state: tauri::State<AudioPlayer>, app: AppHandle) {
+    state.inner().set_queue(paths);
+    emit_now_playing(&app, state.inner());
+}
+
+// キューの末尾に追加する。最後の曲を再生中に追加した場合も、曲間を空けずに続けて再生する
+#[tauri::command]
+fn enqueue(paths: Vec<String>, state: tauri::State<AudioPlayer>, app: AppHandle) {
+    let player = state.inner();
+    if player.enqueue(paths) {
+        queue_gapless_next(player, &app, player.current_play_id());
+    }
+    emit_now_playing(&app, player);
+}
+
+#[tauri::command]
+fn get_queue(state: tauri::State<AudioPlayer>) -> PlayQueue {
+    state.inner().get_queue()
+}
+
+#[tauri::command]
+fn play_queue(state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<(), String> {
+    if state.inner().get_queue().items.is_empty() {
+        return Err("Queue is empty".to_string());
+    }
+    play_queue_entry(state.inner(), &app, 0, None)
+}
+
+#[tauri::command]
+fn skip_next(state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<(), String> {
+    let index = state.inner().get_queue().index.ok_or("Queue is not playing")?;
+    let next = state
+        .inner()
+        .queue_index_after(index)
+        .ok_or("No next item in the queue")?;
+    play_queue_entry(state.inner(), &app, next, None)
+}
+
+#[tauri::command]
+fn skip_previous(state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<(), String> {
+    let index = state.inner().get_queue().index.ok_or("Queue is not playing")?;
+    let previous = index.checked_sub(1).ok_or("No previous item in the queue")?;
+    play_queue_entry(state.inner(), &app, previous, None)
+}
+
+// キューの曲を切り替えるとき・ループで先頭へ戻るときに重ねる長さ（0で従来通り、間を空けずにつなぐ）
+// ループ再生への反映は次に再生を始めたときから
+#[tauri::command]
+fn set_crossfade(millis: u64, state: tauri::State<AudioPlayer>) {
+    state.inner().set_crossfade(millis);
+}
+
+#[tauri::command]
+fn get_crossfade(state: tauri::State<AudioPlayer>) -> u64 {
+    state.inner().get_crossfade()
+}
+
+// キューを空にする（再生中の曲は止めず、終わっても次へ進まない）
+#[tauri::command]
+fn clear_queue(state: tauri::State<AudioPlayer>, app: AppHandle) {
+    state.inner().clear_queue();
+    emit_now_playing(&app, state.inner());
+}
+
+// optionsを省略した場合（または各項目を省略した場合）は既定の動作で再生する
+// normalizeがtrueなら、解析済みのゲイン（EBU R128）で音量を揃えて再生する
+// 未解析のファイルはその回は元の音量で再生し、次の再生までにバックグラウンドで解析しておく
+// normalizeを省略した場合はset_normalize_loudnessの設定に従う
+// お気に入りに音量が設定されていれば、それも掛ける（エンベロープがあれば、再生中にさらに掛ける）
+// volumeはパッドの音量で、お気に入りの音量にさらに掛ける。loopingがtrueなら止めるまで繰り返す
+// fade_in_millisを指定すると、その長さで音量を0から上げる
+// start_secs・end_secs（またはcueで指定したキューポイント）を指定すると、その区間だけを再生する
+// 区間を指定せずにskip_silenceをtrueにすると、先頭の無音（-50dBFS以下）を飛ばして再生する
+// 無音の長さはスキャン時に求めたものを使う（まだ求めていないファイルは、その回は先頭から再生する）
+// 戻り値は再生のID（stop_playbackで止められる）。toggleで止めた場合はNone
+#[tauri::command]
+fn play_audio(
+    path: String,
+    options: Option<PlayAudioOptions>,
+    state: tauri::State<AudioPlayer>,
+    app: tauri::AppHandle,
+) -> Result<Option<String>, String> {
+    let PlayAudioOptions {
+        normalize,
+        fade_in_millis,
+        mode,
+        start_secs,
+        end_secs,
+        cue,
+        skip_silence,
+        volume,
+        looping,
+    } = options.unwrap_or_default();
+    if volume.is_some_and(|volume| !(0.0..=2.0).contains(&volume)) {
+        return Err("Volume must be between 0.0 and 2.0".to_string());
+    }
+    let mode = mode.unwrap_or_default();
+    if mode == PlayMode::Toggle && state.inner().is_path_playing(&path) {
+        stop_pad(state.inner(), &app, &path);
+        return Ok(None);
+    }
+
+    // 解析していないファイルはデコードを待たずに元の音量で鳴らし、解析はバックグラウンドで行う
+    let normalized_gain = normalize
+        .unwrap_or_else(|| normalize_loudness(&app))
+        .then(|| loudness::playback_gain(state.inner(), &path, &app) as f32);
+    let gain = [normalized_gain, favorite_volume(&app, &path), volume]
+        .into_iter()
+        .flatten()
+        .reduce(|gain, volume| gain * volume);
+    let play_options = PlayOptions {
+        looping: looping.unwrap_or(false),
+        gain,
+        fade_in: fade_in_millis.map(Duration::from_millis),
+        envelope: favorite_envelope(&app, &path),
+        ..Default::default()
+    };
+    // キューポイントを指定した場合は、その区間をstart_secs・end_secsより優先する
+    let range = match cue {
+        Some(name) => {
+            let cue = favorite_cue_point(&app, &path, &name).ok_or("Cue point not found")?;
+            Some((cue.start_seconds, cue.end_seconds))
+        }
+        None if start_secs.is_some() || end_secs.is_some() => {
+            Some((start_secs.unwrap_or(0.0), end_secs))
+        }
+        None if skip_silence.unwrap_or(false) => cached_leading_silence(&app, &path)
+            .filter(|start| *start > 0.0)
+            .map(|start| (start, None)),
+        None => None,
+    };
+
+    // 同時再生では他の音を止めずに別のSinkで鳴らす（履歴には記録しない）
+    if mode == PlayMode::Overlap {
+        let id = state
+            .inner()
+            .play_polyphonic_with_options(&path, range, &play_options)
+            .inspect_err(|e| emit_audio_error(&app, &path, e))?;
+        emit_now_playing(&app, state.inner());
+        spawn_voice_monitor(state.inner().clone(), app, id.clone());
+        return Ok(Some(id));
+    }
+
+    let previous = state.inner().get_current_path();
+
+    // current_pathは再生開始と同時に設定される
+    let result = match range {
+        Some((start_seconds, end_seconds)) => {
+            state
+                .inner()
+                .play_range_with_options(&path, start_seconds, end_seconds, &play_options)
+        }
+        None => state.inner().play_with_options(&path, &play_options),
+    };
+    // 鳴っていたファイルは途中で止めたものとして記録する（再生を始める前に止めている）
+    // 履歴の書き込みは別のスレッドで行うため、再生の開始を待たせない
+    if let Some(previous) = previous {
+        history::record_outcome(&app, &previous, PlayOutcome::Stopped);
+    }
+    let play_id = result.inspect_err(|e| emit_audio_error(&app, &path, e))?;
+    if mode == PlayMode::Hold {
+        state.inner().hold(&path, play_id);
+    }
+    spawn_envelope(state.inner().clone(), path.clone(), play_id, play_options.envelope);
+    emit_now_playing(&app, state.inner());
+    history::record(&app, &path);
+
+    spawn_finish_monitor(state.inner().clone(), app, path, play_id);
+
+    Ok(Some(playback_id(play_id)))
+}
+
+// キャッシュに入れた先頭の無音の長さ
+// まだ求めていないファイルはデコードを待たずに先頭から再生し、次回に備えてバックグラウンドで求める
+fn cached_leading_silence(app: &AppHandle, path: &str) -> Option<f64> {
+    let cache_path = probe_cache_path(app).ok()?;
+    let probe_cache = app.state::<ProbeCacheState>();
+    if let Some(leading_silence) = probe_cache.leading_silence(&cache_path, Path::new(path)) {
+        return leading_silence;
+    }
+
+    let app = app.clone();
+    let path = PathBuf::from(path);
+    thread::spawn(move || {
+        let probe_cache = app.state::<ProbeCacheState>();
+        if let Err(e) = probe_cache.store_leading_silence(&cache_path, &path) {
+            eprintln!("プローブ結果を保存できませんでした: {}", e);
+        }
+    });
+    None
+}
+
+// フォルダ内のファイル（filterを指定した場合はsearch_audio_filesと同じ条件に合うもの）から
+// ランダムに1つ選んで再生し、選んだファイルをrandom-selectedで通知する
+#[tauri::command]
+async fn play_random(
+    directory: String,
+    filter: Option<String>,
+    max_depth: Option<usize>,
+    state: tauri::State<'_, AudioPlayer>,
+    app: AppHandle,
+) -> Result<AudioFile, String> {
+    // スキャンに時間がかかってもUIを止めないよう、選ぶところまではブロッキングスレッドで実行する
+    let scan_app = app.clone();
+    let file = tauri::async_runtime::spawn_blocking(move || {
+        let audio_files = scan_audio_files(&directory, max_depth, &scan_app)?;
+        let candidates = filter_audio_files(audio_files, filter.as_deref().unwrap_or(""));
+        candidates
+            .choose(&mut rand::rng())
+            .cloned()
+            .ok_or_else(|| "No audio files to choose from".to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    play_audio(file.path.clone(), None, state, app.clone())?;
+    let _ = app.emit("random-selected", &file);
+    Ok(file)
+}
+
+// pathの音をstop_audioと同じフェードで止める（単発再生だった場合は途中で止めたものとして記録する）
+fn stop_pad(player: &AudioPlayer, app: &AppHandle, path: &str) {
+    if player.get_current_path().as_deref() == Some(path) {
+        history::record_outcome(app, path, PlayOutcome::Stopped);
+    }
+    if player.stop_path(path, player.get_stop_fade()) {
+        emit_now_playing(app, player);
+    }
+}
+
+// holdモードで押したパッドを離したときに呼ぶ
+// そのパッドで鳴らした音だけを止める（後から鳴らした別の音や、同じファイルを同時再生で鳴らした音は止めない）
+#[tauri::command]
+fn release_pad(path: String, state: tauri::State<AudioPlayer>, app: AppHandle) {
+    let player = state.inner();
+    if player.release(&path, player.get_stop_fade()) {
+        history::record_outcome(&app, &path, PlayOutcome::Stopped);
+        emit_now_playing(&app, player);
+    }
+}
+
+// ループ再生。ループ区間の指定がなければWAVに埋め込まれたループポイントを使い、
+// それもなければファイル全体をループする
+#[tauri::command]
+fn play_looped(
+    path: String,
+    loop_start: Option<f64>,
+    loop_end: Option<f64>,
+    state: tauri::State<AudioPlayer>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let region = match (loop_start, loop_end) {
+        (Some(start_seconds), Some(end_seconds)) => {
+            let valid = start_seconds >= 0.0 && start_seconds < end_seconds;
+            if !valid {
+                return Err("Invalid loop points".to_string());
+            }
+            Some(LoopPoints {
+                start_seconds,
+                end_seconds,
+            })
+        }
+        (None, None) => read_wav_loop_points(Path::new(&path)),
+        _ => return Err("Both loop_start and loop_end are required".to_string()),
+    };
+
+    let options = PlayOptions {
+        looping: true,
+        loop_region: region,
+        ..Default::default()
+    };
+    let play_id = state
+        .inner()
+        .play_with_options(&path, &options)
+        .inspect_err(|e| emit_audio_error(&app, &path, e))?;
+    emit_now_playing(&app, state.inner());
+
+    spawn_finish_monitor(state.inner().clone(), app, path, play_id);
+
+    Ok(())
+}
+
+#[tauri::command]
+fn play_with_fade(
+    path: String,
+    fade_in_millis: u64,
+    state: tauri::State<AudioPlayer>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let options = PlayOptions {
+        fade_in: Some(Duration::from_millis(fade_in_millis)),
+        ..Default::default()
+    };
+    let play_id = state
+        .inner()
+        .play_with_options(&path, &options)
+        .inspect_err(|e| emit_audio_error(&app, &path, e))?;
+    emit_now_playing(&app, state.inner());
+
+    spawn_finish_monitor(state.inner().clone(), app, path, play_id);
+
+    Ok(())
+}
+
+#[tauri::command]
+fn play_range(
+    path: String,
+    start_seconds: f64,
+    end_seconds: Option<f64>,
+    state: tauri::State<AudioPlayer>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let play_id = state
+        .inner()
+        .play_range(&path, start_seconds, end_seconds)
+        .inspect_err(|e| emit_audio_error(&app, &path, e))?;
+    emit_now_playing(&app, state.inner());
+
+    spawn_finish_monitor(state.inner().clone(), app, path, play_id);
+
+    Ok(())
+}
+
+// timesは再生する合計回数（1なら通常の再生と同じ）
+// 繰り返しの間は終了を通知せず、最後の再生が終わったときだけaudio-finishedを送る
+#[tauri::command]
+fn play_repeated(
+    path: String,
+    times: u32,
+    state: tauri::State<AudioPlayer>,
+    app: AppHandle,
+) -> Result<(), String> {
+    if times == 0 {
+        return Err("times must be at least 1".to_string());
+    }
+
+    let options = PlayOptions {
+        repeats: times - 1,
+        ..Default::default()
+    };
+    let play_id = state
+        .inner()
+        .play_with_options(&path, &options)
+        .inspect_err(|e| emit_audio_error(&app, &path, e))?;
+    emit_now_playing(&app, state.inner());
+
+    spawn_finish_monitor(state.inner().clone(), app, path, play_id);
+
+    Ok(())
+}
+
+#[tauri::command]
+fn set_loop(enabled: bool, state: tauri::State<AudioPlayer>) -> Result<(), String> {
+    state.inner().set_loop(enabled)
+}
+
+#[tauri::command]
+fn is_looping(state: tauri::State<AudioPlayer>) -> bool {
+    state.inner().is_looping()
+}
+
+#[tauri::command]
+fn get_loop_state(state: tauri::State<AudioPlayer>) -> LoopState {
+    state.inner().loop_state()
+}
+
+#[tauri::command]
+fn play_bytes(data: Vec<u8>, state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<String, String> {
+    let (id, play_id) = state.inner().play_bytes(data)?;
+    emit_now_playing(&app, state.inner());
+
+    spawn_finish_monitor(state.inner().clone(), app, id.clone(), play_id);
+
+    Ok(id)
+}
+
+// 同時再生の1音の終了をバックグラウンドスレッドで監視
+fn spawn_voice_monitor(player: AudioPlayer, app_handle: AppHandle, voice_id: String) {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(100));
+
+        match player.take_finished_voice(&voice_id) {
+            Some(true) => {}
+            Some(false) => continue,
+            // 既に停止された
+            None => break,
+        }
+
+        let _ = app_handle.emit("audio-finished", voice_id.clone());
+        emit_now_playing(&app_handle, &player);
+        break;
+    });
+}
+
+#[tauri::command]
+fn play_polyphonic(path: String, state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<String, String> {
+    let id = state
+        .inner()
+        .play_polyphonic(&path)
+        .inspect_err(|e| emit_audio_error(&app, &path, e))?;
+    emit_now_playing(&app, state.inner());
+
+    spawn_voice_monitor(state.inner().clone(), app, id.clone());
+
+    Ok(id)
+}
+
+#[tauri::command]
+fn stop_sound(id: String, state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<(), String> {
+    state.inner().stop_sound(&id);
+    emit_now_playing(&app, state.inner());
+    Ok(())
+}
+
+// play_audioが返したIDの音だけを止める（単発再生・同時再生のどちらのIDでもよい）
+// 既に終わっている・別の再生に替わっている場合はエラー
+#[tauri::command]
+fn stop_playback(id: String, state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<(), String> {
+    let player = state.inner();
+    let path = player.get_current_path();
+    if !player.stop_playback(&id, player.get_stop_fade()) {
+        return Err("Playback not found".to_string());
+    }
+    if let (Some(path), Some(_)) = (path, parse_playback_id(&id)) {
+        history::record_outcome(&app, &path, PlayOutcome::Stopped);
+    }
+    emit_now_playing(&app, player);
+    Ok(())
+}
+
+// 単発再生・マルチパッド・同時再生の全ての音をすぐに止める
+#[tauri::command]
+fn stop_all(state: tauri::State<AudioPlayer>, app: AppHandle) {
+    let player = state.inner();
+    if let Some(path) = player.get_current_path() {
+        history::record_outcome(&app, &path, PlayOutcome::Stopped);
+    }
+    player.stop_all();
+    emit_now_playing(&app, player);
+}
+
+#[tauri::command]
+fn get_playing_sounds(state: tauri::State<AudioPlayer>) -> Vec<String> {
+    state.inner().get_playing_sounds()
+}
+
+// 全ての音を短くフェードアウトさせて止める（fade_millisを省略した場合はset_stop_fadeの設定）
+#[tauri::command]
+fn stop_audio(
+    fade_millis: Option<u64>,
+    state: tauri::State<AudioPlayer>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let millis = fade_millis.unwrap_or_else(|| state.inner().get_stop_fade());
+    if let Some(path) = state.inner().get_current_path() {
+        history::record_outcome(&app, &path, PlayOutcome::Stopped);
+    }
+    state.inner().stop_with_fade(millis);
+    emit_now_playing(&app, state.inner());
+    Ok(())
+}
+
+// stop_audioのフェードアウトの長さ（0でフェードせずにすぐ止める）
+#[tauri::command]
+fn set_stop_fade(millis: u64, state: tauri::State<AudioPlayer>) {
+    state.inner().set_stop_fade(millis);
+}
+
+#[tauri::command]
+fn get_stop_fade(state: tauri::State<AudioPlayer>) -> u64 {
+    state.inner().get_stop_fade()
+}
+
+#[tauri::command]
+fn stop_audio_fade(millis: u64, state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<(), String> {
+    state.inner().stop_with_fade(millis);
+    emit_now_playing(&app, state.inner());
+    Ok(())
+}
+
+#[tauri::command]
+fn pause_audio(state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<(), String> {
+    state.inner().pause();
+    emit_now_playing(&app, state.inner());
+    Ok(())
+}
+
+#[tauri::command]
+fn resume_audio(state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<(), String> {
+    state.inner().resume();
+    emit_now_playing(&app, state.inner());
+    Ok(())
+}
+
+#[tauri::command]
+fn seek_audio(seconds: f64, state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<(), String> {
+    state.inner().seek(seconds)?;
+    emit_now_playing(&app, state.inner());
+    Ok(())
+}
+
+#[tauri::command]
+fn get_position(state: tauri::State<AudioPlayer>) -> Option<f64> {
+    state.inner().get_position()
+}
+
+#[tauri::command]
+fn is_path_playing(path: String, state: tauri::State<AudioPlayer>) -> bool {
+    state.inner().is_path_playing(&path)
+}
+
+#[tauri::command]
+fn is_audio_paused(state: tauri::State<AudioPlayer>) -> bool {
+    state.inner().is_paused()
+}
+
+#[tauri::command]
+fn get_playback_state(state: tauri::State<AudioPlayer>) -> PlaybackState {
+    state.inner().playback_state()
+}
+
+#[tauri::command]
+fn set_volume(volume: f32, state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<(), String> {
+    state.inner().set_volume(volume)?;
+    emit_now_playing(&app, state.inner());
+    Ok(())
+}
+
+#[tauri::command]
+fn get_volume(state: tauri::State<AudioPlayer>) -> f32 {
+    state.inner().get_volume()
+}
+
+#[tauri::command]
+fn set_speed(factor: f32, state: tauri::State<AudioPlayer>) -> Result<f32, String> {
+    state.inner().set_speed(factor)
+}
+
+#[tauri::command]
+fn get_speed(state: tauri::State<AudioPlayer>) -> f32 {
+    state.inner().get_speed()
+}
+
+#[tauri::command]
+fn reset_speed(state: tauri::State<AudioPlayer>) -> Result<f32, String> {
+    state.inner().set_speed(1.0)
+}
+
+// play_polyphonicで返されたIDの音だけ速度を変える（音の高さも一緒に変わる）
+#[tauri::command]
+fn set_playback_speed(id: String, rate: f32, state: tauri::State<AudioPlayer>) -> Result<f32, String> {
+    state.inner().set_sound_speed(&id, rate)
+}
+
+#[tauri::command]
+fn set_balance(pan: f32, state: tauri::State<AudioPlayer>) -> Result<(), String> {
+    state.inner().set_balance(pan)
+}
+
+#[tauri::command]
+fn get_balance(state: tauri::State<AudioPlayer>) -> f32 {
+    state.inner().get_balance()
+}
+
+#[tauri::command]
+fn set_force_mono(enabled: bool, state: tauri::State<AudioPlayer>) {
+    state.inner().set_force_mono(enabled);
+}
+
+#[tauri::command]
+fn is_force_mono(state: tauri::State<AudioPlayer>) -> bool {
+    state.inner().is_force_mono()
+}
+
+#[tauri::command]
+fn list_output_devices() -> Result<Vec<String>, String> {
+    let devices = rodio::cpal::default_host()
+        .output_devices()
+        .map_err(|e| e.to_string())?;
+    Ok(devices.filter_map(|device| device.name().ok()).collect())
+}
+
+// 切り替えた結果のデバイス（見つからず既定に戻した場合は既定）を次回起動時のために保存する
+#[tauri::command]
+fn set_output_device(name: Option<String>, state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<(), String> {
+    let result = state.inner().set_output_device(name);
+    emit_now_playing(&app, state.inner());
+
+    let settings_path = get_settings_file_path(&app)?;
+    let mut settings = Settings::load(&settings_path).unwrap_or_default();
+    settings.output_device = state.inner().get_output_device();
+    settings.save(&settings_path)?;
+
+    result
+}
+
+#[tauri::command]
+fn get_output_device(state: tauri::State<AudioPlayer>) -> Option<String> {
+    state.inner().get_output_device()
+}
+
+// 仮想オーディオケーブルなどに同じ音を流す（Noneで副出力をやめる）。設定は次回起動時にも使う
+#[tauri::command]
+fn set_secondary_output_device(
+    name: Option<String>,
+    state: tauri::State<AudioPlayer>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let result = state.inner().set_secondary_output_device(name);
+
+    let settings_path = get_settings_file_path(&app)?;
+    let mut settings = Settings::load(&settings_path).unwrap_or_default();
+    settings.secondary_output_device = state.inner().get_secondary_output_device();
+    settings.save(&settings_path)?;
+
+    result
+}
+
+#[tauri::command]
+fn get_secondary_output_device(state: tauri::State<AudioPlayer>) -> Option<String> {
+    state.inner().get_secondary_output_device()
+}
+
+#[tauri::command]
+fn get_now_playing(state: tauri::State<AudioPlayer>) -> NowPlaying {
+    state.inner().now_playing()
+}
+
+// 読み込めたファイルのパスを返す（失敗したファイルはaudio-errorで通知してスキップ）
+#[tauri::command]
+async fn preload(
+    paths: Vec<String>,
+    state: tauri::State<'_, AudioPlayer>,
+    app: AppHandle,
+) -> Result<Vec<String>, String> {
+    let player = state.inner().clone();
+
+    // デコードに時間がかかるため、ブロッキングスレッドで実行する
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut loaded = Vec::new();
+        for path in paths {
+            match player.preload(&path) {
+                Ok(()) => loaded.push(path),
+                Err(e) => emit_audio_error(&app, &path, &e),
+            }
+        }
+        loaded
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// プリロードしていなかった場合はfalse
+#[tauri::command]
+fn unload(path: String, state: tauri::State<AudioPlayer>) -> bool {
+    state.inner().unload(&path)
+}
+
+#[tauri::command]
+fn set_cache_limit(megabytes: usize, state: tauri::State<AudioPlayer>) {
+    state.inner().set_cache_limit(megabytes);
+}
+
+#[tauri::command]
+fn get_cache_limit(state: tauri::State<AudioPlayer>) -> usize {
+    state.inner().get_cache_limit()
+}
+
+#[tauri::command]
+fn clear_cache(state: tauri::State<AudioPlayer>) {
+    state.inner().clear_preloaded();
+}
+
+#[tauri::command]
+async fn measure_trigger_latency(
+    path: String,
+    runs: Option<u32>,
+    state: tauri::State<'_, AudioPlayer>,
+) -> Result<LatencyReport, String> {
+    let player = state.inner().clone();
+    let runs = runs.unwrap_or(5).clamp(1, 20);
+
+    // 計測中は再生と待機を繰り返すため、ブロッキングスレッドで実行する
+    tauri::async_runtime::spawn_blocking(move || player.measure_trigger_latency(&path, runs))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn rename_file(old_path: String, new_name: String, app: AppHandle) -> Result<String, String> {
+    let old = PathBuf::from(&old_path);
+    let parent = old.parent().ok_or("Invalid path")?;
+    let new_path = parent.join(&new_name);
+
+    std::fs::rename(&old, &new_path).map_err(|e| e.to_string())?;
+
+    let new_path = new_path.to_string_lossy().to_string();
+    update_favorite_paths(&app, &[(old_path, Some(new_path.clone()))])?;
+    Ok(new_path)
+}
+
+// parentの中に新しいフォルダを作り、そのパスを返す
+#[tauri::command]
+fn create_directory(parent: String, name: String) -> Result<String, String> {
+    let parent_path = Path::new(&parent);
+    if !parent_path.is_dir() {
+        return Err(format!("親フォルダが見つかりません: {}", parent));
+    }
+
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." {
+        return Err("フォルダ名が空か、使用できない名前です".to_string());
+    }
+    if name.contains(['/', '\\']) {
+        return Err(format!("フォルダ名に区切り文字は使用できません: {}", name));
+    }
+
+    let new_path = parent_path.join(name);
+    if new_path.exists() {
+        return Err(format!("同名のフォルダまたはファイルが既にあります: {}", name));
+    }
+
+    fs::create_dir(&new_path).map_err(|e| format!("フォルダを作成できませんでした: {}", e))?;
+    Ok(new_path.to_string_lossy().to_string())
+}
+
+// 一括リネームの指定方法
+// { "kind": "replace", "find": "...", "replace": "..." } は拡張子を除いたファイル名を置換
+// { "kind": "template", "template": "intro_{n}", "start": 1, "digits": 2 } は連番を振る
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum BatchPattern {
+    Replace {
+        find: String,
+        replace: String,
+    },
+    Template {
+        template: String,
+        start: Option<u32>,
+        // 省略時は最後の番号の桁数に合わせてゼロ埋めする
+        digits: Option<usize>,
+    },
+}
+
+impl BatchPattern {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Replace { find, .. } if find.is_empty() => Err("Find text is empty".to_string()),
+            Self::Template { template, .. } if !template.contains("{n}") => {
+                Err("Template must contain {n}".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // index番目（0始まり）のファイルの新しい名前。拡張子は元のまま残す
+    fn new_name(&self, path: &Path, index: usize, count: usize) -> String {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+
+        let stem = match self {
+            Self::Replace { find, replace } => stem.replace(find.as_str(), replace),
+            Self::Template {
+                template,
+                start,
+                digits,
+            } => {
+                let start = start.unwrap_or(1) as usize;
+                let last = start + count.saturating_sub(1);
+                let digits = digits.unwrap_or_else(|| last.to_string().len());
+                template.replace("{n}", &format!("{:0width$}", start + index, width = digits))
+            }
+        };
+        format!("{}{}", stem, extension)
+    }
+}
+
+// oldを同じフォルダのnew_nameにリネームし、リネーム後のパスを返す
+// 変更がない・使えない名前・同名のファイルがある場合はリネームせず、Ok(Err(理由))を返す
+fn rename_to(old: &Path, new_name: &str) -> Result<Result<PathBuf, String>, String> {
+    if !old.is_file() {
+        return Err("File not found".to_string());
+    }
+    // フォルダ区切りを含む名前や空の名前は使わない
+    let is_plain_name = Path::new(new_name)
+        .file_name()
+        .is_some_and(|name| name.to_str() == Some(new_name));
+    if !is_plain_name {
+        return Ok(Err(format!("Invalid file name: {}", new_name)));
+    }
+
+    let parent = old.parent().ok_or("Invalid path")?;
+    let new_path = parent.join(new_name);
+    if new_path == old {
+        return Ok(Err("Name is unchanged".to_string()));
+    }
+    if new_path.exists() {
+        return Ok(Err(format!("A file named {} already exists", new_name)));
+    }
+
+    fs::rename(old, &new_path).map_err(|e| e.to_string())?;
+    Ok(Ok(new_path))
+}
+
+// 失敗したファイルがあっても残りを続け、リネームできたもの・スキップしたもの・失敗したものを返す
+// 変更がない・使えない名前・同名のファイルが既にあるものはスキップする
+#[tauri::command]
+fn batch_rename(files: Vec<String>, pattern: BatchPattern, app: AppHandle) -> Result<RenameResult, String> {
+    pattern.validate()?;
+
+    let mut result = RenameResult::default();
+    let mut favorite_changes = Vec::new();
+    let count = files.len();
+    for (index, file_path) in files.into_iter().enumerate() {
+        let old = Path::new(&file_path);
+        let new_name = pattern.new_name(old, index, count);
+        match rename_to(old, &new_name) {
+            Ok(Ok(new_path)) => {
+                let new_path = new_path.to_string_lossy().to_string();
+                favorite_changes.push((file_path, Some(new_path.clone())));
+                result.renamed.push(new_path);
+            }
+            Ok(Err(message)) => result.skipped.push(FileFailure {
+                path: file_path,
+                message,
+            }),
+            Err(message) => result.failed.push(FileFailure {
+                path: file_path,
+                message,
+            }),
+        }
+    }
+
+    update_favorite_paths(&app, &favorite_changes)?;
+    Ok(result)
+}
+
+// デフォルトではゴミ箱へ移動し、permanentが指定された場合のみ完全に削除する
+#[tauri::command]
+fn delete_file(path: String, permanent: Option<bool>, app: AppHandle) -> Result<(), String> {
+    let target = Path::new(&path);
+    if !target.exists() {
+        return Err(format!("ファイルが見つかりません: {}", path));
+    }
+    if !target.is_file() {
+        return Err(format!("ファイルではありません: {}", path));
+    }
+
+    if permanent.unwrap_or(false) {
+        fs::remove_file(target).map_err(|e| format!("削除に失敗しました: {}", e))?;
+    } else {
+        trash::delete(target).map_err(|e| format!("ゴミ箱への移動に失敗しました: {}", e))?;
+    }
+
+    update_favorite_paths(&app, &[(path, None)])
+}
+
+// コピー先に同名ファイルがある場合の扱い
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConflictStrategy {
+    Overwrite,
+    Skip,
+    Rename,
+}
+
+impl ConflictStrategy {
+    // 省略時は最も安全な"rename"
+    fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value.unwrap_or("rename") {
+            "overwrite" => Ok(Self::Overwrite),
+            "skip" => Ok(Self::Skip),
+            "rename" => Ok(Self::Rename),
+            other => Err(format!("Unknown conflict strategy: {}", other)),
+        }
+    }
+
+    // 実際に書き込むパスを決める（スキップする場合はNone）
+    fn resolve(self, dest: PathBuf) -> Option<PathBuf> {
+        if !dest.exists() {
+            return Some(dest);
+        }
+        match self {
+            Self::Overwrite => Some(dest),
+            Self::Skip => None,
+            Self::Rename => Some(numbered_path(&dest)),
+        }
+    }
+}
+
+// "name (1).ext", "name (2).ext", ... の中から未使用のパスを探す
+fn numbered_path(path: &Path) -> PathBuf {
+    let parent = path.parent().unwrap_or_else(|| Path::new(""));
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut n = 1;
+    loop {
+        let candidate = parent.join(format!("{} ({}){}", stem, n, extension));
+        if !candidate.exists() {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+// 別のパスでも、シンボリックリンクや相対パスで同じファイルを指していればtrue
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// conflict: "overwrite" | "skip" | "rename"（省略時は"rename"）
+// 戻り値は実際にコピーしたパス（スキップしたファイルは含まない）
+#[tauri::command]
+fn copy_files(
+    files: Vec<String>,
+    destination: String,
+    conflict: Option<String>,
+) -> Result<Vec<String>, String> {
+    let strategy = ConflictStrategy::parse(conflict.as_deref())?;
+
+    let dest_path = Path::new(&destination);
+    if !dest_path.exists() {
+        std::fs::create_dir_all(dest_path).map_err(|e| e.to_string())?;
+    }
+
+    let mut copied_files = Vec::new();
+    for file_path in files {
+        let src = Path::new(&file_path);
+        let file_name = src.file_name().ok_or("Invalid file name")?;
+        let Some(dest) = strategy.resolve(dest_path.join(file_name)) else {
+            continue;
+        };
+        // 同じファイルへコピーすると、読む前に中身が空になってしまう
+        if is_same_file(src, &dest) {
+            return Err(format!("コピー先が元のファイルと同じです: {}", file_path));
+        }
+
+        std::fs::copy(src, &dest).map_err(|e| e.to_string())?;
+        copied_files.push(dest.to_string_lossy().to_string());
+    }
+
+    Ok(copied_files)
 }
 
-#[derive(Clone)]
-pub struct AudioPlayer {
-    sink: Arc<Mutex<Option<Sink>>>,
-    _stream: Arc<Mutex<Option<OutputStream>>>,
-    current_path: Arc<Mutex<Option<String>>>,
+// ファイルを移動（別ドライブ間ではrenameが失敗するため、コピーしてから元を削除する）
+fn move_file(src: &Path, dest: &Path) -> Result<(), String> {
+    if fs::rename(src, dest).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(src, dest).map_err(|e| e.to_string())?;
+    fs::remove_file(src).map_err(|e| e.to_string())
 }
 
-// Safe because all fields are protected by Mutex
-unsafe impl Send for AudioPlayer {}
-unsafe impl Sync for AudioPlayer {}
+// 1ファイルをdest_dirへ移動し、移動先のパスを返す（同名のファイルがありスキップした場合はNone）
+fn move_into(src: &Path, dest_dir: &Path, strategy: ConflictStrategy) -> Result<Option<PathBuf>, String> {
+    if !src.is_file() {
+        return Err("移動元が見つかりません".to_string());
+    }
+    let file_name = src.file_name().ok_or("Invalid file name")?;
+    let Some(dest) = strategy.resolve(dest_dir.join(file_name)) else {
+        return Ok(None);
+    };
+    // 同じファイルへ移動すると、コピーした後に元のファイルとして削除されてしまう
+    if is_same_file(src, &dest) {
+        return Err("移動先が元のファイルと同じです".to_string());
+    }
+
+    move_file(src, &dest)?;
+    Ok(Some(dest))
+}
 
-impl AudioPlayer {
-    pub fn new() -> Self {
-        Self {
-            sink: Arc::new(Mutex::new(None)),
-            _stream: Arc::new(Mutex::new(None)),
-            current_path: Arc::new(Mutex::new(None)),
+// conflictはcopy_filesと同じ（省略時は"rename"）
+// 見つからない・移動できないファイルは飛ばして残りを続け、結果のfailedに理由を入れる
+#[tauri::command]
+fn move_files(
+    files: Vec<String>,
+    destination: String,
+    conflict: Option<String>,
+    app: AppHandle,
+) -> Result<MoveResult, String> {
+    let strategy = ConflictStrategy::parse(conflict.as_deref())?;
+
+    let dest_path = Path::new(&destination);
+    if !dest_path.exists() {
+        std::fs::create_dir_all(dest_path).map_err(|e| e.to_string())?;
+    }
+
+    let mut result = MoveResult::default();
+    let mut favorite_changes = Vec::new();
+    for file_path in files {
+        match move_into(Path::new(&file_path), dest_path, strategy) {
+            Ok(Some(dest)) => {
+                let dest = dest.to_string_lossy().to_string();
+                favorite_changes.push((file_path, Some(dest.clone())));
+                result.moved.push(dest);
+            }
+            Ok(None) => result.skipped.push(file_path),
+            Err(message) => result.failed.push(FileFailure {
+                path: file_path,
+                message,
+            }),
         }
     }
 
-    pub fn play(&self, path: &str) -> Result<(), String> {
-        // 前の再生を停止
-        self.stop();
+    update_favorite_paths(&app, &favorite_changes)?;
+    Ok(result)
+}
 
-        // リソースが完全に解放されるまで少し待つ
-        std::thread::sleep(std::time::Duration::from_millis(50));
+// アプリデータディレクトリ内のファイルパスを取得
+fn get_app_data_file_path(app: &AppHandle, file_name: &str) -> Result<PathBuf, String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?;
 
-        // ファイルを開く（リトライ機能付き）
-        let file = self.open_file_with_retry(path, 3)?;
+    // ディレクトリが存在しない場合は作成
+    if !app_data_dir.exists() {
+        fs::create_dir_all(&app_data_dir).map_err(|e| e.to_string())?;
+    }
 
-        // BufReaderを使わず、直接Fileを渡す（FileはRead + Seekを実装している）
-        let source = Decoder::new(file).map_err(|e| {
-            eprintln!("デコーダーエラー ({}): {}", path, e);
-            format!("デコーダーエラー: {}", e)
-        })?;
+    Ok(app_data_dir.join(file_name))
+}
 
-        let stream = OutputStreamBuilder::open_default_stream().map_err(|e| e.to_string())?;
-        let sink = Sink::connect_new(stream.mixer());
+// アプリの設定（次回起動時にも引き継ぐもの）
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct Settings {
+    // 出力デバイス名（Noneは既定のデバイス）
+    #[serde(default)]
+    output_device: Option<String>,
+    // 副出力のデバイス名（Noneなら副出力なし）
+    #[serde(default)]
+    secondary_output_device: Option<String>,
+    // 同時にプローブするファイル数の上限（NoneはCPUのスレッド数）
+    #[serde(default)]
+    scan_concurrency: Option<usize>,
+    // 既定の拡張子の他にスキャンする拡張子
+    #[serde(default)]
+    extra_extensions: Vec<String>,
+    // play_audioでnormalizeを省略したときに音量を揃えるか
+    #[serde(default)]
+    normalize_loudness: bool,
+    // リモート操作の待ち受け（Noneなら起動時に待ち受けない）
+    #[serde(default)]
+    remote: Option<RemoteSettings>,
+    // OSCの待ち受け（Noneなら起動時に待ち受けない）
+    #[serde(default)]
+    osc: Option<OscSettings>,
+}
 
-        sink.append(source);
-        sink.play();
+impl Settings {
+    fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
 
-        *self.sink.lock().unwrap() = Some(sink);
-        *self._stream.lock().unwrap() = Some(stream);
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    }
 
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let mut file = File::create(path).map_err(|e| e.to_string())?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| e.to_string())?;
         Ok(())
     }
+}
 
-    fn open_file_with_retry(&self, path: &str, max_retries: u32) -> Result<File, String> {
-        let mut last_error = String::new();
-        for i in 0..max_retries {
-            match File::open(path) {
-                Ok(file) => return Ok(file),
-                Err(e) => {
-                    last_error = e.to_string();
-                    if i < max_retries - 1 {
-                        std::thread::sleep(std::time::Duration::from_millis(100));
-                    }
-                }
-            }
+fn get_settings_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_app_data_file_path(app, "settings.json")
+}
+
+// 以前のお気に入りの保存先（今はlibrary.dbに保存し、library.dbを初めて開いたときに取り込む）
+fn get_favorites_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_app_data_file_path(app, "favorites.json")
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FavoriteEntry {
+    path: String,
+    #[serde(default)]
+    display_name: Option<String>,
+    #[serde(default)]
+    color: Option<String>,
+    #[serde(default)]
+    category: Option<String>,
+    // このファイルだけに掛ける音量（全体の音量に乗算する）
+    #[serde(default)]
+    volume: Option<f32>,
+    #[serde(default)]
+    cues: Vec<CuePoint>,
+    // 再生中に音量を変化させる折れ線（空なら一定の音量）
+    #[serde(default)]
+    envelope: Vec<EnvelopePoint>,
+}
+
+// ファイルの中の名前付きの区間（end_secondsがNoneならファイルの最後まで）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CuePoint {
+    name: String,
+    start_seconds: f64,
+    end_seconds: Option<f64>,
+}
+
+// エンベロープの1点（再生開始からtime_ms後の音量の倍率。点の間は直線でつなぐ）
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct EnvelopePoint {
+    time_ms: u64,
+    gain: f32,
+}
+
+// time_msの時点のエンベロープの値（最初の点より前は最初の値、最後の点より後は最後の値のまま）
+fn envelope_gain(envelope: &[EnvelopePoint], time_ms: f64) -> f32 {
+    let Some(first) = envelope.first() else {
+        return 1.0;
+    };
+    if time_ms <= first.time_ms as f64 {
+        return first.gain;
+    }
+    for pair in envelope.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        if time_ms < to.time_ms as f64 {
+            let ratio = (time_ms - from.time_ms as f64) / (to.time_ms - from.time_ms) as f64;
+            return from.gain + (to.gain - from.gain) * ratio as f32;
+        }
+    }
+    envelope[envelope.len() - 1].gain
+}
+
+impl FavoriteEntry {
+    fn new(path: String) -> Self {
+        Self {
+            path,
+            display_name: None,
+            color: None,
+            category: None,
+            volume: None,
+            cues: Vec::new(),
+            envelope: Vec::new(),
         }
-        Err(format!("Failed to open file after {} retries: {}", max_retries, last_error))
     }
+}
+
+// favorites.json・エクスポートしたファイルの形式（旧形式はパスのみの配列）
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredFavorites {
+    Current { entries: Vec<FavoriteEntry> },
+    Legacy { files: Vec<String> },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Favorites {
+    entries: Vec<FavoriteEntry>,
+}
 
-    pub fn stop(&self) {
-        if let Some(sink) = self.sink.lock().unwrap().take() {
-            sink.stop();
+impl Favorites {
+    fn new() -> Self {
+        Self {
+            entries: Vec::new(),
         }
-        *self._stream.lock().unwrap() = None;
-        *self.current_path.lock().unwrap() = None;
     }
 
-    pub fn get_current_path(&self) -> Option<String> {
-        self.current_path.lock().unwrap().clone()
+    fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        match serde_json::from_str(&content).map_err(|e| e.to_string())? {
+            StoredFavorites::Current { entries } => Ok(Self { entries }),
+            // 旧形式は読み込み時に新形式へ変換して保存し直す
+            StoredFavorites::Legacy { files } => {
+                let favorites = Self::from_legacy(files);
+                favorites.save(path)?;
+                Ok(favorites)
+            }
+        }
     }
 
-    pub fn is_playing(&self) -> bool {
-        if let Some(sink) = self.sink.lock().unwrap().as_ref() {
-            !sink.empty()
-        } else {
-            false
+    fn from_legacy(files: Vec<String>) -> Self {
+        Self {
+            entries: files.into_iter().map(FavoriteEntry::new).collect(),
         }
     }
-}
 
-fn get_audio_duration(path: &Path) -> Option<f64> {
-    // symphoniaを使用して音声ファイルの長さを取得
-    let file = File::open(path).ok()?;
-    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    // インポート用。保存先のファイルは書き換えず、形式が違う場合は期待する形式をエラーで伝える
+    fn parse_import(content: &str) -> Result<Self, String> {
+        let value: serde_json::Value =
+            serde_json::from_str(content).map_err(|e| format!("JSONとして読み込めません: {}", e))?;
+        let stored: StoredFavorites = serde_json::from_value(value).map_err(|_| {
+            "お気に入りファイルの形式が正しくありません（{\"entries\": [{\"path\": \"...\"}]} の形式が必要です）"
+                .to_string()
+        })?;
+        let favorites = match stored {
+            StoredFavorites::Current { entries } => Self { entries },
+            StoredFavorites::Legacy { files } => Self::from_legacy(files),
+        };
+
+        if favorites.entries.iter().any(|entry| entry.path.trim().is_empty()) {
+            return Err("お気に入りファイルにパスが空の項目があります".to_string());
+        }
+        Ok(favorites)
+    }
 
-    // 拡張子からヒントを作成
-    let mut hint = Hint::new();
-    if let Some(extension) = path.extension() {
-        if let Some(ext_str) = extension.to_str() {
-            hint.with_extension(ext_str);
+    // 同じパスが重複しないように追加する（既にある項目の表示名などはそのまま）
+    fn merge(&mut self, other: Favorites) {
+        for entry in other.entries {
+            if !self.contains(&entry.path) {
+                self.entries.push(entry);
+            }
         }
     }
 
-    // フォーマットをプローブ
-    let format_opts = FormatOptions::default();
-    let metadata_opts = MetadataOptions::default();
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let mut file = File::create(path).map_err(|e| e.to_string())?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        self.entries.iter().any(|entry| entry.path == path)
+    }
+}
+
+// library.dbに保存しているお気に入り全体
+fn load_favorites(app: &AppHandle) -> Result<Favorites, String> {
+    let connection = library::open_library(app)?;
+    Ok(Favorites {
+        entries: library::load_favorites(&connection)?,
+    })
+}
 
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &format_opts, &metadata_opts)
-        .ok()?;
+fn save_favorites(app: &AppHandle, favorites: &Favorites) -> Result<(), String> {
+    let mut connection = library::open_library(app)?;
+    library::replace_favorites(&mut connection, &favorites.entries)
+}
 
-    let format_reader = probed.format;
+// pathのお気に入りの設定を書き換えて保存する（お気に入りでなければエラー）
+fn modify_favorite(
+    app: &AppHandle,
+    path: &str,
+    modify: impl FnOnce(&mut FavoriteEntry),
+) -> Result<(), String> {
+    let connection = library::open_library(app)?;
+    let mut entry = library::find_favorite(&connection, path)?.ok_or("Favorite not found")?;
+    modify(&mut entry);
+    library::save_favorite(&connection, &entry)
+}
 
-    // デフォルトトラックを取得
-    let track = format_reader.default_track()?;
+fn find_favorite(app: &AppHandle, path: &str) -> Option<FavoriteEntry> {
+    let connection = library::open_library(app).ok()?;
+    library::find_favorite(&connection, path).ok()?
+}
 
-    // time_baseを使用してdurationを計算
-    if let Some(n_frames) = track.codec_params.n_frames {
-        if let Some(sample_rate) = track.codec_params.sample_rate {
-            let duration_secs = n_frames as f64 / sample_rate as f64;
-            return Some(duration_secs);
-        }
-    }
+// お気に入りに設定された音量（お気に入りが読み込めない場合も未設定として扱う）
+fn favorite_volume(app: &AppHandle, path: &str) -> Option<f32> {
+    find_favorite(app, path)?.volume
+}
 
-    None
+// お気に入りに設定されたエンベロープ（未設定・お気に入りでない場合は空）
+fn favorite_envelope(app: &AppHandle, path: &str) -> Vec<EnvelopePoint> {
+    find_favorite(app, path)
+        .map(|entry| entry.envelope)
+        .unwrap_or_default()
 }
 
-#[tauri::command]
-fn get_audio_files(directory: String) -> Result<Vec<AudioFile>, String> {
-    let path = Path::new(&directory);
-    if !path.exists() || !path.is_dir() {
-        return Err("Invalid directory".to_string());
+fn favorite_cue_point(app: &AppHandle, path: &str, name: &str) -> Option<CuePoint> {
+    find_favorite(app, path)?
+        .cues
+        .into_iter()
+        .find(|cue| cue.name == name)
+}
+
+// ファイルの移動・削除をお気に入りに反映（新しいパスがNoneの場合は削除）
+fn update_favorite_paths(app: &AppHandle, changes: &[(String, Option<String>)]) -> Result<(), String> {
+    if changes.is_empty() {
+        return Ok(());
     }
 
-    let mut audio_files = Vec::new();
-    // 注意: m4aファイルは一部のファイルで再生エラーが発生する可能性があります
-    let audio_extensions = ["mp3", "wav", "ogg", "flac", "m4a", "aac"];
+    // タグも同じファイルに付いたままになるよう付け替える
+    tags::update_tag_paths(app, changes)?;
 
-    for entry in WalkDir::new(path)
-        .max_depth(1)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
-        if path.is_file() {
-            if let Some(ext) = path.extension() {
-                if audio_extensions.contains(&ext.to_str().unwrap_or("").to_lowercase().as_str()) {
-                    // 音声ファイルの長さを取得
-                    let duration_seconds = get_audio_duration(path);
-
-                    audio_files.push(AudioFile {
-                        name: path
-                            .file_name()
-                            .unwrap()
-                            .to_string_lossy()
-                            .to_string(),
-                        path: path.to_string_lossy().to_string(),
-                        duration_seconds,
-                    });
+    let mut favorites = load_favorites(app)?;
+    let mut changed = false;
+
+    for (old_path, new_path) in changes {
+        if !favorites.contains(old_path) {
+            continue;
+        }
+        match new_path {
+            Some(new_path) if !favorites.contains(new_path) => {
+                for entry in favorites.entries.iter_mut().filter(|e| &e.path == old_path) {
+                    entry.path = new_path.clone();
                 }
             }
+            // 削除、または移動先が既にお気に入りにある場合は古いパスを取り除く
+            _ => favorites.entries.retain(|e| &e.path != old_path),
         }
+        changed = true;
     }
 
-    audio_files.sort_by(|a, b| a.name.cmp(&b.name));
-    Ok(audio_files)
+    if changed {
+        save_favorites(app, &favorites)?;
+    }
+    Ok(())
 }
 
 #[tauri::command]
-fn play_audio(path: String, state: tauri::State<AudioPlayer>, app: tauri::AppHandle) -> Result<(), String> {
-    state.inner().play(&path)?;
-
-    // 現在のパスを保存
-    *state.inner().current_path.lock().unwrap() = Some(path.clone());
+fn get_favorites(app: AppHandle) -> Result<Vec<String>, String> {
+    let favorites = load_favorites(&app)?;
+    Ok(favorites.entries.into_iter().map(|entry| entry.path).collect())
+}
 
-    // バックグラウンドスレッドで再生終了を監視
-    let player = state.inner().clone();
-    let app_handle = app.clone();
-    let file_path = path.clone();
+#[tauri::command]
+fn get_favorites_detailed(app: AppHandle) -> Result<Vec<FavoriteEntry>, String> {
+    Ok(load_favorites(&app)?.entries)
+}
 
-    thread::spawn(move || {
-        // Sinkが存在し、再生が完了するまで待つ
-        loop {
-            thread::sleep(Duration::from_millis(100));
+// 現在のお気に入り（表示名・色・カテゴリを含む）を指定したファイルに書き出す
+#[tauri::command]
+fn export_favorites(destination: String, app: AppHandle) -> Result<(), String> {
+    load_favorites(&app)?.save(Path::new(&destination))
+}
 
-            let is_empty = {
-                if let Some(sink) = player.sink.lock().unwrap().as_ref() {
-                    sink.empty()
-                } else {
-                    true
-                }
-            };
+// mergeがtrueなら今のお気に入りに追加し、falseなら置き換える
+// 戻り値はインポート後のお気に入り
+#[tauri::command]
+fn import_favorites(source: String, merge: bool, app: AppHandle) -> Result<Vec<FavoriteEntry>, String> {
+    let content = fs::read_to_string(&source).map_err(|e| e.to_string())?;
+    let imported = Favorites::parse_import(&content)?;
 
-            // Sinkが空になったら再生終了
-            if is_empty {
-                // current_pathと一致する場合のみイベントを送信
-                let current = player.current_path.lock().unwrap().clone();
+    let mut favorites = if merge {
+        load_favorites(&app)?
+    } else {
+        Favorites::new()
+    };
+    favorites.merge(imported);
+    save_favorites(&app, &favorites)?;
 
-                if current.as_deref() == Some(&file_path) {
-                    let _ = app_handle.emit("audio-finished", file_path.clone());
-                    *player.current_path.lock().unwrap() = None;
-                }
-                break;
-            }
-        }
-    });
+    Ok(favorites.entries)
+}
 
-    Ok(())
+#[tauri::command]
+fn add_favorite(file_path: String, app: AppHandle) -> Result<(), String> {
+    let connection = library::open_library(&app)?;
+    library::add_favorite(&connection, &file_path)
 }
 
+// 表示名・色・カテゴリを設定（Noneの項目は未設定に戻す）
 #[tauri::command]
-fn stop_audio(state: tauri::State<AudioPlayer>) -> Result<(), String> {
-    state.inner().stop();
-    Ok(())
+fn update_favorite(
+    path: String,
+    display_name: Option<String>,
+    color: Option<String>,
+    category: Option<String>,
+    app: AppHandle,
+) -> Result<(), String> {
+    modify_favorite(&app, &path, |entry| {
+        entry.display_name = display_name;
+        entry.color = color;
+        entry.category = category;
+    })
 }
 
+// お気に入りごとの音量を設定（Noneなら未設定に戻し、全体の音量だけで再生する）
 #[tauri::command]
-fn rename_file(old_path: String, new_name: String) -> Result<String, String> {
-    let old = PathBuf::from(&old_path);
-    let parent = old.parent().ok_or("Invalid path")?;
-    let new_path = parent.join(&new_name);
+fn set_favorite_volume(path: String, volume: Option<f32>, app: AppHandle) -> Result<(), String> {
+    if volume.is_some_and(f32::is_nan) {
+        return Err("Volume must be a number".to_string());
+    }
 
-    std::fs::rename(&old, &new_path).map_err(|e| e.to_string())?;
-    Ok(new_path.to_string_lossy().to_string())
+    modify_favorite(&app, &path, |entry| {
+        entry.volume = volume.map(|volume| volume.clamp(0.0, 2.0));
+    })
 }
 
+// お気に入りにキューポイントを保存する（同じ名前があれば置き換える）
+// play_audioのcueに名前を渡すと、その区間だけを再生する
 #[tauri::command]
-fn copy_files(files: Vec<String>, destination: String) -> Result<Vec<String>, String> {
-    let dest_path = Path::new(&destination);
-    if !dest_path.exists() {
-        std::fs::create_dir_all(dest_path).map_err(|e| e.to_string())?;
+fn set_cue_point(
+    path: String,
+    name: String,
+    start_seconds: f64,
+    end_seconds: Option<f64>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Cue point name is empty".to_string());
+    }
+    let valid = start_seconds >= 0.0 && end_seconds.is_none_or(|end| start_seconds < end);
+    if !valid {
+        return Err("Invalid range: start must be before end".to_string());
     }
 
-    let mut copied_files = Vec::new();
-    for file_path in files {
-        let src = Path::new(&file_path);
-        let file_name = src.file_name().ok_or("Invalid file name")?;
-        let dest = dest_path.join(file_name);
+    let cue = CuePoint {
+        name,
+        start_seconds,
+        end_seconds,
+    };
+    modify_favorite(&app, &path, |entry| {
+        match entry.cues.iter_mut().find(|existing| existing.name == cue.name) {
+            Some(existing) => *existing = cue,
+            None => entry.cues.push(cue),
+        }
+    })
+}
 
-        std::fs::copy(src, &dest).map_err(|e| e.to_string())?;
-        copied_files.push(dest.to_string_lossy().to_string());
+// お気に入りに音量のエンベロープを設定する（空にすると一定の音量に戻す）
+// 各点は再生開始からの時間（ミリ秒）と、ファイルごとの音量に掛ける倍率（0.0〜2.0）
+// play_audioで単発再生したときに、再生位置に合わせて音量を変える
+#[tauri::command]
+fn set_favorite_envelope(path: String, envelope: Vec<EnvelopePoint>, app: AppHandle) -> Result<(), String> {
+    if envelope
+        .iter()
+        .any(|point| !(0.0..=2.0).contains(&point.gain))
+    {
+        return Err("Envelope gain must be between 0.0 and 2.0".to_string());
+    }
+    if envelope.windows(2).any(|pair| pair[0].time_ms >= pair[1].time_ms) {
+        return Err("Envelope points must be in increasing time order".to_string());
     }
 
-    Ok(copied_files)
+    modify_favorite(&app, &path, |entry| entry.envelope = envelope)
 }
 
-// お気に入りファイルのパスを取得
-fn get_favorites_file_path(app: &AppHandle) -> Result<PathBuf, String> {
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+fn remove_cue_point(path: String, name: String, app: AppHandle) -> Result<(), String> {
+    modify_favorite(&app, &path, |entry| entry.cues.retain(|cue| cue.name != name))
+}
 
-    // ディレクトリが存在しない場合は作成
-    if !app_data_dir.exists() {
-        fs::create_dir_all(&app_data_dir).map_err(|e| e.to_string())?;
-    }
+// お気に入りでないファイルは空
+#[tauri::command]
+fn get_cue_points(path: String, app: AppHandle) -> Result<Vec<CuePoint>, String> {
+    let connection = library::open_library(&app)?;
+    Ok(library::find_favorite(&connection, &path)?
+        .map(|entry| entry.cues)
+        .unwrap_or_default())
+}
 
-    Ok(app_data_dir.join("favorites.json"))
+#[tauri::command]
+fn remove_favorite(file_path: String, app: AppHandle) -> Result<(), String> {
+    let connection = library::open_library(&app)?;
+    library::remove_favorite(&connection, &file_path)
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-struct Favorites {
-    files: Vec<String>,
+struct MultiPads {
+    pads: Vec<MultiPad>,
 }
 
-impl Favorites {
+impl MultiPads {
     fn new() -> Self {
-        Self { files: Vec::new() }
+        Self { pads: Vec::new() }
     }
 
     fn load(path: &Path) -> Result<Self, String> {
@@ -305,53 +2337,444 @@ impl Favorites {
 }
 
 #[tauri::command]
-fn get_favorites(app: AppHandle) -> Result<Vec<String>, String> {
-    let favorites_path = get_favorites_file_path(&app)?;
-    let favorites = Favorites::load(&favorites_path)?;
-    Ok(favorites.files)
+fn get_multi_pads(app: AppHandle) -> Result<Vec<MultiPad>, String> {
+    let multi_pads_path = get_app_data_file_path(&app, "multi_pads.json")?;
+    let multi_pads = MultiPads::load(&multi_pads_path)?;
+    Ok(multi_pads.pads)
 }
 
 #[tauri::command]
-fn add_favorite(file_path: String, app: AppHandle) -> Result<(), String> {
-    let favorites_path = get_favorites_file_path(&app)?;
-    let mut favorites = Favorites::load(&favorites_path)?;
-
-    if !favorites.files.contains(&file_path) {
-        favorites.files.push(file_path);
-        favorites.save(&favorites_path)?;
+fn create_multi_pad(name: String, members: Vec<MultiPadMember>, app: AppHandle) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Multi pad name is empty".to_string());
+    }
+    if members.is_empty() {
+        return Err("Multi pad has no members".to_string());
     }
 
+    let multi_pads_path = get_app_data_file_path(&app, "multi_pads.json")?;
+    let mut multi_pads = MultiPads::load(&multi_pads_path)?;
+
+    // 同名のマルチパッドは置き換える
+    multi_pads.pads.retain(|pad| pad.name != name);
+    multi_pads.pads.push(MultiPad { name, members });
+    multi_pads.save(&multi_pads_path)?;
+
     Ok(())
 }
 
 #[tauri::command]
-fn remove_favorite(file_path: String, app: AppHandle) -> Result<(), String> {
-    let favorites_path = get_favorites_file_path(&app)?;
-    let mut favorites = Favorites::load(&favorites_path)?;
+fn delete_multi_pad(name: String, app: AppHandle) -> Result<(), String> {
+    let multi_pads_path = get_app_data_file_path(&app, "multi_pads.json")?;
+    let mut multi_pads = MultiPads::load(&multi_pads_path)?;
+
+    multi_pads.pads.retain(|pad| pad.name != name);
+    multi_pads.save(&multi_pads_path)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+fn trigger_multi_pad(name: String, state: tauri::State<AudioPlayer>, app: AppHandle) -> Result<(), String> {
+    let multi_pads_path = get_app_data_file_path(&app, "multi_pads.json")?;
+    let multi_pads = MultiPads::load(&multi_pads_path)?;
+    let pad = multi_pads
+        .pads
+        .into_iter()
+        .find(|pad| pad.name == name)
+        .ok_or("Multi pad not found")?;
+
+    // どのメンバーで失敗したかはエラーメッセージに含まれる
+    state
+        .inner()
+        .play_layered(&pad.name, &pad.members)
+        .inspect_err(|e| emit_audio_error(&app, &pad.name, e))?;
+
+    let _ = app.emit("multi-pad-started", pad.name.clone());
+    emit_now_playing(&app, state.inner());
+
+    // バックグラウンドスレッドで全レイヤーの再生終了を監視
+    let player = state.inner().clone();
+    let app_handle = app.clone();
+    let pad_name = pad.name;
+
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(100));
+
+        match player.take_finished_layers(&pad_name) {
+            Some(true) => {}
+            Some(false) => continue,
+            // 停止または別のマルチパッドに置き換えられた
+            None => break,
+        }
 
-    favorites.files.retain(|f| f != &file_path);
-    favorites.save(&favorites_path)?;
+        let _ = app_handle.emit("multi-pad-finished", pad_name.clone());
+        emit_now_playing(&app_handle, &player);
+        break;
+    });
 
     Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
+// コマンドライン引数でplay・list・serveなどを指定した場合は、ウィンドウを開かずに実行する
+// （Windowsのリリースビルドはコンソールを持たないため、出力はリダイレクトしたときだけ見える）
 pub fn run() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli::parse(&args) {
+        Ok(None) => run_app(false),
+        Ok(Some(CliCommand::Serve)) => run_app(true),
+        Ok(Some(command)) => std::process::exit(cli::execute(command)),
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+// headlessならウィンドウを隠したまま動かす（リモート操作・OSC・ホットキーからだけ操作する）
+fn run_app(headless: bool) {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
+        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .manage(AudioPlayer::new())
+        .manage(DirectoryWatcher::new())
+        .manage(Recorder::new())
+        .manage(RemoteServer::new())
+        .manage(MidiState::new())
+        .manage(OscState::new())
+        .manage(ScanState::new())
+        .manage(HistoryRecorder::new())
+        .manage(ProbeCacheState::new())
+        .manage(LoudnessState::new())
+        .setup(move |app| {
+            if headless {
+                if let Some(window) = app.get_webview_window("main") {
+                    let _ = window.hide();
+                }
+            }
+            let player = app.state::<AudioPlayer>();
+            match get_settings_file_path(app.handle()).and_then(|path| Settings::load(&path)) {
+                Ok(settings) => {
+                    player.inner().restore_output_device(settings.output_device);
+                    player
+                        .inner()
+                        .restore_secondary_output_device(settings.secondary_output_device);
+                }
+                Err(e) => eprintln!("設定の読み込みに失敗しました: {}", e),
+            }
+            if let Err(e) = player.inner().open_stream() {
+                eprintln!("出力ストリームを開けませんでした: {}", e);
+            }
+            hotkeys::register_saved_hotkeys(app.handle());
+            remote::restore_remote(app.handle());
+            osc::restore_osc(app.handle());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             get_audio_files,
+            get_audio_files_multi,
+            search_audio_files,
+            play_random,
+            release_pad,
+            tags::search_library,
+            tags::add_tag,
+            tags::remove_tag,
+            tags::get_tags,
+            tags::list_tags,
+            library::index_library,
+            library::query_library,
+            library::remove_library_directory,
+            library::get_library_directories,
+            find_duplicates,
+            background_scan::start_scan,
+            background_scan::cancel_scan,
+            set_scan_concurrency,
+            get_scan_concurrency,
+            set_extra_extensions,
+            get_audio_extensions,
+            get_audio_info,
+            get_cover_art,
+            get_cover_art_data_url,
+            waveform::get_waveform,
             play_audio,
+            play_bytes,
+            play_looped,
+            play_with_fade,
+            play_range,
+            set_queue,
+            enqueue,
+            get_queue,
+            play_queue,
+            skip_next,
+            skip_previous,
+            clear_queue,
+            set_crossfade,
+            get_crossfade,
+            play_repeated,
+            set_loop,
+            is_looping,
+            get_loop_state,
+            play_polyphonic,
+            stop_sound,
+            stop_playback,
+            stop_all,
+            get_playing_sounds,
             stop_audio,
+            set_stop_fade,
+            get_stop_fade,
+            stop_audio_fade,
+            pause_audio,
+            resume_audio,
+            seek_audio,
+            get_position,
+            is_audio_paused,
+            get_playback_state,
+            is_path_playing,
+            set_volume,
+            get_volume,
+            set_speed,
+            get_speed,
+            reset_speed,
+            set_playback_speed,
+            set_balance,
+            get_balance,
+            set_force_mono,
+            is_force_mono,
+            list_output_devices,
+            set_output_device,
+            get_output_device,
+            set_secondary_output_device,
+            get_secondary_output_device,
+            get_now_playing,
+            measure_trigger_latency,
+            loudness::analyze_loudness,
+            set_normalize_loudness,
+            get_normalize_loudness,
+            history::get_history,
+            history::get_recent_plays,
+            history::get_play_stats,
+            history::clear_history,
+            profiles::list_profiles,
+            profiles::save_profile,
+            profiles::load_profile,
+            profiles::delete_profile,
+            profiles::export_board,
+            profiles::import_board,
+            export::export_to_wav,
+            export::export_clip,
+            export::convert_files,
+            silence::analyze_silence,
+            recorder::start_recording,
+            recorder::stop_recording,
+            recorder::list_input_devices,
+            recorder::start_loopback_capture,
+            tts::generate_tts,
+            tts::speak,
+            remote::start_remote,
+            remote::stop_remote,
+            osc::start_osc,
+            osc::stop_osc,
+            midi::list_midi_devices,
+            midi::connect_midi_device,
+            midi::disconnect_midi_device,
+            midi::set_midi_learn,
+            midi::bind_midi_note,
+            midi::unbind_midi_note,
+            midi::get_midi_bindings,
+            preload,
+            unload,
+            set_cache_limit,
+            get_cache_limit,
+            clear_cache,
             rename_file,
+            batch_rename,
+            delete_file,
             copy_files,
+            move_files,
+            create_directory,
             get_favorites,
+            get_favorites_detailed,
+            export_favorites,
+            import_favorites,
             add_favorite,
-            remove_favorite
+            update_favorite,
+            set_favorite_volume,
+            set_favorite_envelope,
+            set_cue_point,
+            remove_cue_point,
+            get_cue_points,
+            remove_favorite,
+            get_multi_pads,
+            create_multi_pad,
+            delete_multi_pad,
+            trigger_multi_pad,
+            hotkeys::bind_hotkey,
+            hotkeys::unbind_hotkey,
+            hotkeys::register_hotkey,
+            hotkeys::unregister_hotkey,
+            hotkeys::get_hotkeys,
+            watcher::watch_directory,
+            watcher::unwatch_directory,
+            watcher::get_watched_directory
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wav_loop_points_from_smpl_chunk() {
+        let path = test_support::write_wav("smpl-loop", 1, 8000, vec![0i16; 8000]);
+        assert!(read_wav_loop_points(&path).is_none());
+
+        // smplチャンク（ヘッダー36バイト + ループ1つ24バイト）を末尾に足し、RIFFのサイズを直す
+        let mut smpl = vec![0u8; 60];
+        smpl[28..32].copy_from_slice(&1u32.to_le_bytes());
+        smpl[44..48].copy_from_slice(&2000u32.to_le_bytes());
+        smpl[48..52].copy_from_slice(&5999u32.to_le_bytes());
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.extend_from_slice(b"smpl");
+        bytes.extend_from_slice(&(smpl.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&smpl);
+        let riff_size = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+
+        let points = read_wav_loop_points(&path).unwrap();
+        assert_eq!(points.start_seconds, 0.25);
+        assert_eq!(points.end_seconds, 0.75);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn numbered_path_skips_used_numbers() {
+        let dir = crate::test_support::empty_dir("numbered");
+        fs::write(dir.join("a.wav"), b"").unwrap();
+        fs::write(dir.join("a (1).wav"), b"").unwrap();
+
+        assert_eq!(numbered_path(&dir.join("a.wav")), dir.join("a (2).wav"));
+        assert_eq!(numbered_path(&dir.join("notes")), dir.join("notes (1)"));
+        assert_eq!(
+            numbered_path(&dir.join("a.b.wav")),
+            dir.join("a.b (1).wav")
+        );
+    }
+
+    #[test]
+    fn overwrite_copy_onto_itself_is_rejected() {
+        let dir = crate::test_support::empty_dir("copy-self");
+        let file = dir.join("a.wav");
+        fs::write(&file, b"data").unwrap();
+        let files = vec![file.to_string_lossy().to_string()];
+        let destination = dir.to_string_lossy().to_string();
+
+        assert!(copy_files(files.clone(), destination.clone(), Some("overwrite".to_string())).is_err());
+        assert_eq!(fs::read(&file).unwrap(), b"data");
+
+        // renameなら別名で複製する
+        let copied = copy_files(files, destination, None).unwrap();
+        assert_eq!(copied, vec![dir.join("a (1).wav").to_string_lossy().to_string()]);
+    }
+
+    #[test]
+    fn batch_pattern_new_name() {
+        let replace = BatchPattern::Replace {
+            find: "take".to_string(),
+            replace: "mix".to_string(),
+        };
+        assert_eq!(replace.new_name(Path::new("/a/take_take.wav"), 0, 1), "mix_mix.wav");
+        // 拡張子は置換の対象にしない
+        let replace_ext = BatchPattern::Replace {
+            find: "wav".to_string(),
+            replace: "mp3".to_string(),
+        };
+        assert_eq!(replace_ext.new_name(Path::new("/a/wave.wav"), 0, 1), "mp3e.wav");
+
+        // 桁数の省略時は最後の番号（10）の桁数でゼロ埋めする
+        let template = BatchPattern::Template {
+            template: "intro_{n}".to_string(),
+            start: None,
+            digits: None,
+        };
+        assert_eq!(template.new_name(Path::new("/a/x.wav"), 0, 10), "intro_01.wav");
+        assert_eq!(template.new_name(Path::new("/a/x.wav"), 9, 10), "intro_10.wav");
+        assert_eq!(template.new_name(Path::new("/a/notes"), 0, 9), "intro_1");
+
+        let fixed = BatchPattern::Template {
+            template: "{n}-{n}".to_string(),
+            start: Some(98),
+            digits: Some(4),
+        };
+        assert_eq!(fixed.new_name(Path::new("/a/x.flac"), 1, 2), "0099-0099.flac");
+    }
+
+    #[test]
+    fn rename_to_skips_unusable_names() {
+        let dir = crate::test_support::empty_dir("rename-to");
+        fs::write(dir.join("a.wav"), b"a").unwrap();
+        fs::write(dir.join("b.wav"), b"b").unwrap();
+
+        assert!(rename_to(&dir.join("missing.wav"), "c.wav").is_err());
+        assert!(rename_to(&dir.join("a.wav"), "a.wav").unwrap().is_err());
+        assert!(rename_to(&dir.join("a.wav"), "b.wav").unwrap().is_err());
+        assert!(rename_to(&dir.join("a.wav"), "sub/c.wav").unwrap().is_err());
+        assert!(rename_to(&dir.join("a.wav"), "").unwrap().is_err());
+        assert_eq!(fs::read(dir.join("b.wav")).unwrap(), b"b");
+
+        assert_eq!(rename_to(&dir.join("a.wav"), "c.wav"), Ok(Ok(dir.join("c.wav"))));
+        assert!(!dir.join("a.wav").exists());
+    }
+
+    #[test]
+    fn move_into_follows_conflict_strategy() {
+        let source_dir = crate::test_support::empty_dir("move-source");
+        let dest_dir = crate::test_support::empty_dir("move-dest");
+        for name in ["a.wav", "b.wav", "c.wav"] {
+            fs::write(source_dir.join(name), name).unwrap();
+            fs::write(dest_dir.join(name), b"existing").unwrap();
+        }
+
+        let skipped = move_into(&source_dir.join("a.wav"), &dest_dir, ConflictStrategy::Skip);
+        assert_eq!(skipped, Ok(None));
+        assert!(source_dir.join("a.wav").exists());
+
+        let renamed = move_into(&source_dir.join("b.wav"), &dest_dir, ConflictStrategy::Rename);
+        assert_eq!(renamed, Ok(Some(dest_dir.join("b (1).wav"))));
+        assert_eq!(fs::read(dest_dir.join("b.wav")).unwrap(), b"existing");
+
+        let overwritten = move_into(&source_dir.join("c.wav"), &dest_dir, ConflictStrategy::Overwrite);
+        assert_eq!(overwritten, Ok(Some(dest_dir.join("c.wav"))));
+        assert_eq!(fs::read(dest_dir.join("c.wav")).unwrap(), b"c.wav");
+        assert!(!source_dir.join("c.wav").exists());
+    }
+
+    #[test]
+    fn move_into_reports_missing_and_same_file() {
+        let dir = crate::test_support::empty_dir("move-self");
+        fs::write(dir.join("a.wav"), b"data").unwrap();
+
+        assert!(move_into(&dir.join("missing.wav"), &dir, ConflictStrategy::Rename).is_err());
+        assert!(move_into(&dir.join("a.wav"), &dir, ConflictStrategy::Overwrite).is_err());
+        assert_eq!(fs::read(dir.join("a.wav")).unwrap(), b"data");
+    }
+
+    #[test]
+    fn envelope_interpolates_between_points() {
+        let point = |time_ms, gain| EnvelopePoint { time_ms, gain };
+        let envelope = [point(100, 0.0), point(300, 1.0), point(500, 0.5)];
+
+        assert_eq!(envelope_gain(&envelope, 0.0), 0.0);
+        assert_eq!(envelope_gain(&envelope, 200.0), 0.5);
+        assert_eq!(envelope_gain(&envelope, 300.0), 1.0);
+        assert_eq!(envelope_gain(&envelope, 400.0), 0.75);
+        assert_eq!(envelope_gain(&envelope, 10_000.0), 0.5);
+        assert_eq!(envelope_gain(&[], 200.0), 1.0);
+    }
+}
@@ -0,0 +1,683 @@
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::player::AudioPlayer;
+use crate::{
+    get_app_data_file_path, get_favorites_file_path, history, loudness, scan_audio_files, tags,
+    AudioFile, FavoriteEntry, Favorites,
+};
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS files (
+    path TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    source_directory TEXT NOT NULL,
+    subdirectory TEXT NOT NULL,
+    duration_seconds REAL,
+    title TEXT,
+    artist TEXT,
+    album TEXT,
+    modified_at INTEGER,
+    size_bytes INTEGER,
+    sample_rate INTEGER,
+    channels INTEGER,
+    bitrate_kbps INTEGER,
+    loudness_gain REAL,
+    loudness_version INTEGER
+);
+CREATE INDEX IF NOT EXISTS files_source_directory ON files (source_directory);
+CREATE TABLE IF NOT EXISTS favorites (
+    path TEXT PRIMARY KEY,
+    position INTEGER NOT NULL,
+    display_name TEXT,
+    color TEXT,
+    category TEXT,
+    volume REAL,
+    cues TEXT NOT NULL DEFAULT '[]',
+    envelope TEXT NOT NULL DEFAULT '[]'
+);
+CREATE TABLE IF NOT EXISTS tags (
+    path TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (path, tag)
+);
+CREATE TABLE IF NOT EXISTS history (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    played_at INTEGER NOT NULL,
+    outcome TEXT
+);
+CREATE TABLE IF NOT EXISTS play_stats (
+    path TEXT PRIMARY KEY,
+    plays INTEGER NOT NULL DEFAULT 0,
+    completed INTEGER NOT NULL DEFAULT 0,
+    stopped INTEGER NOT NULL DEFAULT 0,
+    last_played_at INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS play_days (
+    date TEXT PRIMARY KEY,
+    plays INTEGER NOT NULL
+);
+";
+
+// 保存形式の段階（PRAGMA user_versionに記録し、上げたらmigrateに手順を足す）
+// 1: favorites.json・tags.jsonの内容を取り込んだ
+// 2: history.json・play_stats.jsonの内容を取り込んだ
+// 3: filesに音量の正規化用のゲインの列を足した（loudness_cache.jsonは取り込まず、登録時に解析し直す）
+// 4: favoritesに音量のエンベロープの列を足した
+const LIBRARY_VERSION: u32 = 4;
+
+// 他のスレッドが書き込み中のとき、エラーにせずに待つ時間
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const COLUMNS: &str = "path, name, source_directory, subdirectory, duration_seconds, title, \
+    artist, album, modified_at, size_bytes, sample_rate, channels, bitrate_kbps";
+
+// query_libraryの条件（省略した項目は絞り込まない）
+#[derive(Debug, Deserialize, Default)]
+pub struct LibraryQuery {
+    // 登録したフォルダのうち、このフォルダのファイルだけを返す
+    directory: Option<String>,
+    // ファイル名・タイトル・アーティスト・アルバムのいずれかに含む文字列（英字の大文字・小文字は区別しない）
+    text: Option<String>,
+    // "name"（フォルダ→ファイル名）、"duration"、"modified"、"size"、"title"、"artist"
+    sort_by: Option<String>,
+    descending: Option<bool>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LibraryPage {
+    // limit・offsetを適用する前の件数
+    total: usize,
+    files: Vec<AudioFile>,
+}
+
+pub fn open_library(app: &AppHandle) -> Result<Connection, String> {
+    let db_path = get_app_data_file_path(app, "library.db")?;
+    let mut connection = Connection::open(db_path).map_err(|e| e.to_string())?;
+    connection
+        .busy_timeout(BUSY_TIMEOUT)
+        .map_err(|e| e.to_string())?;
+    connection
+        .execute_batch(SCHEMA)
+        .map_err(|e| e.to_string())?;
+    migrate(&mut connection, app)?;
+    Ok(connection)
+}
+
+// 以前のバージョンがJSONファイルに保存していた内容を取り込む（元のファイルは消さずに残す）
+fn migrate(connection: &mut Connection, app: &AppHandle) -> Result<(), String> {
+    // 同時に開いた接続が二重に取り込まないよう、書き込みロックを取ってから段階を確認する
+    let tx = connection
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(|e| e.to_string())?;
+    let version: u32 = tx
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if version >= LIBRARY_VERSION {
+        return Ok(());
+    }
+
+    if version < 1 {
+        let favorites = Favorites::load(&get_favorites_file_path(app)?)?;
+        write_favorites(&tx, &favorites.entries)?;
+        for (path, file_tags) in tags::load_json_tags(app)? {
+            for tag in file_tags {
+                tx.execute(
+                    "INSERT OR IGNORE INTO tags (path, tag) VALUES (?1, ?2)",
+                    params![path, tag],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    if version < 2 {
+        history::import_json(&tx, app)?;
+    }
+    // 新しく作ったデータベースには、SCHEMAの時点で列がある
+    if version < 3 && !has_column(&tx, "files", "loudness_gain")? {
+        tx.execute_batch(
+            "ALTER TABLE files ADD COLUMN loudness_gain REAL;
+             ALTER TABLE files ADD COLUMN loudness_version INTEGER;",
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    if version < 4 && !has_column(&tx, "favorites", "envelope")? {
+        tx.execute_batch("ALTER TABLE favorites ADD COLUMN envelope TEXT NOT NULL DEFAULT '[]';")
+            .map_err(|e| e.to_string())?;
+    }
+    tx.pragma_update(None, "user_version", LIBRARY_VERSION)
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+fn has_column(connection: &Connection, table: &str, column: &str) -> Result<bool, String> {
+    let mut statement = connection
+        .prepare(&format!("PRAGMA table_info({})", table))
+        .map_err(|e| e.to_string())?;
+    let names = statement
+        .query_map([], |row| row.get::<_, String>(1))
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+    Ok(names.iter().any(|name| name == column))
+}
+
+const FAVORITE_COLUMNS: &str = "path, display_name, color, category, volume, cues, envelope";
+
+fn favorite_from_row(row: &Row) -> rusqlite::Result<FavoriteEntry> {
+    let cues: String = row.get(5)?;
+    let envelope: String = row.get(6)?;
+    Ok(FavoriteEntry {
+        path: row.get(0)?,
+        display_name: row.get(1)?,
+        color: row.get(2)?,
+        category: row.get(3)?,
+        volume: row.get(4)?,
+        // 読めないキューポイントは無いものとして扱う
+        cues: serde_json::from_str(&cues).unwrap_or_default(),
+        envelope: serde_json::from_str(&envelope).unwrap_or_default(),
+    })
+}
+
+// お気に入りを追加した順に返す
+pub fn load_favorites(connection: &Connection) -> Result<Vec<FavoriteEntry>, String> {
+    let mut statement = connection
+        .prepare(&format!(
+            "SELECT {} FROM favorites ORDER BY position",
+            FAVORITE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let entries = statement
+        .query_map([], favorite_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+    Ok(entries)
+}
+
+pub fn find_favorite(connection: &Connection, path: &str) -> Result<Option<FavoriteEntry>, String> {
+    connection
+        .query_row(
+            &format!("SELECT {} FROM favorites WHERE path = ?1", FAVORITE_COLUMNS),
+            params![path],
+            favorite_from_row,
+        )
+        .optional()
+        .map_err(|e| e.to_string())
+}
+
+// 末尾に追加する（既にあれば何もしない）
+pub fn add_favorite(connection: &Connection, path: &str) -> Result<(), String> {
+    connection
+        .execute(
+            "INSERT OR IGNORE INTO favorites (path, position) \
+             VALUES (?1, (SELECT IFNULL(MAX(position), 0) + 1 FROM favorites))",
+            params![path],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// 表示名・色・カテゴリ・音量・キューポイント・エンベロープを書き換える（並び順はそのまま）
+pub fn save_favorite(connection: &Connection, entry: &FavoriteEntry) -> Result<(), String> {
+    let cues = serde_json::to_string(&entry.cues).map_err(|e| e.to_string())?;
+    let envelope = serde_json::to_string(&entry.envelope).map_err(|e| e.to_string())?;
+    connection
+        .execute(
+            "UPDATE favorites SET display_name = ?2, color = ?3, category = ?4, volume = ?5, \
+             cues = ?6, envelope = ?7 WHERE path = ?1",
+            params![
+                entry.path,
+                entry.display_name,
+                entry.color,
+                entry.category,
+                entry.volume,
+                cues,
+                envelope
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn remove_favorite(connection: &Connection, path: &str) -> Result<(), String> {
+    connection
+        .execute("DELETE FROM favorites WHERE path = ?1", params![path])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// お気に入りの全体をentriesの順で置き換える
+pub fn replace_favorites(
+    connection: &mut Connection,
+    entries: &[FavoriteEntry],
+) -> Result<(), String> {
+    let tx = connection.transaction().map_err(|e| e.to_string())?;
+    write_favorites(&tx, entries)?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+fn write_favorites(connection: &Connection, entries: &[FavoriteEntry]) -> Result<(), String> {
+    connection
+        .execute("DELETE FROM favorites", [])
+        .map_err(|e| e.to_string())?;
+    let mut insert = connection
+        .prepare(
+            "INSERT OR IGNORE INTO favorites \
+             (path, position, display_name, color, category, volume, cues, envelope) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )
+        .map_err(|e| e.to_string())?;
+    for (position, entry) in entries.iter().enumerate() {
+        let cues = serde_json::to_string(&entry.cues).map_err(|e| e.to_string())?;
+        let envelope = serde_json::to_string(&entry.envelope).map_err(|e| e.to_string())?;
+        insert
+            .execute(params![
+                entry.path,
+                position as i64,
+                entry.display_name,
+                entry.color,
+                entry.category,
+                entry.volume,
+                cues,
+                envelope
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn file_from_row(row: &Row) -> rusqlite::Result<AudioFile> {
+    Ok(AudioFile {
+        path: row.get(0)?,
+        name: row.get(1)?,
+        source_directory: row.get(2)?,
+        subdirectory: row.get(3)?,
+        duration_seconds: row.get(4)?,
+        title: row.get(5)?,
+        artist: row.get(6)?,
+        album: row.get(7)?,
+        modified_at: row.get::<_, Option<i64>>(8)?.map(|value| value as u64),
+        size_bytes: row.get::<_, Option<i64>>(9)?.map(|value| value as u64),
+        sample_rate: row.get(10)?,
+        channels: row.get(11)?,
+        bitrate_kbps: row.get(12)?,
+    })
+}
+
+// フォルダ内のファイルを登録し直す（見つからなくなったファイルは削除する）
+// 更新されていないファイルは、解析済みのゲインを引き継ぐ
+fn index_directory(
+    connection: &mut Connection,
+    directory: &str,
+    audio_files: &[AudioFile],
+) -> Result<(), String> {
+    let tx = connection.transaction().map_err(|e| e.to_string())?;
+    let mut gains: HashMap<String, (Option<i64>, f64, Option<u32>)> = {
+        let mut statement = tx
+            .prepare(
+                "SELECT path, modified_at, loudness_gain, loudness_version FROM files \
+                 WHERE source_directory = ?1 AND loudness_gain IS NOT NULL",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params![directory], |row| {
+                Ok((row.get(0)?, (row.get(1)?, row.get(2)?, row.get(3)?)))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| e.to_string())?
+    };
+    tx.execute(
+        "DELETE FROM files WHERE source_directory = ?1",
+        params![directory],
+    )
+    .map_err(|e| e.to_string())?;
+    {
+        let mut insert = tx
+            .prepare(&format!(
+                "INSERT OR REPLACE INTO files ({}, loudness_gain, loudness_version) VALUES \
+                 (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                COLUMNS
+            ))
+            .map_err(|e| e.to_string())?;
+        for file in audio_files {
+            let modified_at = file.modified_at.map(|value| value as i64);
+            let (gain, version) = match gains.remove(&file.path) {
+                Some((previous, gain, version)) if previous == modified_at => (Some(gain), version),
+                _ => (None, None),
+            };
+            insert
+                .execute(params![
+                    file.path,
+                    file.name,
+                    file.source_directory,
+                    file.subdirectory,
+                    file.duration_seconds,
+                    file.title,
+                    file.artist,
+                    file.album,
+                    modified_at,
+                    file.size_bytes.map(|value| value as i64),
+                    file.sample_rate,
+                    file.channels,
+                    file.bitrate_kbps,
+                    gain,
+                    version,
+                ])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+// LIKEの特殊文字をエスケープする（ESCAPE '\' と一緒に使う）
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// 値がないファイルは昇順・降順どちらでも末尾に置く
+fn order_by(sort_by: &str, descending: bool) -> Result<String, String> {
+    let direction = if descending { "DESC" } else { "ASC" };
+    let column = match sort_by {
+        "name" => return Ok(format!("subdirectory {0}, name {0}", direction)),
+        "duration" => "duration_seconds",
+        "modified" => "modified_at",
+        "size" => "size_bytes",
+        "title" => "title",
+        "artist" => "artist",
+        other => return Err(format!("Unknown sort key: {}", other)),
+    };
+    Ok(format!(
+        "{0} IS NULL, {0} {1}, subdirectory, name",
+        column, direction
+    ))
+}
+
+fn query_files(connection: &Connection, query: LibraryQuery) -> Result<LibraryPage, String> {
+    let mut conditions = Vec::new();
+    let mut values = Vec::new();
+    if let Some(directory) = query.directory {
+        conditions.push("source_directory = ?".to_string());
+        values.push(Value::Text(directory));
+    }
+    if let Some(text) = query.text.filter(|text| !text.trim().is_empty()) {
+        let pattern = format!("%{}%", escape_like(text.trim()));
+        let fields = ["name", "title", "artist", "album"];
+        conditions.push(format!(
+            "({})",
+            fields
+                .iter()
+                .map(|field| format!("{} LIKE ? ESCAPE '\\'", field))
+                .collect::<Vec<_>>()
+                .join(" OR ")
+        ));
+        values.extend(fields.iter().map(|_| Value::Text(pattern.clone())));
+    }
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+
+    let total: i64 = connection
+        .query_row(
+            &format!("SELECT COUNT(*) FROM files {}", where_clause),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+
+    let order = order_by(
+        query.sort_by.as_deref().unwrap_or("name"),
+        query.descending.unwrap_or(false),
+    )?;
+    // LIMITに負の値を渡すと上限なしになる
+    values.push(Value::Integer(query.limit.map_or(-1, |limit| limit as i64)));
+    values.push(Value::Integer(query.offset.unwrap_or(0) as i64));
+    let mut statement = connection
+        .prepare(&format!(
+            "SELECT {} FROM files {} ORDER BY {} LIMIT ? OFFSET ?",
+            COLUMNS, where_clause, order
+        ))
+        .map_err(|e| e.to_string())?;
+    let files = statement
+        .query_map(params_from_iter(values.iter()), file_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(LibraryPage {
+        total: total as usize,
+        files,
+    })
+}
+
+// フォルダをスキャンしてライブラリ（app_data_dirのlibrary.db）に登録し、登録したファイル数を返す
+// 同じフォルダを再度登録すると、そのフォルダの内容を置き換える
+// 音量の正規化に使うゲインも、新しいファイル・更新されたファイルの分をここで解析しておく
+#[tauri::command]
+pub async fn index_library(
+    directory: String,
+    max_depth: Option<usize>,
+    app: AppHandle,
+) -> Result<usize, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let audio_files = scan_audio_files(&directory, max_depth, &app)?;
+        let mut connection = open_library(&app)?;
+        index_directory(&mut connection, &directory, &audio_files)?;
+        loudness::analyze_directory(
+            &mut connection,
+            app.state::<AudioPlayer>().inner(),
+            &directory,
+        )?;
+        Ok(audio_files.len())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// 登録済みのファイルを、スキャンせずにデータベースから絞り込み・並べ替えて返す
+#[tauri::command]
+pub async fn query_library(
+    query: Option<LibraryQuery>,
+    app: AppHandle,
+) -> Result<LibraryPage, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let connection = open_library(&app)?;
+        query_files(&connection, query.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// フォルダをライブラリから外す（ファイル自体は削除しない）
+#[tauri::command]
+pub fn remove_library_directory(directory: String, app: AppHandle) -> Result<(), String> {
+    let connection = open_library(&app)?;
+    connection
+        .execute(
+            "DELETE FROM files WHERE source_directory = ?1",
+            params![directory],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// ライブラリに登録されているフォルダを名前順に返す
+#[tauri::command]
+pub fn get_library_directories(app: AppHandle) -> Result<Vec<String>, String> {
+    let connection = open_library(&app)?;
+    let mut statement = connection
+        .prepare("SELECT DISTINCT source_directory FROM files ORDER BY source_directory")
+        .map_err(|e| e.to_string())?;
+    let directories = statement
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<String>>>()
+        .map_err(|e| e.to_string())?;
+    Ok(directories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CuePoint, EnvelopePoint};
+
+    fn memory_library() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+        connection
+    }
+
+    fn audio_file(name: &str, duration_seconds: Option<f64>) -> AudioFile {
+        AudioFile {
+            name: name.to_string(),
+            path: format!("/sounds/{}", name),
+            duration_seconds,
+            source_directory: "/sounds".to_string(),
+            subdirectory: String::new(),
+            title: None,
+            artist: None,
+            album: None,
+            modified_at: None,
+            size_bytes: None,
+            sample_rate: None,
+            channels: None,
+            bitrate_kbps: None,
+        }
+    }
+
+    fn names(page: &LibraryPage) -> Vec<&str> {
+        page.files.iter().map(|file| file.name.as_str()).collect()
+    }
+
+    #[test]
+    fn escape_like_escapes_wildcards() {
+        assert_eq!(escape_like("100%_a\\b"), "100\\%\\_a\\\\b");
+        assert_eq!(escape_like("plain"), "plain");
+    }
+
+    #[test]
+    fn order_by_builds_clause_for_each_key() {
+        assert_eq!(
+            order_by("name", false).unwrap(),
+            "subdirectory ASC, name ASC"
+        );
+        assert_eq!(
+            order_by("duration", true).unwrap(),
+            "duration_seconds IS NULL, duration_seconds DESC, subdirectory, name"
+        );
+        assert!(order_by("bitrate; DROP TABLE files", false).is_err());
+    }
+
+    #[test]
+    fn text_query_matches_wildcards_literally() {
+        let mut connection = memory_library();
+        let files = [audio_file("a_b.wav", None), audio_file("axb.wav", None)];
+        index_directory(&mut connection, "/sounds", &files).unwrap();
+
+        let query = LibraryQuery {
+            text: Some("a_b".to_string()),
+            ..Default::default()
+        };
+        let page = query_files(&connection, query).unwrap();
+        assert_eq!(names(&page), ["a_b.wav"]);
+    }
+
+    #[test]
+    fn sort_keeps_missing_values_last() {
+        let mut connection = memory_library();
+        let files = [
+            audio_file("a.wav", None),
+            audio_file("b.wav", Some(2.0)),
+            audio_file("c.wav", Some(1.0)),
+        ];
+        index_directory(&mut connection, "/sounds", &files).unwrap();
+
+        for (descending, expected) in [
+            (false, ["c.wav", "b.wav", "a.wav"]),
+            (true, ["b.wav", "c.wav", "a.wav"]),
+        ] {
+            let query = LibraryQuery {
+                sort_by: Some("duration".to_string()),
+                descending: Some(descending),
+                ..Default::default()
+            };
+            let page = query_files(&connection, query).unwrap();
+            assert_eq!(page.total, 3);
+            assert_eq!(names(&page), expected);
+        }
+    }
+
+    #[test]
+    fn reindex_keeps_gain_of_unchanged_files() {
+        let mut connection = memory_library();
+        let mut files = [audio_file("a.wav", None), audio_file("b.wav", None)];
+        files[0].modified_at = Some(1000);
+        files[1].modified_at = Some(1000);
+        index_directory(&mut connection, "/sounds", &files).unwrap();
+        connection
+            .execute(
+                "UPDATE files SET loudness_gain = 0.5, loudness_version = 2",
+                [],
+            )
+            .unwrap();
+
+        files[1].modified_at = Some(2000);
+        index_directory(&mut connection, "/sounds", &files).unwrap();
+
+        let gain = |name: &str| -> Option<f64> {
+            connection
+                .query_row(
+                    "SELECT loudness_gain FROM files WHERE name = ?1",
+                    params![name],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        assert_eq!(gain("a.wav"), Some(0.5));
+        assert_eq!(gain("b.wav"), None);
+    }
+
+    #[test]
+    fn favorites_keep_order_and_settings() {
+        let connection = memory_library();
+        add_favorite(&connection, "b.wav").unwrap();
+        add_favorite(&connection, "a.wav").unwrap();
+        add_favorite(&connection, "b.wav").unwrap();
+
+        let mut entry = find_favorite(&connection, "a.wav").unwrap().unwrap();
+        entry.volume = Some(0.5);
+        entry.cues.push(CuePoint {
+            name: "intro".to_string(),
+            start_seconds: 1.0,
+            end_seconds: None,
+        });
+        entry.envelope = vec![EnvelopePoint {
+            time_ms: 500,
+            gain: 0.25,
+        }];
+        save_favorite(&connection, &entry).unwrap();
+
+        let entries = load_favorites(&connection).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["b.wav", "a.wav"]);
+        assert_eq!(entries[1].volume, Some(0.5));
+        assert_eq!(entries[1].cues[0].name, "intro");
+        assert_eq!(entries[1].envelope[0].time_ms, 500);
+
+        remove_favorite(&connection, "b.wav").unwrap();
+        assert!(find_favorite(&connection, "b.wav").unwrap().is_none());
+    }
+}
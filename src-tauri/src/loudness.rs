@@ -0,0 +1,275 @@
+use ebur128::{EbuR128, Mode};
+use rodio::Source;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use tauri::{AppHandle, Manager};
+
+use crate::library::open_library;
+use crate::player::AudioPlayer;
+use crate::scan::{file_modified_ms, map_parallel};
+
+// 正規化の目標とするラウドネス（ReplayGain 2.0と同じ-18 LUFS）
+const TARGET_LUFS: f64 = -18.0;
+// 極端に小さい・大きい音のファイルで増幅しすぎないよう、ゲインはこの範囲に収める
+const MIN_GAIN: f64 = 0.1;
+const MAX_GAIN: f64 = 4.0;
+// 解析方法を変えたら上げる（古い方法で求めたゲインは解析し直す）
+const LOUDNESS_VERSION: u32 = 2;
+// 解析時にまとめてメーターに渡すフレーム数
+const ANALYSIS_FRAMES: usize = 4096;
+
+// 解析済みのゲイン。ライブラリに登録したファイルはlibrary.dbのfilesに保存し、
+// 登録していないファイルは起動中だけメモリに持つ
+pub struct LoudnessState {
+    // パス -> (解析時の更新日時, ゲイン)
+    gains: Mutex<HashMap<String, (u64, f64)>>,
+    // バックグラウンドで解析中のファイル
+    pending: Mutex<HashSet<String>>,
+}
+
+impl LoudnessState {
+    pub fn new() -> Self {
+        Self {
+            gains: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn remember(&self, path: &str, modified_ms: u64, gain: f64) {
+        self.gains
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), (modified_ms, gain));
+    }
+}
+
+// EBU R128の統合ラウドネスから目標の音量に揃えるゲインを求める
+// ピークが1.0を超えないよう、ピークに合わせた上限もかける
+fn compute_gain(source: impl Source) -> Result<f64, String> {
+    let channels = source.channels().max(1) as usize;
+    let mut meter = EbuR128::new(
+        channels as u32,
+        source.sample_rate(),
+        Mode::I | Mode::SAMPLE_PEAK,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let chunk_len = ANALYSIS_FRAMES * channels;
+    let mut buffer = Vec::with_capacity(chunk_len);
+    for sample in source {
+        buffer.push(sample);
+        if buffer.len() == chunk_len {
+            meter.add_frames_f32(&buffer).map_err(|e| e.to_string())?;
+            buffer.clear();
+        }
+    }
+    // 最後の1フレームに満たないサンプルは捨てる
+    buffer.truncate(buffer.len() / channels * channels);
+    meter.add_frames_f32(&buffer).map_err(|e| e.to_string())?;
+
+    let loudness = meter.loudness_global().map_err(|e| e.to_string())?;
+    let mut peak: f64 = 0.0;
+    for channel in 0..channels as u32 {
+        peak = peak.max(meter.sample_peak(channel).map_err(|e| e.to_string())?);
+    }
+
+    // 無音（ラウドネスが-∞）のファイルはそのまま
+    if !loudness.is_finite() || peak <= f64::EPSILON {
+        return Ok(1.0);
+    }
+
+    let gain = 10f64.powf((TARGET_LUFS - loudness) / 20.0);
+    Ok(gain.min(1.0 / peak).clamp(MIN_GAIN, MAX_GAIN))
+}
+
+// library.dbに保存されたゲイン（解析後にファイルが更新されていればNone）
+fn stored_gain(
+    connection: &Connection,
+    path: &str,
+    modified_ms: u64,
+) -> Result<Option<f64>, String> {
+    let gain = connection
+        .query_row(
+            "SELECT loudness_gain FROM files \
+             WHERE path = ?1 AND modified_at = ?2 AND loudness_version = ?3",
+            params![path, modified_ms as i64, LOUDNESS_VERSION],
+            |row| row.get::<_, Option<f64>>(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(gain.flatten())
+}
+
+// ライブラリに登録されていれば、そのファイルの行にゲインを保存する
+fn store_gain(connection: &Connection, path: &str, gain: f64) -> Result<(), String> {
+    connection
+        .execute(
+            "UPDATE files SET loudness_gain = ?2, loudness_version = ?3 WHERE path = ?1",
+            params![path, gain, LOUDNESS_VERSION],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// 解析済みのゲイン（まだ解析していない、または解析後に更新されたファイルはNone）
+fn cached_gain(app: &AppHandle, path: &str) -> Option<f64> {
+    let modified_ms = file_modified_ms(Path::new(path))?;
+    let state = app.state::<LoudnessState>();
+    if let Some(&(cached_ms, gain)) = state.gains.lock().unwrap().get(path) {
+        if cached_ms == modified_ms {
+            return Some(gain);
+        }
+    }
+
+    let connection = open_library(app).ok()?;
+    let gain = stored_gain(&connection, path, modified_ms).ok()??;
+    state.remember(path, modified_ms, gain);
+    Some(gain)
+}
+
+// ファイルを解析してゲインを保存する（ファイル全体をデコードするため時間がかかる）
+fn analyze_and_store(player: &AudioPlayer, path: &str, app: &AppHandle) -> Result<f64, String> {
+    let modified_ms = file_modified_ms(Path::new(path));
+    let gain = compute_gain(player.open_decoder(path)?)?;
+
+    if let Some(modified_ms) = modified_ms {
+        app.state::<LoudnessState>()
+            .remember(path, modified_ms, gain);
+    }
+    store_gain(&open_library(app)?, path, gain)?;
+    Ok(gain)
+}
+
+// キャッシュにあればそれを、なければファイルを解析してゲインを返す
+fn file_gain(player: &AudioPlayer, path: &str, app: &AppHandle) -> Result<f64, String> {
+    match cached_gain(app, path) {
+        Some(gain) => Ok(gain),
+        None => analyze_and_store(player, path, app),
+    }
+}
+
+// 登録したフォルダのうち、ゲインがまだない（または解析方法が古い）ファイルを解析して保存する
+// 解析できなかったファイルはゲインなしのまま残し、次に登録し直したときにもう一度試す
+pub fn analyze_directory(
+    connection: &mut Connection,
+    player: &AudioPlayer,
+    directory: &str,
+) -> Result<(), String> {
+    let paths = {
+        let mut statement = connection
+            .prepare(
+                "SELECT path FROM files WHERE source_directory = ?1 \
+                 AND (loudness_gain IS NULL OR loudness_version IS NOT ?2)",
+            )
+            .map_err(|e| e.to_string())?;
+        statement
+            .query_map(params![directory, LOUDNESS_VERSION], |row| {
+                row.get::<_, String>(0).map(PathBuf::from)
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| e.to_string())?
+    };
+
+    let gains = map_parallel(&paths, None, |path| {
+        let source = player.open_decoder(&path.to_string_lossy()).ok()?;
+        compute_gain(source).ok()
+    });
+
+    let tx = connection.transaction().map_err(|e| e.to_string())?;
+    for (path, gain) in paths.iter().zip(gains) {
+        if let Some(gain) = gain {
+            store_gain(&tx, &path.to_string_lossy(), gain)?;
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+// 再生時に使うゲイン。解析済みでなければデコードを待たずに1.0を返し、
+// 次の再生に備えてバックグラウンドで解析する（同じファイルの解析は1つだけ走らせる）
+pub fn playback_gain(player: &AudioPlayer, path: &str, app: &AppHandle) -> f64 {
+    if let Some(gain) = cached_gain(app, path) {
+        return gain;
+    }
+
+    let state = app.state::<LoudnessState>();
+    if state.pending.lock().unwrap().insert(path.to_string()) {
+        let player = player.clone();
+        let path = path.to_string();
+        let app = app.clone();
+        thread::spawn(move || {
+            if let Err(e) = analyze_and_store(&player, &path, &app) {
+                eprintln!("ラウドネスを解析できませんでした ({}): {}", path, e);
+            }
+            app.state::<LoudnessState>()
+                .pending
+                .lock()
+                .unwrap()
+                .remove(&path);
+        });
+    }
+    1.0
+}
+
+// 音量を揃えるためのゲイン（1.0で変化なし）を返す
+#[tauri::command]
+pub async fn analyze_loudness(
+    path: String,
+    state: tauri::State<'_, AudioPlayer>,
+    app: AppHandle,
+) -> Result<f64, String> {
+    let player = state.inner().clone();
+
+    // デコードに時間がかかるため、ブロッキングスレッドで実行する
+    tauri::async_runtime::spawn_blocking(move || file_gain(&player, &path, &app))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+    use std::f32::consts::PI;
+
+    fn sine(amplitude: f32, seconds: u32) -> SamplesBuffer {
+        let rate = 48000;
+        let samples = (0..rate * seconds)
+            .map(|i| amplitude * (2.0 * PI * 997.0 * i as f32 / rate as f32).sin())
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(1, rate, samples)
+    }
+
+    #[test]
+    fn full_scale_sine_is_turned_down_to_target() {
+        // 0 dBFSの997 Hzの正弦波は-3.01 LUFS
+        let gain = compute_gain(sine(1.0, 3)).unwrap();
+        let expected = 10f64.powf((TARGET_LUFS + 3.01) / 20.0);
+        assert!((gain - expected).abs() < 0.005, "gain = {}", gain);
+    }
+
+    #[test]
+    fn quiet_sine_is_capped_at_max_gain() {
+        assert_eq!(compute_gain(sine(0.01, 3)).unwrap(), MAX_GAIN);
+    }
+
+    #[test]
+    fn gain_does_not_push_peak_over_full_scale() {
+        // 短いクリックだけのファイルはラウドネスが低く、ピークで上限がかかる
+        let mut samples = vec![0.0; 48000 * 3];
+        for i in (0..samples.len()).step_by(4800) {
+            samples[i] = 0.5;
+        }
+        let gain = compute_gain(SamplesBuffer::new(1, 48000, samples)).unwrap();
+        assert!((gain - 2.0).abs() < 1e-9, "gain = {}", gain);
+    }
+
+    #[test]
+    fn silence_keeps_unity_gain() {
+        let silence = SamplesBuffer::new(2, 48000, vec![0.0; 48000 * 2]);
+        assert_eq!(compute_gain(silence).unwrap(), 1.0);
+    }
+}
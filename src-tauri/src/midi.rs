@@ -0,0 +1,266 @@
+use midir::{MidiInput, MidiInputConnection};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::player::AudioPlayer;
+use crate::{get_app_data_file_path, play_audio};
+
+const CLIENT_NAME: &str = "sound-pad";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MidiBinding {
+    note: u8,
+    // MIDIチャンネル（0〜15）。Noneならどのチャンネルでも反応する
+    #[serde(default)]
+    channel: Option<u8>,
+    path: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MidiNoteReceived {
+    note: u8,
+    velocity: u8,
+    channel: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MidiBindings {
+    bindings: Vec<MidiBinding>,
+}
+
+impl MidiBindings {
+    fn new() -> Self {
+        Self {
+            bindings: Vec::new(),
+        }
+    }
+
+    fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let mut file = File::create(path).map_err(|e| e.to_string())?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+// MIDI入力の接続状態（接続するデバイスは1つだけ）
+pub struct MidiState {
+    connection: Mutex<Option<MidiInputConnection<()>>>,
+    // trueの間は受け取ったノートをUIへ通知するだけで、音は鳴らさない
+    learning: Arc<AtomicBool>,
+    // 接続中に参照する割り当て（変更したらファイルと一緒に更新する）
+    bindings: Arc<Mutex<Vec<MidiBinding>>>,
+}
+
+impl MidiState {
+    pub fn new() -> Self {
+        Self {
+            connection: Mutex::new(None),
+            learning: Arc::new(AtomicBool::new(false)),
+            bindings: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+fn get_midi_bindings_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_app_data_file_path(app, "midi_bindings.json")
+}
+
+fn new_midi_input() -> Result<MidiInput, String> {
+    MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())
+}
+
+// ノートオン（ベロシティ0はノートオフ扱い）なら (チャンネル, ノート, ベロシティ) を返す
+fn parse_note_on(message: &[u8]) -> Option<(u8, u8, u8)> {
+    match *message {
+        [status, note, velocity, ..] if status & 0xF0 == 0x90 && velocity > 0 => {
+            Some((status & 0x0F, note, velocity))
+        }
+        _ => None,
+    }
+}
+
+// play_audioと同じ経路で再生する（失敗はaudio-errorで通知される）
+fn handle_note(
+    app: &AppHandle,
+    learning: &AtomicBool,
+    bindings: &Mutex<Vec<MidiBinding>>,
+    message: &[u8],
+) {
+    let Some((channel, note, velocity)) = parse_note_on(message) else {
+        return;
+    };
+
+    if learning.load(Ordering::Relaxed) {
+        let _ = app.emit(
+            "midi-note-received",
+            MidiNoteReceived {
+                note,
+                velocity,
+                channel,
+            },
+        );
+        return;
+    }
+
+    let path = bindings
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|binding| binding.note == note && binding.channel.is_none_or(|c| c == channel))
+        .map(|binding| binding.path.clone());
+    if let Some(path) = path {
+        let state = app.state::<AudioPlayer>();
+        let _ = play_audio(path, None, state, app.clone());
+    }
+}
+
+#[tauri::command]
+pub fn list_midi_devices() -> Result<Vec<String>, String> {
+    let midi_in = new_midi_input()?;
+    Ok(midi_in
+        .ports()
+        .iter()
+        .filter_map(|port| midi_in.port_name(port).ok())
+        .collect())
+}
+
+// 指定した名前のMIDI入力デバイスに接続する（接続中のデバイスがあれば切り替える）
+#[tauri::command]
+pub fn connect_midi_device(
+    name: String,
+    state: tauri::State<MidiState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let midi = state.inner();
+    let mut connection = midi.connection.lock().unwrap();
+    // 同じデバイスに繋ぎ直す場合に備えて、先に今の接続を閉じる
+    if let Some(previous) = connection.take() {
+        let _ = previous.close();
+    }
+
+    let bindings_path = get_midi_bindings_file_path(&app)?;
+    *midi.bindings.lock().unwrap() = MidiBindings::load(&bindings_path)?.bindings;
+
+    let midi_in = new_midi_input()?;
+    let port = midi_in
+        .ports()
+        .into_iter()
+        .find(|port| midi_in.port_name(port).is_ok_and(|port_name| port_name == name))
+        .ok_or_else(|| format!("MIDIデバイスが見つかりません: {}", name))?;
+
+    let learning = midi.learning.clone();
+    let bindings = midi.bindings.clone();
+    let opened = midi_in
+        .connect(
+            &port,
+            CLIENT_NAME,
+            move |_timestamp, message, _| handle_note(&app, &learning, &bindings, message),
+            (),
+        )
+        .map_err(|e| e.to_string())?;
+
+    *connection = Some(opened);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn disconnect_midi_device(state: tauri::State<MidiState>) {
+    if let Some(connection) = state.inner().connection.lock().unwrap().take() {
+        let _ = connection.close();
+    }
+}
+
+// 学習モード中は、押したノートをmidi-note-receivedで通知する（割り当て済みのノートも鳴らさない）
+#[tauri::command]
+pub fn set_midi_learn(enabled: bool, state: tauri::State<MidiState>) {
+    state.inner().learning.store(enabled, Ordering::Relaxed);
+}
+
+// 同じノート・チャンネルの割り当ては置き換える
+#[tauri::command]
+pub fn bind_midi_note(
+    note: u8,
+    channel: Option<u8>,
+    path: String,
+    state: tauri::State<MidiState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    if note > 127 || channel.is_some_and(|channel| channel > 15) {
+        return Err("Invalid MIDI note or channel".to_string());
+    }
+    if !Path::new(&path).is_file() {
+        return Err("File does not exist".to_string());
+    }
+
+    let bindings_path = get_midi_bindings_file_path(&app)?;
+    let mut stored = MidiBindings::load(&bindings_path)?;
+    stored
+        .bindings
+        .retain(|binding| !(binding.note == note && binding.channel == channel));
+    stored.bindings.push(MidiBinding {
+        note,
+        channel,
+        path,
+    });
+    stored.save(&bindings_path)?;
+
+    *state.inner().bindings.lock().unwrap() = stored.bindings;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn unbind_midi_note(
+    note: u8,
+    channel: Option<u8>,
+    state: tauri::State<MidiState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let bindings_path = get_midi_bindings_file_path(&app)?;
+    let mut stored = MidiBindings::load(&bindings_path)?;
+    stored
+        .bindings
+        .retain(|binding| !(binding.note == note && binding.channel == channel));
+    stored.save(&bindings_path)?;
+
+    *state.inner().bindings.lock().unwrap() = stored.bindings;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_midi_bindings(app: AppHandle) -> Result<Vec<MidiBinding>, String> {
+    let bindings_path = get_midi_bindings_file_path(&app)?;
+    Ok(MidiBindings::load(&bindings_path)?.bindings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_note_on_messages() {
+        assert_eq!(parse_note_on(&[0x90, 60, 100]), Some((0, 60, 100)));
+        assert_eq!(parse_note_on(&[0x9F, 36, 1]), Some((15, 36, 1)));
+        // ベロシティ0のノートオン・ノートオフ・短すぎるメッセージは無視する
+        assert_eq!(parse_note_on(&[0x90, 60, 0]), None);
+        assert_eq!(parse_note_on(&[0x80, 60, 100]), None);
+        assert_eq!(parse_note_on(&[0xB0, 7, 127]), None);
+        assert_eq!(parse_note_on(&[0x90, 60]), None);
+        assert_eq!(parse_note_on(&[]), None);
+    }
+}
@@ -0,0 +1,150 @@
+use rodio::source::SeekError;
+use rodio::Source;
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// 1回に送るフレーム数（チャンクを捨ててもチャンネルの並びがずれないよう、フレーム単位で区切る）
+const CHUNK_FRAMES: usize = 256;
+// 送り先に溜めておけるチャンク数（これを超えた分は捨てる。48kHzで約170ms）
+pub const MIRROR_BUFFER_CHUNKS: usize = 32;
+
+pub type MirrorSender = Arc<Mutex<Option<SyncSender<Vec<rodio::Sample>>>>>;
+
+// メインの出力に流すサンプルを、そのまま別の出力先にも送るSource
+// 送り先が設定されていない間は何もしない（送り先はいつでも差し替えられる）
+pub struct TeeSource<S> {
+    inner: S,
+    sender: MirrorSender,
+    chunk: Vec<rodio::Sample>,
+}
+
+impl<S: Source> TeeSource<S> {
+    pub fn new(inner: S, sender: MirrorSender) -> Self {
+        Self {
+            inner,
+            sender,
+            chunk: Vec::new(),
+        }
+    }
+
+    fn flush(&mut self) {
+        let chunk = std::mem::take(&mut self.chunk);
+        let mut sender = self.sender.lock().unwrap();
+        if let Some(tx) = sender.as_ref() {
+            // 送り先が遅れている場合はそのチャンクを捨て、メインの出力は止めない
+            if let Err(TrySendError::Disconnected(_)) = tx.try_send(chunk) {
+                *sender = None;
+            }
+        }
+    }
+}
+
+impl<S: Source> Iterator for TeeSource<S> {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.inner.next()?;
+        self.chunk.push(sample);
+        if self.chunk.len() >= CHUNK_FRAMES * self.inner.channels().max(1) as usize {
+            self.flush();
+        }
+        Some(sample)
+    }
+}
+
+impl<S: Source> Source for TeeSource<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+// TeeSourceから送られたサンプルを再生するSource（届いていない間は無音）
+pub struct MirrorSource {
+    receiver: Receiver<Vec<rodio::Sample>>,
+    chunk: Vec<rodio::Sample>,
+    position: usize,
+    channels: rodio::ChannelCount,
+    sample_rate: rodio::SampleRate,
+}
+
+impl MirrorSource {
+    pub fn new(
+        receiver: Receiver<Vec<rodio::Sample>>,
+        channels: rodio::ChannelCount,
+        sample_rate: rodio::SampleRate,
+    ) -> Self {
+        Self {
+            receiver,
+            chunk: Vec::new(),
+            position: 0,
+            channels,
+            sample_rate,
+        }
+    }
+}
+
+impl Iterator for MirrorSource {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.chunk.len() {
+            match self.receiver.try_recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                // 間に合わなかった分はフレーム単位で無音を挟み、チャンネルの並びを崩さない
+                Err(_) => {
+                    self.chunk.clear();
+                    self.chunk.resize(self.channels.max(1) as usize, 0.0);
+                    self.position = 0;
+                }
+            }
+        }
+
+        let sample = self.chunk.get(self.position).copied().unwrap_or(0.0);
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl Source for MirrorSource {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn try_seek(&mut self, _pos: Duration) -> Result<(), SeekError> {
+        Err(SeekError::NotSupported {
+            underlying_source: std::any::type_name::<Self>(),
+        })
+    }
+}
@@ -0,0 +1,267 @@
+use rosc::{OscMessage, OscPacket, OscType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::player::AudioPlayer;
+use crate::profiles::{load_profile, Pad, Profile};
+use crate::{get_settings_file_path, play_audio, stop_audio, Settings};
+
+// 停止の合図を確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// UDPで受け取れる最大のサイズ
+const MAX_PACKET: usize = 65536;
+
+// 全ての音を止める
+const STOP_ADDRESS: &str = "/soundpad/stop";
+// 最初の文字列の引数をファイルのパスとして鳴らす（プロファイルのパッドにあるファイルのみ）
+const PLAY_ADDRESS: &str = "/soundpad/play";
+
+// 次回起動時にも待ち受けを再開するための設定（settings.jsonに保存する）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OscSettings {
+    port: u16,
+    allow_lan: bool,
+    // パッドのOSCアドレスを読むプロファイル
+    profile: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OscReceived {
+    address: String,
+    // 対応する操作があったか
+    handled: bool,
+    error: Option<String>,
+}
+
+// プロファイルから読んだ、OSCで鳴らせるパッド
+#[derive(Debug, Default)]
+struct OscRoutes {
+    // OSCのアドレス -> パッド
+    mappings: HashMap<String, Pad>,
+    // /soundpad/playで鳴らせるパッド（パス -> パッド）
+    pads: HashMap<String, Pad>,
+}
+
+impl OscRoutes {
+    fn new(profile: &Profile) -> Self {
+        Self {
+            mappings: profile.osc_mappings(),
+            pads: profile.pads_by_path(),
+        }
+    }
+}
+
+struct ActiveOsc {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+    profile: String,
+    // 受け取るたびにプロファイルを読まないよう、保存されたときだけ読み直す
+    routes: Arc<Mutex<OscRoutes>>,
+}
+
+// OSCの待ち受け状態（待ち受けるのは1ポートだけ）
+pub struct OscState {
+    active: Mutex<Option<ActiveOsc>>,
+}
+
+impl OscState {
+    pub fn new() -> Self {
+        Self {
+            active: Mutex::new(None),
+        }
+    }
+}
+
+fn first_string(message: &OscMessage) -> Option<String> {
+    message.args.iter().find_map(|arg| match arg {
+        OscType::String(value) => Some(value.clone()),
+        _ => None,
+    })
+}
+
+// LANから任意のファイルを鳴らされないよう、/soundpad/playでもプロファイルのパッドにないファイルは鳴らさない
+// パッドの音量・動作（ループ・トグル）は、画面でパッドを押したときと同じように反映する
+fn dispatch(
+    app: &AppHandle,
+    routes: &Mutex<OscRoutes>,
+    message: &OscMessage,
+) -> Result<bool, String> {
+    let pad = match message.addr.as_str() {
+        STOP_ADDRESS => {
+            stop_audio(None, app.state::<AudioPlayer>(), app.clone())?;
+            return Ok(true);
+        }
+        PLAY_ADDRESS => {
+            let path = first_string(message).ok_or("Missing path argument")?;
+            match routes.lock().unwrap().pads.get(&path) {
+                Some(pad) => pad.clone(),
+                None => return Err(format!("Not a pad in the OSC profile: {}", path)),
+            }
+        }
+        address => match routes.lock().unwrap().mappings.get(address) {
+            Some(pad) => pad.clone(),
+            None => return Ok(false),
+        },
+    };
+
+    let options = pad.play_options();
+    let state = app.state::<AudioPlayer>();
+    play_audio(pad.path().to_string(), Some(options), state, app.clone())?;
+    Ok(true)
+}
+
+fn handle_packet(app: &AppHandle, routes: &Mutex<OscRoutes>, packet: OscPacket) {
+    match packet {
+        OscPacket::Message(message) => {
+            let (handled, error) = match dispatch(app, routes, &message) {
+                Ok(handled) => (handled, None),
+                Err(e) => (false, Some(e)),
+            };
+            let _ = app.emit(
+                "osc-received",
+                OscReceived {
+                    address: message.addr,
+                    handled,
+                    error,
+                },
+            );
+        }
+        // バンドルの中身は時刻の指定に関わらず、受け取った時点で順に処理する
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                handle_packet(app, routes, packet);
+            }
+        }
+    }
+}
+
+fn start(state: &OscState, settings: &OscSettings, app: AppHandle) -> Result<String, String> {
+    let mut active = state.active.lock().unwrap();
+    if active.is_some() {
+        return Err("OSC is already running".to_string());
+    }
+    let profile = load_profile(settings.profile.clone(), app.clone())?;
+    let routes = Arc::new(Mutex::new(OscRoutes::new(&profile)));
+
+    let host = if settings.allow_lan {
+        "0.0.0.0"
+    } else {
+        "127.0.0.1"
+    };
+    let socket = UdpSocket::bind((host, settings.port)).map_err(|e| e.to_string())?;
+    let address = socket.local_addr().map_err(|e| e.to_string())?;
+    socket
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .map_err(|e| e.to_string())?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    let thread_routes = routes.clone();
+    let handle = thread::spawn(move || {
+        let mut buffer = vec![0u8; MAX_PACKET];
+        while !thread_stop.load(Ordering::Relaxed) {
+            let size = match socket.recv_from(&mut buffer) {
+                Ok((size, _)) => size,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue
+                }
+                Err(e) => {
+                    eprintln!("OSCの受信に失敗しました: {}", e);
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+            };
+            match rosc::decoder::decode_udp(&buffer[..size]) {
+                Ok((_, packet)) => handle_packet(&app, &thread_routes, packet),
+                Err(e) => eprintln!("OSCのメッセージを読めませんでした: {}", e),
+            }
+        }
+    });
+
+    *active = Some(ActiveOsc {
+        stop,
+        handle,
+        profile: settings.profile.clone(),
+        routes,
+    });
+    Ok(address.to_string())
+}
+
+fn save_osc_settings(app: &AppHandle, osc: Option<OscSettings>) -> Result<(), String> {
+    let settings_path = get_settings_file_path(app)?;
+    let mut settings = Settings::load(&settings_path).unwrap_or_default();
+    settings.osc = osc;
+    settings.save(&settings_path)
+}
+
+// プロファイルを保存・削除したときに呼ぶ（削除した場合はprofileをNoneにする）
+// 待ち受けに使っているプロファイルなら、パッドの対応を読み直す
+pub fn reload_profile(app: &AppHandle, name: &str, profile: Option<&Profile>) {
+    let state = app.state::<OscState>();
+    let active = state.active.lock().unwrap();
+    if let Some(active) = active.as_ref().filter(|active| active.profile == name) {
+        *active.routes.lock().unwrap() = profile.map(OscRoutes::new).unwrap_or_default();
+    }
+}
+
+// 前回待ち受けたまま終了していれば、同じ設定で待ち受けを再開する
+pub fn restore_osc(app: &AppHandle) {
+    let osc = get_settings_file_path(app)
+        .and_then(|path| Settings::load(&path))
+        .ok()
+        .and_then(|settings| settings.osc);
+    if let Some(osc) = osc {
+        if let Err(e) = start(app.state::<OscState>().inner(), &osc, app.clone()) {
+            eprintln!("OSCの待ち受けを再開できませんでした: {}", e);
+        }
+    }
+}
+
+// UDPでOSCを待ち受ける。profileのパッドに設定したアドレスで、そのパッドの音を鳴らす
+// /soundpad/stop で全ての音を止め、/soundpad/play "<パス>" でプロファイルのパッドにあるファイルを鳴らす
+// allow_lanがtrueなら全てのインターフェースで待ち受ける（既定ではこのPCからの送信のみ）
+// 戻り値は待ち受けているアドレス
+#[tauri::command]
+pub fn start_osc(
+    port: u16,
+    profile: String,
+    allow_lan: Option<bool>,
+    state: tauri::State<OscState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    // 存在しないプロファイルでは待ち受けを始めない（startでプロファイルを読む）
+    let settings = OscSettings {
+        port,
+        allow_lan: allow_lan.unwrap_or(false),
+        profile,
+    };
+
+    let address = start(state.inner(), &settings, app.clone())?;
+    save_osc_settings(&app, Some(settings))?;
+    Ok(address)
+}
+
+// 待ち受けを止める（次回起動時にも再開しない）
+#[tauri::command]
+pub fn stop_osc(state: tauri::State<OscState>, app: AppHandle) -> Result<(), String> {
+    let osc = state
+        .inner()
+        .active
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or("OSC is not running")?;
+
+    osc.stop.store(true, Ordering::Relaxed);
+    osc.handle
+        .join()
+        .map_err(|_| "OSCのスレッドが異常終了しました".to_string())?;
+    save_osc_settings(&app, None)
+}
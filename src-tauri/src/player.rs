@@ -0,0 +1,1468 @@
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::mixer::Mixer;
+use rodio::{Decoder, OutputStream, OutputStreamBuilder, Sink, Source};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cache::{SampleCache, DEFAULT_CACHE_SAMPLES};
+use crate::decoder::SymphoniaSource;
+use crate::effects::{LoopingSource, MonoDownmix, OffsetSource, StereoBalance};
+use crate::mirror::{MirrorSender, MirrorSource, TeeSource, MIRROR_BUFFER_CHUNKS};
+use crate::scan::get_audio_duration;
+use crate::{
+    envelope_gain, ActiveSound, EnvelopePoint, LatencyReport, LoopState, MultiPadMember,
+    NowPlaying, PlayOptions, PlayQueue, PlaybackState,
+};
+
+// マルチパッドの1レイヤー（gainはメンバー個別の音量）
+struct Layer {
+    path: String,
+    sink: Sink,
+    gain: f32,
+}
+
+// 同時再生中のマルチパッドのSink群
+struct LayerGroup {
+    name: String,
+    layers: Vec<Layer>,
+}
+
+// 曲間を空けずに続けて再生するため、再生中のSinkの後ろに追加したキューの曲
+struct GaplessEntry {
+    // 追加先の再生ID
+    play_id: u64,
+    index: usize,
+    path: String,
+    duration: Option<f64>,
+    loop_flag: Arc<AtomicBool>,
+    repeats: Arc<AtomicU32>,
+    // trueにすると、その曲は再生されずに読み飛ばされる
+    cancelled: Arc<AtomicBool>,
+}
+
+// メインの出力デバイス
+// Sinkはデバイスのミキサーではなくmixerにつなぎ、その出力をデバイスへ流しつつ副出力にも送る
+struct PrimaryOutput {
+    // テストではデバイスを開かず、mixerの出力を直接読む（その場合はNone）
+    stream: Option<OutputStream>,
+    mixer: Mixer,
+}
+
+// 同時再生（ポリフォニック）で鳴っている1音
+struct Voice {
+    path: String,
+    sink: Sink,
+    // ファイルごとの音量補正（全体の音量に掛けてSinkに設定する）
+    gain: f32,
+}
+
+// 終了した再生（finished_id）がまだ現在の再生（current_id）なら、current_pathを空にしてtrueを返す
+// 後から始まった再生があれば、古い再生の終了監視はcurrent_pathに触れない
+fn release_finished_play(current_id: u64, finished_id: u64, current_path: &mut Option<String>) -> bool {
+    if current_id != finished_id {
+        return false;
+    }
+    *current_path = None;
+    true
+}
+
+// 停止時のクリックノイズを防ぐための既定のフェードアウト
+const DEFAULT_STOP_FADE_MILLIS: u64 = 30;
+// エンベロープに合わせて音量を更新する間隔
+const ENVELOPE_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Clone)]
+pub struct AudioPlayer {
+    sink: Arc<Mutex<Option<Sink>>>,
+    stream: Arc<Mutex<Option<PrimaryOutput>>>,
+    current_path: Arc<Mutex<Option<String>>>,
+    layers: Arc<Mutex<Option<LayerGroup>>>,
+    volume: Arc<Mutex<f32>>,
+    // 単発再生中のクリップの音量補正（音量の正規化用）
+    gain: Arc<Mutex<f32>>,
+    speed: Arc<Mutex<f32>>,
+    // 左右のバランス（-1.0で左のみ、1.0で右のみ。以降の再生に反映）
+    balance: Arc<Mutex<f32>>,
+    // trueなら全チャンネルを1チャンネルにまとめて再生する（以降の再生に反映）
+    force_mono: Arc<AtomicBool>,
+    duration: Arc<Mutex<Option<f64>>>,
+    voices: Arc<Mutex<HashMap<String, Voice>>>,
+    next_voice_id: Arc<AtomicU64>,
+    output_device: Arc<Mutex<Option<String>>>,
+    // 出力デバイスが使えなくなったらtrue（次の再生でストリームを開き直す）
+    stream_failed: Arc<AtomicBool>,
+    // 副出力（仮想オーディオケーブルなど）。メインの出力と同じ音を鳴らす
+    secondary_device: Arc<Mutex<Option<String>>>,
+    secondary_stream: Arc<Mutex<Option<OutputStream>>>,
+    mirror_sender: MirrorSender,
+    // 単発再生中のクリップのループ有効フラグ
+    looping: Arc<Mutex<Option<Arc<AtomicBool>>>>,
+    // 単発再生中のクリップの残りの繰り返し回数
+    repeats: Arc<Mutex<Option<Arc<AtomicU32>>>>,
+    queue: Arc<Mutex<PlayQueue>>,
+    gapless_next: Arc<Mutex<Option<GaplessEntry>>>,
+    // デコード済みのサンプル（パスごと）。再生時はファイルを読まずにこれを複製して使う
+    preloaded: Arc<Mutex<SampleCache>>,
+    // 単発再生ごとに増える番号。停止時にも増やし、終了監視は自分の番号のままのときだけ通知する
+    play_id: Arc<AtomicU64>,
+    crossfade_millis: Arc<Mutex<u64>>,
+    // stop_audioで止めるときのフェードアウトの長さ（0ならすぐに止める）
+    stop_fade_millis: Arc<Mutex<u64>>,
+    // holdモードで鳴らした単発再生（パッドのファイルパス -> 再生ID）
+    held: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+// Safe because all fields are protected by Mutex
+unsafe impl Send for AudioPlayer {}
+unsafe impl Sync for AudioPlayer {}
+
+impl AudioPlayer {
+    pub fn new() -> Self {
+        Self {
+            sink: Arc::new(Mutex::new(None)),
+            stream: Arc::new(Mutex::new(None)),
+            current_path: Arc::new(Mutex::new(None)),
+            layers: Arc::new(Mutex::new(None)),
+            volume: Arc::new(Mutex::new(1.0)),
+            gain: Arc::new(Mutex::new(1.0)),
+            speed: Arc::new(Mutex::new(1.0)),
+            balance: Arc::new(Mutex::new(0.0)),
+            force_mono: Arc::new(AtomicBool::new(false)),
+            duration: Arc::new(Mutex::new(None)),
+            voices: Arc::new(Mutex::new(HashMap::new())),
+            next_voice_id: Arc::new(AtomicU64::new(1)),
+            output_device: Arc::new(Mutex::new(None)),
+            stream_failed: Arc::new(AtomicBool::new(false)),
+            secondary_device: Arc::new(Mutex::new(None)),
+            secondary_stream: Arc::new(Mutex::new(None)),
+            mirror_sender: Arc::new(Mutex::new(None)),
+            looping: Arc::new(Mutex::new(None)),
+            repeats: Arc::new(Mutex::new(None)),
+            queue: Arc::new(Mutex::new(PlayQueue::default())),
+            gapless_next: Arc::new(Mutex::new(None)),
+            preloaded: Arc::new(Mutex::new(SampleCache::new(DEFAULT_CACHE_SAMPLES))),
+            play_id: Arc::new(AtomicU64::new(0)),
+            crossfade_millis: Arc::new(Mutex::new(0)),
+            stop_fade_millis: Arc::new(Mutex::new(DEFAULT_STOP_FADE_MILLIS)),
+            held: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // 戻り値は再生ID（終了監視に渡す）
+    pub fn play(&self, path: &str) -> Result<u64, String> {
+        self.play_with_options(path, &PlayOptions::default())
+    }
+
+    // キュー以外から再生した場合は、Sinkを取り合わないようキューを破棄する
+    pub fn play_with_options(&self, path: &str, options: &PlayOptions) -> Result<u64, String> {
+        self.clear_queue();
+        self.start_file(path, options)
+    }
+
+    fn start_file(&self, path: &str, options: &PlayOptions) -> Result<u64, String> {
+        // 前の再生を停止
+        self.stop();
+
+        let source = self.open_decoder(path)?;
+
+        // シーク位置の上限に使うため長さを保持
+        let duration = source
+            .total_duration()
+            .map(|d| d.as_secs_f64())
+            .or_else(|| get_audio_duration(Path::new(path)));
+
+        self.start_source(path, source, duration, options)
+    }
+
+    // ファイルの一部（start〜end秒）だけを再生する。endがNoneならファイルの最後まで
+    // 再生位置と長さは区間の先頭からの秒数になる
+    pub fn play_range(&self, path: &str, start_seconds: f64, end_seconds: Option<f64>) -> Result<u64, String> {
+        self.play_range_with_options(path, start_seconds, end_seconds, &PlayOptions::default())
+    }
+
+    pub fn play_range_with_options(
+        &self,
+        path: &str,
+        start_seconds: f64,
+        end_seconds: Option<f64>,
+        options: &PlayOptions,
+    ) -> Result<u64, String> {
+        self.clear_queue();
+        self.stop();
+
+        let (source, length) = self.open_range(path, start_seconds, end_seconds)?;
+        self.start_source(path, source, length, options)
+    }
+
+    // ファイルのstart〜end秒を切り出したSourceと、その長さを返す
+    fn open_range(
+        &self,
+        path: &str,
+        start_seconds: f64,
+        end_seconds: Option<f64>,
+    ) -> Result<(Box<dyn Source + Send>, Option<f64>), String> {
+        let source = self.open_decoder(path)?;
+        let duration = source
+            .total_duration()
+            .map(|d| d.as_secs_f64())
+            .or_else(|| get_audio_duration(Path::new(path)));
+
+        let end_seconds = end_seconds.or(duration);
+        let valid = start_seconds >= 0.0
+            && end_seconds.is_none_or(|end| {
+                start_seconds < end && duration.is_none_or(|duration| end <= duration)
+            });
+        if !valid {
+            return Err("Invalid range: start must be before end, and end must not exceed the duration".to_string());
+        }
+
+        let source = OffsetSource::new(source, Duration::from_secs_f64(start_seconds))
+            .map_err(|e| format!("シークできませんでした: {}", e))?;
+        // 区間の終わりでSourceが終了するため、終了監視もそこで再生終了を通知する
+        let source: Box<dyn Source + Send> = match end_seconds {
+            Some(end) => Box::new(source.take_duration(Duration::from_secs_f64(end - start_seconds))),
+            None => Box::new(source),
+        };
+
+        Ok((source, end_seconds.map(|end| end - start_seconds)))
+    }
+
+    // メモリ上の音声データを再生し、ファイルパスの代わりに使う識別子と再生IDを返す
+    // 対応形式はファイル再生と同じ（MP3、WAV、OGG、FLAC、AAC/M4A）
+    pub fn play_bytes(&self, data: Vec<u8>) -> Result<(String, u64), String> {
+        self.clear_queue();
+        self.stop();
+
+        let source = SymphoniaSource::from_bytes(data)?;
+        let duration = source.total_duration().map(|d| d.as_secs_f64());
+        let id = format!("memory-{}", self.next_voice_id.fetch_add(1, Ordering::SeqCst));
+        let play_id = self.start_source(&id, Box::new(source), duration, &PlayOptions::default())?;
+
+        Ok((id, play_id))
+    }
+
+    // labelは再生中のファイルパス（メモリ再生の場合は識別子）
+    fn start_source(
+        &self,
+        label: &str,
+        source: Box<dyn Source + Send>,
+        duration: Option<f64>,
+        options: &PlayOptions,
+    ) -> Result<u64, String> {
+        let source = self.apply_channel_effects(source);
+        let gain = options.gain.unwrap_or(1.0);
+        let sink = self.new_sink()?;
+        sink.set_volume(gain * self.get_volume() * envelope_gain(&options.envelope, 0.0));
+        sink.set_speed(self.get_speed());
+        *self.gain.lock().unwrap() = gain;
+
+        // 再生中にループを切り替えられるよう、常にループ用のSourceで包んでおく
+        let loop_flag = Arc::new(AtomicBool::new(options.looping));
+        let (loop_start, loop_end) = match options.loop_region {
+            Some(region) => (
+                Duration::from_secs_f64(region.start_seconds),
+                Some(Duration::from_secs_f64(region.end_seconds)),
+            ),
+            None => (Duration::ZERO, None),
+        };
+        let repeats = Arc::new(AtomicU32::new(options.repeats));
+        let source = LoopingSource::new(
+            source,
+            loop_flag.clone(),
+            repeats.clone(),
+            loop_start,
+            loop_end,
+        )
+        // ループで先頭へ戻るときも、キューと同じ長さのクロスフェードでつなぐ
+        .with_crossfade(Duration::from_millis(self.get_crossfade()));
+
+        // フェードインはSource側で0から1倍へ上げるため、最終的な音量はSinkの設定音量になる
+        match options.fade_in {
+            Some(fade_in) => sink.append(source.fade_in(fade_in)),
+            None => sink.append(source),
+        }
+        sink.play();
+
+        *self.duration.lock().unwrap() = duration;
+        *self.looping.lock().unwrap() = Some(loop_flag);
+        *self.repeats.lock().unwrap() = Some(repeats);
+
+        // Sinkの差し替え・current_path・再生IDの更新を同じロックの中で行い、
+        // 直前の再生の終了監視が新しい再生を自分のものと取り違えないようにする
+        let mut current = self.sink.lock().unwrap();
+        *current = Some(sink);
+        *self.current_path.lock().unwrap() = Some(label.to_string());
+        Ok(self.play_id.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    pub fn is_current_play(&self, play_id: u64) -> bool {
+        self.play_id.load(Ordering::SeqCst) == play_id
+    }
+
+    pub fn current_play_id(&self) -> u64 {
+        self.play_id.load(Ordering::SeqCst)
+    }
+
+    // 再生が最後まで終わったとき、まだ同じ再生であれば片付けてtrueを返す
+    pub fn finish_play(&self, play_id: u64) -> bool {
+        let _sink = self.sink.lock().unwrap();
+        let mut current_path = self.current_path.lock().unwrap();
+        release_finished_play(self.play_id.load(Ordering::SeqCst), play_id, &mut current_path)
+    }
+
+    // 他の音を止めずに再生し、停止用のIDを返す
+    pub fn play_polyphonic(&self, path: &str) -> Result<String, String> {
+        self.play_polyphonic_with_options(path, None, &PlayOptions::default())
+    }
+
+    // rangeを指定すると、その区間（開始秒, 終了秒）だけを再生する
+    // optionsのうち使うのは音量補正とフェードインだけ（ループ・繰り返しは単発再生のみ）
+    pub fn play_polyphonic_with_options(
+        &self,
+        path: &str,
+        range: Option<(f64, Option<f64>)>,
+        options: &PlayOptions,
+    ) -> Result<String, String> {
+        let source = match range {
+            Some((start_seconds, end_seconds)) => self.open_range(path, start_seconds, end_seconds)?.0,
+            None => self.open_decoder(path)?,
+        };
+        let source = self.apply_channel_effects(source);
+        let gain = options.gain.unwrap_or(1.0);
+        let sink = self.new_sink()?;
+
+        sink.set_volume(gain * self.get_volume());
+        sink.set_speed(self.get_speed());
+        match options.fade_in {
+            Some(fade_in) => sink.append(source.fade_in(fade_in)),
+            None => sink.append(source),
+        }
+
+        let id = format!("sound-{}", self.next_voice_id.fetch_add(1, Ordering::SeqCst));
+        self.voices.lock().unwrap().insert(
+            id.clone(),
+            Voice {
+                path: path.to_string(),
+                sink,
+                gain,
+            },
+        );
+
+        Ok(id)
+    }
+
+    // 再生の設定に応じてチャンネルを加工する（設定が既定値なら元のSourceのまま）
+    // モノラル化してからバランスをかけるため、両方有効なら左右に振ったモノラルになる
+    fn apply_channel_effects(&self, source: Box<dyn Source + Send>) -> Box<dyn Source + Send> {
+        let source: Box<dyn Source + Send> = if self.is_force_mono() && source.channels() > 1 {
+            Box::new(MonoDownmix::new(source))
+        } else {
+            source
+        };
+
+        let balance = self.get_balance();
+        if balance == 0.0 {
+            source
+        } else {
+            Box::new(StereoBalance::new(source, balance))
+        }
+    }
+
+    // 出力ストリームは起動時に一度だけ開き、以降はSinkだけを作り直す
+    fn new_sink(&self) -> Result<Sink, String> {
+        let mut stream = self.stream.lock().unwrap();
+        self.ensure_stream(&mut stream)?;
+        match stream.as_ref() {
+            Some(output) => Ok(Sink::connect_new(&output.mixer)),
+            None => Err("Output stream is not available".to_string()),
+        }
+    }
+
+    // 起動時に出力ストリームを開いておき、最初の再生でデバイスを開く待ち時間をなくす
+    pub fn open_stream(&self) -> Result<(), String> {
+        let mut stream = self.stream.lock().unwrap();
+        self.ensure_stream(&mut stream)
+    }
+
+    // デバイスの代わりにmixerへ鳴らす（テストで出力のサンプルを読むため）
+    #[cfg(test)]
+    fn connect_mixer(&self, mixer: Mixer) {
+        *self.stream.lock().unwrap() = Some(PrimaryOutput { stream: None, mixer });
+    }
+
+    // ストリームがまだない、またはデバイスのエラーで使えなくなっている場合に開く
+    fn ensure_stream(&self, stream: &mut Option<PrimaryOutput>) -> Result<(), String> {
+        if self.stream_failed.swap(false, Ordering::SeqCst) {
+            *stream = None;
+        }
+        if stream.is_some() {
+            return Ok(());
+        }
+
+        let mut output_device = self.output_device.lock().unwrap();
+        let opened = match open_output_stream(output_device.as_deref(), self.stream_failed.clone()) {
+            Ok(opened) => opened,
+            // 選択中のデバイスが使えなくなった場合は既定のデバイスに戻す
+            Err(e) if output_device.is_some() => {
+                eprintln!("{}（既定のデバイスを使用します）", e);
+                *output_device = None;
+                open_output_stream(None, self.stream_failed.clone())?
+            }
+            Err(e) => return Err(e),
+        };
+        *stream = Some(self.connect_output(opened));
+        Ok(())
+    }
+
+    // メインの出力にアプリ内のミキサーをつなぐ
+    // 副出力はメインと同じ形式でサンプルを受け取るため、メインを開き直したら副出力もつなぎ直す
+    fn connect_output(&self, stream: OutputStream) -> PrimaryOutput {
+        let channels = stream.config().channel_count();
+        let sample_rate = stream.config().sample_rate();
+        let (mixer, mixer_source) = rodio::mixer::mixer(channels, sample_rate);
+        stream
+            .mixer()
+            .add(TeeSource::new(mixer_source, self.mirror_sender.clone()));
+
+        let secondary_device = self.secondary_device.lock().unwrap().clone();
+        if let Some(name) = secondary_device {
+            if let Err(e) = self.open_secondary(&name, channels, sample_rate) {
+                eprintln!("副出力のデバイスを開けませんでした: {}", e);
+            }
+        }
+        PrimaryOutput {
+            stream: Some(stream),
+            mixer,
+        }
+    }
+
+    fn open_secondary(
+        &self,
+        name: &str,
+        channels: rodio::ChannelCount,
+        sample_rate: rodio::SampleRate,
+    ) -> Result<(), String> {
+        let stream = open_output_stream(Some(name), Arc::new(AtomicBool::new(false)))?;
+        let (sender, receiver) = mpsc::sync_channel(MIRROR_BUFFER_CHUNKS);
+        stream
+            .mixer()
+            .add(MirrorSource::new(receiver, channels, sample_rate));
+
+        *self.mirror_sender.lock().unwrap() = Some(sender);
+        *self.secondary_stream.lock().unwrap() = Some(stream);
+        Ok(())
+    }
+
+    // 副出力のデバイスを設定する（Noneで副出力をやめる）
+    // 単発再生・マルチパッド・同時再生のすべてが、メインと同じ音量・再生位置で副出力からも鳴る
+    pub fn set_secondary_output_device(&self, name: Option<String>) -> Result<(), String> {
+        *self.mirror_sender.lock().unwrap() = None;
+        *self.secondary_stream.lock().unwrap() = None;
+        *self.secondary_device.lock().unwrap() = None;
+        let Some(name) = name else {
+            return Ok(());
+        };
+
+        let mut stream = self.stream.lock().unwrap();
+        self.ensure_stream(&mut stream)?;
+        let config = stream
+            .as_ref()
+            .and_then(|output| output.stream.as_ref())
+            .ok_or("Output stream is not available")?
+            .config();
+        self.open_secondary(&name, config.channel_count(), config.sample_rate())?;
+        *self.secondary_device.lock().unwrap() = Some(name);
+        Ok(())
+    }
+
+    pub fn get_secondary_output_device(&self) -> Option<String> {
+        self.secondary_device.lock().unwrap().clone()
+    }
+
+    // 保存済みの副出力を、ストリームを開く前に設定する（メインを開くときに一緒に開く）
+    pub fn restore_secondary_output_device(&self, name: Option<String>) {
+        *self.secondary_device.lock().unwrap() = name;
+    }
+
+    // プリロード済みならメモリ上のサンプルを、なければファイルをデコードするSourceを返す
+    pub fn open_decoder(&self, path: &str) -> Result<Box<dyn Source + Send>, String> {
+        if let Some(buffer) = self.preloaded.lock().unwrap().get(path) {
+            return Ok(Box::new(buffer));
+        }
+        self.open_file_decoder(path)
+    }
+
+    fn open_file_decoder(&self, path: &str) -> Result<Box<dyn Source + Send>, String> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+
+        // MP4/AACはrodioのDecoderだと失敗することがあるため、symphoniaで直接デコードする
+        if matches!(extension.as_deref(), Some("m4a" | "aac" | "mp4" | "m4b")) {
+            let source = SymphoniaSource::open(Path::new(path)).inspect_err(|e| {
+                eprintln!("{} ({})", e, path);
+            })?;
+            return Ok(Box::new(source));
+        }
+
+        let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        let byte_len = file.metadata().map_err(|e| e.to_string())?.len();
+
+        // BufReaderを使わず、直接Fileを渡す（FileはRead + Seekを実装している）
+        // シークにはファイルサイズが必要
+        let mut builder = Decoder::builder()
+            .with_data(file)
+            .with_byte_len(byte_len)
+            .with_seekable(true);
+        if let Some(ext) = Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            builder = builder.with_hint(ext);
+        }
+
+        match builder.build() {
+            Ok(source) => Ok(Box::new(source)),
+            // rodioのDecoderで開けなかった場合は、symphoniaで直接デコードできないか試す
+            Err(e) => {
+                eprintln!("デコーダーエラー ({}): {}", path, e);
+                let source = SymphoniaSource::open(Path::new(path))
+                    .map_err(|_| format!("デコーダーエラー: {}", e))?;
+                Ok(Box::new(source))
+            }
+        }
+    }
+
+    // ファイル全体をデコードしてメモリに保持し、再生開始時の読み込みとデコードを省く
+    // サンプルは32bit浮動小数点で展開されるため、44.1kHzステレオなら1分あたり約21MBを使う
+    // 長いファイルを大量にプリロードするとその分メモリを消費するので、不要になったらunloadする
+    pub fn preload(&self, path: &str) -> Result<(), String> {
+        let source = self.open_file_decoder(path)?;
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+        let samples: Vec<rodio::Sample> = source.collect();
+
+        let inserted = self
+            .preloaded
+            .lock()
+            .unwrap()
+            .insert(path, channels, sample_rate, samples);
+        if !inserted {
+            return Err("ファイルが大きすぎるため、キャッシュに読み込めません".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn unload(&self, path: &str) -> bool {
+        self.preloaded.lock().unwrap().remove(path).is_some()
+    }
+
+    pub fn clear_preloaded(&self) {
+        self.preloaded.lock().unwrap().clear();
+    }
+
+    // キャッシュの上限（MB）。超えた分は最後に使ってから最も時間が経ったものから捨てる
+    pub fn set_cache_limit(&self, megabytes: usize) {
+        let max_samples = megabytes * 1024 * 1024 / std::mem::size_of::<rodio::Sample>();
+        self.preloaded.lock().unwrap().set_max_samples(max_samples);
+    }
+
+    pub fn get_cache_limit(&self) -> usize {
+        let max_samples = self.preloaded.lock().unwrap().max_samples();
+        max_samples * std::mem::size_of::<rodio::Sample>() / (1024 * 1024)
+    }
+
+    // 複数ファイルを同時に再生（マルチパッド）
+    pub fn play_layered(&self, name: &str, members: &[MultiPadMember]) -> Result<(), String> {
+        self.clear_queue();
+        self.stop();
+
+        // 全メンバーを先にデコードして、鳴り始めのタイミングを揃える
+        let mut sources = Vec::new();
+        for member in members {
+            let source = self
+                .open_decoder(&member.path)
+                .map_err(|e| format!("{} ({})", e, member.path))?;
+            sources.push((member, source));
+        }
+
+        let master_volume = self.get_volume();
+        let mut layers = Vec::new();
+        for (member, source) in sources {
+            let gain = member.volume.unwrap_or(1.0).clamp(0.0, 2.0);
+            let sink = self.new_sink()?;
+            // 全レイヤーの準備が整うまで一時停止しておく
+            sink.pause();
+            sink.set_volume(gain * master_volume);
+            sink.set_speed(self.get_speed());
+            let source = self.apply_channel_effects(source);
+            sink.append(source.delay(Duration::from_millis(member.offset_ms.unwrap_or(0))));
+            layers.push(Layer {
+                path: member.path.clone(),
+                sink,
+                gain,
+            });
+        }
+
+        for layer in &layers {
+            layer.sink.play();
+        }
+
+        *self.layers.lock().unwrap() = Some(LayerGroup {
+            name: name.to_string(),
+            layers,
+        });
+
+        Ok(())
+    }
+
+    // nameのマルチパッドの全レイヤーが鳴り終わっていれば外してtrueを返す
+    // 鳴っている間はfalse、停止または別のマルチパッドに置き換えられていればNone
+    pub fn take_finished_layers(&self, name: &str) -> Option<bool> {
+        let mut layers = self.layers.lock().unwrap();
+        let group = layers.as_ref().filter(|group| group.name == name)?;
+        let finished = group.layers.iter().all(|layer| layer.sink.empty());
+        if finished {
+            *layers = None;
+        }
+        Some(finished)
+    }
+
+    pub fn stop(&self) {
+        {
+            let mut sink = self.sink.lock().unwrap();
+            if let Some(sink) = sink.take() {
+                sink.stop();
+            }
+            self.play_id.fetch_add(1, Ordering::SeqCst);
+        }
+        if let Some(group) = self.layers.lock().unwrap().take() {
+            for layer in group.layers {
+                layer.sink.stop();
+            }
+        }
+        *self.current_path.lock().unwrap() = None;
+        *self.duration.lock().unwrap() = None;
+        *self.looping.lock().unwrap() = None;
+        *self.repeats.lock().unwrap() = None;
+    }
+
+    // 音量を徐々に下げてから停止する（クリックノイズ防止）
+    // Sinkは呼び出し時点で取り出すため、連続して呼ばれても同じSinkを二重にフェードしない
+    pub fn stop_with_fade(&self, millis: u64) {
+        if millis == 0 {
+            self.stop_all();
+            return;
+        }
+
+        let mut sinks = Vec::new();
+        {
+            let mut sink = self.sink.lock().unwrap();
+            sinks.extend(sink.take());
+            self.play_id.fetch_add(1, Ordering::SeqCst);
+        }
+        if let Some(group) = self.layers.lock().unwrap().take() {
+            sinks.extend(group.layers.into_iter().map(|layer| layer.sink));
+        }
+        sinks.extend(self.voices.lock().unwrap().drain().map(|(_, voice)| voice.sink));
+
+        *self.current_path.lock().unwrap() = None;
+        *self.duration.lock().unwrap() = None;
+        *self.looping.lock().unwrap() = None;
+        *self.repeats.lock().unwrap() = None;
+
+        fade_out_sinks(sinks, millis);
+    }
+
+    // クロスフェード用に、再生中のSinkを鳴らしたまま取り出す（play_idの再生が続いている場合のみ）
+    // 以降この再生の終了監視はイベントを送信しない
+    pub fn detach_sink(&self, play_id: u64) -> Option<Sink> {
+        let mut sink = self.sink.lock().unwrap();
+        if !self.is_current_play(play_id) {
+            return None;
+        }
+        let detached = sink.take();
+        self.play_id.fetch_add(1, Ordering::SeqCst);
+        *self.current_path.lock().unwrap() = None;
+        detached
+    }
+
+    // キューの曲同士を重ねる長さ（0なら重ねずに切り替える）
+    pub fn set_crossfade(&self, millis: u64) {
+        *self.crossfade_millis.lock().unwrap() = millis;
+    }
+
+    pub fn get_crossfade(&self) -> u64 {
+        *self.crossfade_millis.lock().unwrap()
+    }
+
+    pub fn set_stop_fade(&self, millis: u64) {
+        *self.stop_fade_millis.lock().unwrap() = millis;
+    }
+
+    pub fn get_stop_fade(&self) -> u64 {
+        *self.stop_fade_millis.lock().unwrap()
+    }
+
+    // キューを差し替える（再生中の音はそのまま）
+    pub fn set_queue(&self, paths: Vec<String>) {
+        self.cancel_gapless_next();
+        *self.queue.lock().unwrap() = PlayQueue {
+            items: paths,
+            index: None,
+        };
+    }
+
+    // キューの末尾に追加する（再生中のキューもそのまま続ける）
+    // 次の曲を後ろに追加しておく必要がある（再生中で、まだ追加していない）場合はtrueを返す
+    pub fn enqueue(&self, paths: Vec<String>) -> bool {
+        let playing = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.items.extend(paths);
+            queue.index.is_some()
+        };
+        playing && self.gapless_next.lock().unwrap().is_none()
+    }
+
+    pub fn clear_queue(&self) {
+        self.cancel_gapless_next();
+        *self.queue.lock().unwrap() = PlayQueue::default();
+    }
+
+    // 最後まで再生したときにキューの再生を終える（曲の一覧は残す）
+    pub fn finish_queue(&self) {
+        self.queue.lock().unwrap().index = None;
+    }
+
+    // 後ろに追加済みの曲を鳴らさないようにする
+    fn cancel_gapless_next(&self) {
+        if let Some(entry) = self.gapless_next.lock().unwrap().take() {
+            entry.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn get_queue(&self) -> PlayQueue {
+        self.queue.lock().unwrap().clone()
+    }
+
+    pub fn queue_index(&self) -> Option<usize> {
+        self.queue.lock().unwrap().index
+    }
+
+    pub fn queue_item(&self, index: usize) -> Option<String> {
+        self.queue.lock().unwrap().items.get(index).cloned()
+    }
+
+    // キューのindex番目（path）を再生する
+    pub fn play_queue_index(&self, index: usize, path: &str, fade_in: Option<Duration>) -> Result<u64, String> {
+        let options = PlayOptions {
+            fade_in,
+            ..Default::default()
+        };
+        let play_id = self.start_file(path, &options)?;
+        self.queue.lock().unwrap().index = Some(index);
+        Ok(play_id)
+    }
+
+    // キューのindex番目（path）を再生中のSinkの後ろに追加する（play_idの再生が続いている場合のみ）
+    // Sinkは追加された順に間を空けずに再生するため、前の曲の終わりからそのまま次の曲になる
+    pub fn append_queue_index(&self, index: usize, path: &str, play_id: u64) -> Result<(), String> {
+        let source = self.open_decoder(path)?;
+        let duration = source
+            .total_duration()
+            .map(|d| d.as_secs_f64())
+            .or_else(|| get_audio_duration(Path::new(path)));
+
+        let loop_flag = Arc::new(AtomicBool::new(false));
+        let repeats = Arc::new(AtomicU32::new(0));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let skip_flag = cancelled.clone();
+        let source = LoopingSource::new(
+            self.apply_channel_effects(source),
+            loop_flag.clone(),
+            repeats.clone(),
+            Duration::ZERO,
+            None,
+        )
+        .skippable()
+        .periodic_access(Duration::from_millis(5), move |source| {
+            if skip_flag.load(Ordering::Relaxed) {
+                source.skip();
+            }
+        });
+
+        let current = self.sink.lock().unwrap();
+        if !self.is_current_play(play_id) {
+            return Ok(());
+        }
+        let Some(sink) = current.as_ref() else {
+            return Ok(());
+        };
+        sink.append(source);
+        *self.gapless_next.lock().unwrap() = Some(GaplessEntry {
+            play_id,
+            index,
+            path: path.to_string(),
+            duration,
+            loop_flag,
+            repeats,
+            cancelled,
+        });
+        Ok(())
+    }
+
+    // 後ろに追加した曲の再生が始まっていれば、再生中の曲をその曲に切り替えてindexとpathを返す
+    pub fn take_gapless_transition(&self, play_id: u64) -> Option<(usize, String)> {
+        let current = self.sink.lock().unwrap();
+        if !self.is_current_play(play_id) {
+            return None;
+        }
+        // 前の曲が終わるとSinkに残る曲は1つ以下になる
+        if current.as_ref()?.len() > 1 {
+            return None;
+        }
+        let mut gapless_next = self.gapless_next.lock().unwrap();
+        if gapless_next.as_ref()?.play_id != play_id {
+            return None;
+        }
+        let entry = gapless_next.take()?;
+
+        *self.current_path.lock().unwrap() = Some(entry.path.clone());
+        *self.duration.lock().unwrap() = entry.duration;
+        *self.looping.lock().unwrap() = Some(entry.loop_flag);
+        *self.repeats.lock().unwrap() = Some(entry.repeats);
+        self.queue.lock().unwrap().index = Some(entry.index);
+        Some((entry.index, entry.path))
+    }
+
+    // 指定位置の次の曲（キューの最後ならNone）
+    pub fn queue_index_after(&self, index: usize) -> Option<usize> {
+        let queue = self.queue.lock().unwrap();
+        (index + 1 < queue.items.len()).then_some(index + 1)
+    }
+
+    // 再生中のクリップのループを切り替える（再生し直さずに反映される）
+    pub fn set_loop(&self, enabled: bool) -> Result<(), String> {
+        let looping = self.looping.lock().unwrap();
+        let flag = looping.as_ref().ok_or("No audio is playing")?;
+        flag.store(enabled, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+
+    pub fn loop_state(&self) -> LoopState {
+        let remaining_repeats = self
+            .repeats
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, |repeats| repeats.load(Ordering::Relaxed));
+        LoopState {
+            looping: self.is_looping(),
+            remaining_repeats,
+        }
+    }
+
+    // 出力デバイスを切り替える（Noneは既定のデバイス）
+    // 見つからない場合は既定のデバイスに切り替えたうえでエラーを返す
+    pub fn set_output_device(&self, name: Option<String>) -> Result<(), String> {
+        self.stop_all();
+
+        self.stream_failed.store(false, Ordering::SeqCst);
+        let (stream, result) = match open_output_stream(name.as_deref(), self.stream_failed.clone()) {
+            Ok(stream) => (stream, Ok(())),
+            Err(e) if name.is_some() => (
+                open_output_stream(None, self.stream_failed.clone())?,
+                Err(format!("{}（既定のデバイスに切り替えました）", e)),
+            ),
+            Err(e) => return Err(e),
+        };
+
+        *self.output_device.lock().unwrap() = if result.is_ok() { name } else { None };
+        *self.stream.lock().unwrap() = Some(self.connect_output(stream));
+        result
+    }
+
+    pub fn get_output_device(&self) -> Option<String> {
+        self.output_device.lock().unwrap().clone()
+    }
+
+    // 保存済みのデバイスを、ストリームを開く前に設定する（見つからなければ開くときに既定に戻す）
+    pub fn restore_output_device(&self, name: Option<String>) {
+        *self.output_device.lock().unwrap() = name;
+    }
+
+    // 指定したIDの音だけを停止（既に終了していれば何もしない）
+    pub fn stop_sound(&self, id: &str) {
+        if let Some(voice) = self.voices.lock().unwrap().remove(id) {
+            voice.sink.stop();
+        }
+    }
+
+    // 同時再生中で、まだ鳴っている音のID一覧
+    pub fn get_playing_sounds(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .voices
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, voice)| !voice.sink.empty())
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    // 同時再生の1音が鳴り終わっていれば取り除いてtrueを返す
+    // 鳴っている間はfalse、既に停止されていればNone
+    pub fn take_finished_voice(&self, id: &str) -> Option<bool> {
+        let mut voices = self.voices.lock().unwrap();
+        let finished = voices.get(id)?.sink.empty();
+        if finished {
+            voices.remove(id);
+        }
+        Some(finished)
+    }
+
+    // play_audioが返したIDの音だけをフェードアウトして止める。止めた音があればtrue
+    // 単発再生のIDは、その再生がまだ続いている場合のみ止める（同時再生のIDはstop_soundと同じ）
+    pub fn stop_playback(&self, id: &str, millis: u64) -> bool {
+        let sink = match parse_playback_id(id) {
+            Some(play_id) => self.take_current_sink(|current, _| current == play_id),
+            None => self.voices.lock().unwrap().remove(id).map(|voice| voice.sink),
+        };
+        let stopped = sink.is_some();
+        fade_out_sinks(sink.into_iter().collect(), millis);
+        stopped
+    }
+
+    // 単発再生に加えて、同時再生中の音もすべて停止
+    pub fn stop_all(&self) {
+        self.stop();
+        for (_, voice) in self.voices.lock().unwrap().drain() {
+            voice.sink.stop();
+        }
+    }
+
+    // pathの音だけを止める（単発再生はそのファイルを再生中の場合のみ）。止めた音があればtrue
+    pub fn stop_path(&self, path: &str, millis: u64) -> bool {
+        let mut sinks = Vec::new();
+        sinks.extend(self.take_current_sink(|_, current_path| current_path == Some(path)));
+
+        {
+            let mut voices = self.voices.lock().unwrap();
+            let ids: Vec<String> = voices
+                .iter()
+                .filter(|(_, voice)| voice.path == path)
+                .map(|(id, _)| id.clone())
+                .collect();
+            sinks.extend(ids.iter().filter_map(|id| voices.remove(id)).map(|voice| voice.sink));
+        }
+
+        let stopped = !sinks.is_empty();
+        fade_out_sinks(sinks, millis);
+        stopped
+    }
+
+    // 単発再生がconditionを満たせば、そのSinkを取り出して再生の状態を片付ける
+    // conditionには現在の再生IDと再生中のファイルパスを渡す
+    fn take_current_sink(&self, condition: impl FnOnce(u64, Option<&str>) -> bool) -> Option<Sink> {
+        let sink = {
+            let mut sink = self.sink.lock().unwrap();
+            let mut current_path = self.current_path.lock().unwrap();
+            if !condition(self.play_id.load(Ordering::SeqCst), current_path.as_deref()) {
+                return None;
+            }
+            self.play_id.fetch_add(1, Ordering::SeqCst);
+            *current_path = None;
+            sink.take()
+        };
+        *self.duration.lock().unwrap() = None;
+        *self.looping.lock().unwrap() = None;
+        *self.repeats.lock().unwrap() = None;
+        sink
+    }
+
+    // holdモードで鳴らした再生を、パッド（ファイルパス）ごとに覚えておく
+    pub fn hold(&self, path: &str, play_id: u64) {
+        self.held.lock().unwrap().insert(path.to_string(), play_id);
+    }
+
+    // pathのパッドをholdモードで鳴らした再生だけを止める。止めた音があればtrue
+    // その後に別の再生が始まっていたり、既に終わっていたりすれば何もしない
+    pub fn release(&self, path: &str, millis: u64) -> bool {
+        let Some(play_id) = self.held.lock().unwrap().remove(path) else {
+            return false;
+        };
+        let sink = self.take_current_sink(|current_id, current_path| {
+            current_id == play_id && current_path == Some(path)
+        });
+        let stopped = sink.is_some();
+        fade_out_sinks(sink.into_iter().collect(), millis);
+        stopped
+    }
+
+    // 現在の再生位置（秒）。シーク後はシーク先からの位置を返す
+    pub fn get_position(&self) -> Option<f64> {
+        self.sink
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|sink| self.file_position(sink))
+    }
+
+    // 単発再生中のクリップの長さ（秒）
+    pub fn get_duration(&self) -> Option<f64> {
+        *self.duration.lock().unwrap()
+    }
+
+    // Sinkの位置は速度を掛けた後の経過時間なので、ファイル上の秒数に換算する
+    fn file_position(&self, sink: &Sink) -> f64 {
+        sink.get_pos().as_secs_f64() * self.get_speed() as f64
+    }
+
+    // 再生位置を移動（長さが分かる場合はその範囲に収める）
+    pub fn seek(&self, seconds: f64) -> Result<(), String> {
+        if seconds.is_nan() {
+            return Err("Seek position must be a number".to_string());
+        }
+        let mut seconds = seconds.max(0.0);
+        if let Some(duration) = *self.duration.lock().unwrap() {
+            seconds = seconds.min(duration);
+        }
+
+        let sink = self.sink.lock().unwrap();
+        let sink = sink.as_ref().ok_or("No audio is playing")?;
+        // Sinkは速度を掛けた後の時間でシークするため、ファイル上の秒数から換算する
+        sink.try_seek(Duration::from_secs_f64(seconds / self.get_speed() as f64))
+            .map_err(|e| format!("シークできませんでした: {}", e))
+    }
+
+    pub fn get_current_path(&self) -> Option<String> {
+        self.current_path.lock().unwrap().clone()
+    }
+
+    pub fn pause(&self) {
+        if let Some(sink) = self.sink.lock().unwrap().as_ref() {
+            sink.pause();
+        }
+        if let Some(group) = self.layers.lock().unwrap().as_ref() {
+            for layer in &group.layers {
+                layer.sink.pause();
+            }
+        }
+        for voice in self.voices.lock().unwrap().values() {
+            voice.sink.pause();
+        }
+    }
+
+    pub fn resume(&self) {
+        if let Some(sink) = self.sink.lock().unwrap().as_ref() {
+            sink.play();
+        }
+        if let Some(group) = self.layers.lock().unwrap().as_ref() {
+            for layer in &group.layers {
+                layer.sink.play();
+            }
+        }
+        for voice in self.voices.lock().unwrap().values() {
+            voice.sink.play();
+        }
+    }
+
+    // 一時停止中はSinkが空にならないため、再生中とは区別する
+    // 単発再生のSinkがないか、最後まで再生して空になっていればtrue（一時停止中はfalse）
+    pub fn is_sink_empty(&self) -> bool {
+        self.sink.lock().unwrap().as_ref().is_none_or(|sink| sink.empty())
+    }
+
+    pub fn is_playing(&self) -> bool {
+        if let Some(sink) = self.sink.lock().unwrap().as_ref() {
+            !sink.empty() && !sink.is_paused()
+        } else {
+            false
+        }
+    }
+
+    // 指定したファイルが単発再生・マルチパッド・同時再生のいずれかで鳴っているか
+    // 一時停止中でもSinkが空でなければ再生中とみなす
+    pub fn is_path_playing(&self, path: &str) -> bool {
+        if self.get_current_path().as_deref() == Some(path)
+            && self.sink.lock().unwrap().as_ref().is_some_and(|sink| !sink.empty())
+        {
+            return true;
+        }
+        if let Some(group) = self.layers.lock().unwrap().as_ref() {
+            if group
+                .layers
+                .iter()
+                .any(|layer| layer.path == path && !layer.sink.empty())
+            {
+                return true;
+            }
+        }
+        self.voices
+            .lock()
+            .unwrap()
+            .values()
+            .any(|voice| voice.path == path && !voice.sink.empty())
+    }
+
+    // 単発再生・マルチパッド・同時再生のどれか1つでも鳴っていればPlaying、
+    // 鳴っている音がなく一時停止中の音があればPaused
+    pub fn playback_state(&self) -> PlaybackState {
+        let active = self.now_playing().active;
+        if active.iter().any(|sound| !sound.paused) {
+            PlaybackState::Playing
+        } else if active.is_empty() {
+            PlaybackState::Stopped
+        } else {
+            PlaybackState::Paused
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        if let Some(sink) = self.sink.lock().unwrap().as_ref() {
+            !sink.empty() && sink.is_paused()
+        } else {
+            false
+        }
+    }
+
+    // 再生を始めてからSinkが実際に出力を始めるまでの時間を計測
+    // 計測用のSinkは単発再生・同時再生とは別に作るため、鳴っている音は止めない（計測の音は重なって鳴る）
+    pub fn measure_trigger_latency(&self, path: &str, runs: u32) -> Result<LatencyReport, String> {
+        let mut samples = Vec::new();
+
+        for _ in 0..runs {
+            // playと同じく、デコーダーを開いてSinkを作るところから計る
+            let started = Instant::now();
+            let source = self.open_decoder(path)?;
+            let sink = self.new_sink()?;
+            sink.set_volume(self.get_volume());
+            sink.set_speed(self.get_speed());
+            sink.append(self.apply_channel_effects(source));
+
+            // 再生位置が進み始めた時点を出力開始とみなす
+            let deadline = started + Duration::from_secs(2);
+            while sink.get_pos().is_zero() && !sink.empty() {
+                if Instant::now() > deadline {
+                    sink.stop();
+                    return Err("Playback did not start within 2 seconds".to_string());
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            samples.push(started.elapsed().as_secs_f64() * 1000.0);
+            sink.stop();
+        }
+
+        Ok(LatencyReport {
+            runs,
+            average_ms: samples.iter().sum::<f64>() / samples.len() as f64,
+            min_ms: samples.iter().cloned().fold(f64::INFINITY, f64::min),
+            max_ms: samples.iter().cloned().fold(0.0, f64::max),
+        })
+    }
+
+    // 音量を設定（0.0〜2.0、再生中のSinkと以降の再生に反映）
+    pub fn set_volume(&self, volume: f32) -> Result<(), String> {
+        if volume.is_nan() {
+            return Err("Volume must be a number".to_string());
+        }
+        let volume = volume.clamp(0.0, 2.0);
+        *self.volume.lock().unwrap() = volume;
+
+        if let Some(sink) = self.sink.lock().unwrap().as_ref() {
+            sink.set_volume(*self.gain.lock().unwrap() * volume);
+        }
+        if let Some(group) = self.layers.lock().unwrap().as_ref() {
+            for layer in &group.layers {
+                layer.sink.set_volume(layer.gain * volume);
+            }
+        }
+        for voice in self.voices.lock().unwrap().values() {
+            voice.sink.set_volume(voice.gain * volume);
+        }
+
+        Ok(())
+    }
+
+    pub fn get_volume(&self) -> f32 {
+        *self.volume.lock().unwrap()
+    }
+
+    // 再生速度を設定（0.25〜4.0、音程も一緒に変わる）し、実際に設定した値を返す
+    // 再生中のSinkと以降の再生に反映される
+    // get_positionと長さはファイル上の秒数のままなので、実際の残り時間は速度で割って求める
+    pub fn set_speed(&self, factor: f32) -> Result<f32, String> {
+        if factor.is_nan() {
+            return Err("Speed must be a number".to_string());
+        }
+        let factor = factor.clamp(0.25, 4.0);
+        *self.speed.lock().unwrap() = factor;
+
+        if let Some(sink) = self.sink.lock().unwrap().as_ref() {
+            sink.set_speed(factor);
+        }
+        if let Some(group) = self.layers.lock().unwrap().as_ref() {
+            for layer in &group.layers {
+                layer.sink.set_speed(factor);
+            }
+        }
+        for voice in self.voices.lock().unwrap().values() {
+            voice.sink.set_speed(factor);
+        }
+
+        Ok(factor)
+    }
+
+    pub fn get_speed(&self) -> f32 {
+        *self.speed.lock().unwrap()
+    }
+
+    // 同時再生中の1音だけ速度を変える（全体の速度を変えると、この音も全体の速度に戻る）
+    pub fn set_sound_speed(&self, id: &str, factor: f32) -> Result<f32, String> {
+        if factor.is_nan() {
+            return Err("Speed must be a number".to_string());
+        }
+        let factor = factor.clamp(0.25, 4.0);
+
+        let voices = self.voices.lock().unwrap();
+        let voice = voices.get(id).ok_or("Sound not found")?;
+        voice.sink.set_speed(factor);
+        Ok(factor)
+    }
+
+    // 左右のバランスを設定（-1.0〜1.0、0.0で中央に戻す）。次に再生する音から反映される
+    pub fn set_balance(&self, pan: f32) -> Result<(), String> {
+        if pan.is_nan() {
+            return Err("Balance must be a number".to_string());
+        }
+        *self.balance.lock().unwrap() = pan.clamp(-1.0, 1.0);
+        Ok(())
+    }
+
+    pub fn get_balance(&self) -> f32 {
+        *self.balance.lock().unwrap()
+    }
+
+    // 次に再生する音から反映される
+    pub fn set_force_mono(&self, enabled: bool) {
+        self.force_mono.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_force_mono(&self) -> bool {
+        self.force_mono.load(Ordering::Relaxed)
+    }
+
+    // 再生中のサウンドの状態をまとめて取得
+    pub fn now_playing(&self) -> NowPlaying {
+        let mut active = Vec::new();
+        if let Some(path) = self.get_current_path() {
+            if let Some(sink) = self.sink.lock().unwrap().as_ref() {
+                if !sink.empty() {
+                    active.push(ActiveSound {
+                        path,
+                        position_seconds: self.file_position(sink),
+                        paused: sink.is_paused(),
+                    });
+                }
+            }
+        }
+        if let Some(group) = self.layers.lock().unwrap().as_ref() {
+            for layer in &group.layers {
+                if !layer.sink.empty() {
+                    active.push(ActiveSound {
+                        path: layer.path.clone(),
+                        position_seconds: self.file_position(&layer.sink),
+                        paused: layer.sink.is_paused(),
+                    });
+                }
+            }
+        }
+        for voice in self.voices.lock().unwrap().values() {
+            if !voice.sink.empty() {
+                active.push(ActiveSound {
+                    path: voice.path.clone(),
+                    position_seconds: self.file_position(&voice.sink),
+                    paused: voice.sink.is_paused(),
+                });
+            }
+        }
+        NowPlaying {
+            active,
+            master_volume: self.get_volume(),
+            queue: self.get_queue(),
+        }
+    }
+}
+
+fn find_output_device(name: &str) -> Option<rodio::cpal::Device> {
+    rodio::cpal::default_host()
+        .output_devices()
+        .ok()?
+        .find(|device| device.name().is_ok_and(|n| n == name))
+}
+
+// 出力ストリームを開く（device_nameがNoneなら既定のデバイス）
+// failedはデバイスが抜かれるなどしてストリームが使えなくなったときにtrueになる
+fn open_output_stream(device_name: Option<&str>, failed: Arc<AtomicBool>) -> Result<OutputStream, String> {
+    let builder = match device_name {
+        Some(name) => {
+            let device = find_output_device(name)
+                .ok_or_else(|| format!("出力デバイスが見つかりません: {}", name))?;
+            OutputStreamBuilder::from_device(device).map_err(|e| e.to_string())?
+        }
+        None => OutputStreamBuilder::from_default_device().map_err(|e| e.to_string())?,
+    };
+
+    builder
+        .with_error_callback(move |e| {
+            eprintln!("出力デバイスのエラー: {}", e);
+            if matches!(e, rodio::cpal::StreamError::DeviceNotAvailable) {
+                failed.store(true, Ordering::SeqCst);
+            }
+        })
+        .open_stream_or_fallback()
+        .map_err(|e| e.to_string())
+}
+
+// 単発再生の再生IDを、同時再生のID（"sound-N"）と同じく文字列のIDとして返す
+pub fn playback_id(play_id: u64) -> String {
+    format!("play-{}", play_id)
+}
+
+pub fn parse_playback_id(id: &str) -> Option<u64> {
+    id.strip_prefix("play-")?.parse().ok()
+}
+
+// 音量を徐々に下げてから停止する（Sinkは呼び出し元で取り出しておく）
+pub fn fade_out_sinks(sinks: Vec<Sink>, millis: u64) {
+    if sinks.is_empty() {
+        return;
+    }
+
+    thread::spawn(move || {
+        let steps = (millis / 10).max(1);
+        let step_duration = Duration::from_millis(millis / steps);
+        let start_volumes: Vec<f32> = sinks.iter().map(|sink| sink.volume()).collect();
+
+        for step in 1..=steps {
+            let factor = 1.0 - step as f32 / steps as f32;
+            for (sink, volume) in sinks.iter().zip(&start_volumes) {
+                sink.set_volume(volume * factor);
+            }
+            thread::sleep(step_duration);
+        }
+
+        for sink in sinks {
+            sink.stop();
+        }
+    });
+}
+
+// 単発再生の間、再生位置に合わせてSinkの音量を「ファイルごとの音量 × 全体の音量 × エンベロープ」にする
+// 再生が終わるか、別の再生（キューの次の曲を含む）に切り替わったら、エンベロープを外して終える
+pub fn spawn_envelope(player: AudioPlayer, path: String, play_id: u64, envelope: Vec<EnvelopePoint>) {
+    if envelope.is_empty() {
+        return;
+    }
+    thread::spawn(move || loop {
+        {
+            let sink = player.sink.lock().unwrap();
+            let Some(sink) = sink.as_ref().filter(|_| player.is_current_play(play_id)) else {
+                break;
+            };
+            let volume = *player.gain.lock().unwrap() * player.get_volume();
+            if sink.empty() || player.get_current_path().as_deref() != Some(path.as_str()) {
+                sink.set_volume(volume);
+                break;
+            }
+            let time_ms = player.file_position(sink) * 1000.0;
+            sink.set_volume(volume * envelope_gain(&envelope, time_ms));
+        }
+        thread::sleep(ENVELOPE_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_wav;
+
+    #[test]
+    fn queued_clips_play_back_to_back() {
+        let first = write_wav("queue-1", 1, 8000, vec![8192i16; 800]);
+        let second = write_wav("queue-2", 1, 8000, vec![16384i16; 800]);
+        let first = first.to_string_lossy().to_string();
+        let second = second.to_string_lossy().to_string();
+
+        let player = AudioPlayer::new();
+        let (mixer, output) = rodio::mixer::mixer(1, 8000);
+        player.connect_mixer(mixer);
+        player.set_queue(vec![first.clone(), second.clone()]);
+
+        // play_queue_entry・queue_gapless_nextと同じく、1曲目を鳴らしてから2曲目を後ろに追加する
+        let play_id = player.play_queue_index(0, &first, None).unwrap();
+        player.append_queue_index(1, &second, play_id).unwrap();
+
+        // 1曲目の直後のサンプルから2曲目になり、つなぎ目に無音が入らない
+        let samples: Vec<f32> = output.take(1200).collect();
+        assert_eq!(samples.len(), 1200);
+        assert!(samples[..800].iter().all(|&sample| sample == 0.25));
+        assert!(samples[800..].iter().all(|&sample| sample == 0.5));
+
+        // 終了監視と同じく、再生中の曲とキューの位置を2曲目に切り替える
+        assert_eq!(player.take_gapless_transition(play_id), Some((1, second.clone())));
+        assert_eq!(player.get_current_path(), Some(second.clone()));
+        assert_eq!(player.get_queue().index, Some(1));
+
+        player.stop_all();
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn stale_finish_does_not_clear_newer_play() {
+        // 1つ目の再生（ID 1）の直後に2つ目（ID 2）を始め、古い監視が最後に終わる場合
+        // その時点では3つ目（ID 3）が鳴っていても、current_pathを消してはいけない
+        let mut current_path = Some("b.wav".to_string());
+
+        assert!(release_finished_play(2, 2, &mut current_path));
+        assert_eq!(current_path, None);
+
+        current_path = Some("c.wav".to_string());
+        assert!(!release_finished_play(3, 1, &mut current_path));
+        assert_eq!(current_path.as_deref(), Some("c.wav"));
+    }
+
+    #[test]
+    fn stale_finish_before_current_play_ends() {
+        let mut current_path = Some("b.wav".to_string());
+
+        assert!(!release_finished_play(2, 1, &mut current_path));
+        assert_eq!(current_path.as_deref(), Some("b.wav"));
+
+        assert!(release_finished_play(2, 2, &mut current_path));
+        assert_eq!(current_path, None);
+    }
+
+    #[test]
+    fn playback_ids_round_trip() {
+        assert_eq!(parse_playback_id(&playback_id(42)), Some(42));
+        // 同時再生のIDは単発再生のIDとして読まない
+        assert_eq!(parse_playback_id("sound-42"), None);
+        assert_eq!(parse_playback_id("play-"), None);
+    }
+}
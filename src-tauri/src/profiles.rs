@@ -0,0 +1,625 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use tauri::AppHandle;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    get_app_data_file_path, load_favorites, numbered_path, osc, PlayAudioOptions, PlayMode,
+};
+
+// 最初に作るプロファイル・ページの名前
+const DEFAULT_PROFILE_NAME: &str = "Default";
+const DEFAULT_PAGE_NAME: &str = "Favorites";
+
+// パックの形式を変えたら上げる（新しい形式のパックは読み込まない）
+const PACK_VERSION: u32 = 1;
+const PACK_MANIFEST: &str = "manifest.json";
+const PACK_AUDIO_DIR: &str = "audio";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PadMode {
+    // 押すたびに最初から鳴らす
+    #[default]
+    OneShot,
+    // 止めるまで繰り返す
+    Loop,
+    // 鳴っている間にもう一度押すと止める
+    Toggle,
+}
+
+impl PadMode {
+    // play_audioの再生方法（loopはone_shotと同じく鳴らし直し、止めるまで繰り返す）
+    fn play_mode(self) -> PlayMode {
+        match self {
+            Self::OneShot | Self::Loop => PlayMode::Restart,
+            Self::Toggle => PlayMode::Toggle,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Pad {
+    path: String,
+    #[serde(default)]
+    label: Option<String>,
+    #[serde(default)]
+    color: Option<String>,
+    // ショートカットの表記（"Ctrl+Shift+1" など）。グローバルホットキーとは別に保存する
+    #[serde(default)]
+    hotkey: Option<String>,
+    #[serde(default)]
+    volume: Option<f32>,
+    #[serde(default)]
+    mode: PadMode,
+    // OSCで鳴らすときのアドレス（"/"で始まらなければ /soundpad/play/<値> とみなす）
+    #[serde(default)]
+    osc_address: Option<String>,
+}
+
+impl Pad {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // パッドを押したときのplay_audioのオプション（パッドの音量と動作を反映する）
+    pub fn play_options(&self) -> PlayAudioOptions {
+        PlayAudioOptions {
+            volume: self.volume,
+            mode: Some(self.mode.play_mode()),
+            looping: Some(self.mode == PadMode::Loop),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Page {
+    name: String,
+    pads: Vec<Pad>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Profile {
+    name: String,
+    pages: Vec<Page>,
+}
+
+impl Profile {
+    // OSCのアドレスとパッドの対応
+    pub fn osc_mappings(&self) -> HashMap<String, Pad> {
+        self.pages
+            .iter()
+            .flat_map(|page| &page.pads)
+            .filter_map(|pad| {
+                let address = pad.osc_address.as_deref()?.trim();
+                if address.is_empty() {
+                    return None;
+                }
+                let address = if address.starts_with('/') {
+                    address.to_string()
+                } else {
+                    format!("/soundpad/play/{}", address)
+                };
+                Some((address, pad.clone()))
+            })
+            .collect()
+    }
+
+    // どのページかに関わらず、パッドに割り当てたファイルのパスとそのパッド
+    // 同じファイルのパッドが複数あれば、先のページ・先の位置のパッドを使う
+    pub fn pads_by_path(&self) -> HashMap<String, Pad> {
+        let mut pads = HashMap::new();
+        for pad in self.pages.iter().flat_map(|page| &page.pads) {
+            pads.entry(pad.path.clone()).or_insert_with(|| pad.clone());
+        }
+        pads
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Profiles {
+    profiles: Vec<Profile>,
+}
+
+impl Profiles {
+    fn new() -> Self {
+        Self {
+            profiles: Vec::new(),
+        }
+    }
+
+    // まだ保存したことがなければ、お気に入りから最初のプロファイルを作る
+    fn load(path: &Path, app: &AppHandle) -> Result<Self, String> {
+        if !path.exists() {
+            let profiles = Self::from_favorites(app)?;
+            profiles.save(path)?;
+            return Ok(profiles);
+        }
+
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let mut file = File::create(path).map_err(|e| e.to_string())?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // カテゴリごとにページを分ける（カテゴリなしはFavoritesページ）
+    fn from_favorites(app: &AppHandle) -> Result<Self, String> {
+        let favorites = load_favorites(app)?;
+        if favorites.entries.is_empty() {
+            return Ok(Self::new());
+        }
+
+        let mut pages: Vec<Page> = Vec::new();
+        for entry in favorites.entries {
+            let page_name = entry
+                .category
+                .clone()
+                .unwrap_or_else(|| DEFAULT_PAGE_NAME.to_string());
+            let pad = Pad {
+                path: entry.path,
+                label: entry.display_name,
+                color: entry.color,
+                hotkey: None,
+                volume: entry.volume,
+                mode: PadMode::default(),
+                osc_address: None,
+            };
+            match pages.iter_mut().find(|page| page.name == page_name) {
+                Some(page) => page.pads.push(pad),
+                None => pages.push(Page {
+                    name: page_name,
+                    pads: vec![pad],
+                }),
+            }
+        }
+
+        Ok(Self {
+            profiles: vec![Profile {
+                name: DEFAULT_PROFILE_NAME.to_string(),
+                pages,
+            }],
+        })
+    }
+
+    fn find(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+}
+
+// パック内のmanifest.json（パッドのパスはパック内のパス）
+#[derive(Debug, Serialize, Deserialize)]
+struct PackManifest {
+    version: u32,
+    profile: Profile,
+}
+
+fn get_profiles_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_app_data_file_path(app, "profiles.json")
+}
+
+// 保存されているプロファイル名を保存順に返す
+#[tauri::command]
+pub fn list_profiles(app: AppHandle) -> Result<Vec<String>, String> {
+    let profiles_path = get_profiles_file_path(&app)?;
+    let profiles = Profiles::load(&profiles_path, &app)?;
+    Ok(profiles
+        .profiles
+        .into_iter()
+        .map(|profile| profile.name)
+        .collect())
+}
+
+// 保存・取り込みの前に確認する
+fn validate_profile(profile: &Profile) -> Result<(), String> {
+    if profile.name.trim().is_empty() {
+        return Err("Profile name is empty".to_string());
+    }
+    if profile.pages.iter().any(|page| page.name.trim().is_empty()) {
+        return Err("Page name is empty".to_string());
+    }
+    if profile
+        .pages
+        .iter()
+        .flat_map(|page| &page.pads)
+        .any(|pad| pad.volume.is_some_and(|volume| !(0.0..=2.0).contains(&volume)))
+    {
+        return Err("Pad volume must be between 0.0 and 2.0".to_string());
+    }
+    Ok(())
+}
+
+// 同じ名前のプロファイルがあれば置き換え、なければ末尾に追加する
+#[tauri::command]
+pub fn save_profile(profile: Profile, app: AppHandle) -> Result<(), String> {
+    validate_profile(&profile)?;
+
+    let profiles_path = get_profiles_file_path(&app)?;
+    let mut profiles = Profiles::load(&profiles_path, &app)?;
+    match profiles
+        .profiles
+        .iter_mut()
+        .find(|stored| stored.name == profile.name)
+    {
+        Some(stored) => *stored = profile.clone(),
+        None => profiles.profiles.push(profile.clone()),
+    }
+    profiles.save(&profiles_path)?;
+    osc::reload_profile(&app, &profile.name, Some(&profile));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn load_profile(name: String, app: AppHandle) -> Result<Profile, String> {
+    let profiles_path = get_profiles_file_path(&app)?;
+    let profiles = Profiles::load(&profiles_path, &app)?;
+    profiles
+        .find(&name)
+        .cloned()
+        .ok_or_else(|| format!("Profile not found: {}", name))
+}
+
+#[tauri::command]
+pub fn delete_profile(name: String, app: AppHandle) -> Result<(), String> {
+    let profiles_path = get_profiles_file_path(&app)?;
+    let mut profiles = Profiles::load(&profiles_path, &app)?;
+    let before = profiles.profiles.len();
+    profiles.profiles.retain(|profile| profile.name != name);
+    if profiles.profiles.len() == before {
+        return Err(format!("Profile not found: {}", name));
+    }
+    profiles.save(&profiles_path)?;
+    osc::reload_profile(&app, &name, None);
+    Ok(())
+}
+
+// プロファイルと、パッドが使う音声ファイルを1つのzipにまとめる
+fn write_pack(profile: &Profile, dest_zip: &Path) -> Result<(), String> {
+    let file = File::create(dest_zip).map_err(|e| e.to_string())?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // 同じファイルを使うパッドが複数あっても、パックには1つだけ入れる
+    let mut packed = profile.clone();
+    let mut entries: HashMap<String, String> = HashMap::new();
+    for pad in packed.pages.iter_mut().flat_map(|page| page.pads.iter_mut()) {
+        if let Some(entry_name) = entries.get(&pad.path) {
+            pad.path = entry_name.clone();
+            continue;
+        }
+
+        let file_name = Path::new(&pad.path)
+            .file_name()
+            .ok_or_else(|| format!("Invalid pad path: {}", pad.path))?
+            .to_string_lossy()
+            .to_string();
+        // ファイル名が同じ別のファイルと衝突しないよう、番号を付ける
+        let entry_name = format!("{}/{}-{}", PACK_AUDIO_DIR, entries.len() + 1, file_name);
+        let mut source =
+            File::open(&pad.path).map_err(|e| format!("Failed to open {}: {}", pad.path, e))?;
+        zip.start_file(entry_name.as_str(), options)
+            .map_err(|e| e.to_string())?;
+        io::copy(&mut source, &mut zip).map_err(|e| e.to_string())?;
+
+        entries.insert(pad.path.clone(), entry_name.clone());
+        pad.path = entry_name;
+    }
+
+    let manifest = PackManifest {
+        version: PACK_VERSION,
+        profile: packed,
+    };
+    let content = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    zip.start_file(PACK_MANIFEST, options)
+        .map_err(|e| e.to_string())?;
+    zip.write_all(content.as_bytes())
+        .map_err(|e| e.to_string())?;
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// 既にある名前なら "名前 (2)" のように番号を付ける
+fn unique_profile_name(profiles: &Profiles, name: &str) -> String {
+    if profiles.find(name).is_none() {
+        return name.to_string();
+    }
+    (2..)
+        .map(|n| format!("{} ({})", name, n))
+        .find(|candidate| profiles.find(candidate).is_none())
+        .unwrap()
+}
+
+// 取り込んだ音声ファイルの置き場所（app_data_dir/boards/<プロファイル名>）
+fn get_board_directory(app: &AppHandle, profile_name: &str) -> Result<PathBuf, String> {
+    let boards_dir = get_app_data_file_path(app, "boards")?;
+    let folder: String = profile_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' { c } else { '_' })
+        .collect();
+    let mut directory = boards_dir.join(&folder);
+    let mut n = 2;
+    while directory.exists() {
+        directory = boards_dir.join(format!("{} ({})", folder, n));
+        n += 1;
+    }
+    fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+    Ok(directory)
+}
+
+// パック内のパスを展開先からの相対パスにする（".."・絶対パスなど、展開先の外を指すものはNone）
+fn pack_relative_path(path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!relative.as_os_str().is_empty()).then_some(relative)
+}
+
+// パッドの音声ファイルをパック内のフォルダ構成のままdirectoryに展開し、パッドのパスを展開先に書き換える
+// 大文字・小文字だけが違うなどで展開先が重なった場合は、番号を付けて別のファイルにする
+fn extract_pads<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    profile: &mut Profile,
+    directory: &Path,
+) -> Result<(), String> {
+    let mut extracted: HashMap<String, String> = HashMap::new();
+    for pad in profile.pages.iter_mut().flat_map(|page| page.pads.iter_mut()) {
+        if let Some(path) = extracted.get(&pad.path) {
+            pad.path = path.clone();
+            continue;
+        }
+
+        let relative = pack_relative_path(&pad.path)
+            .ok_or_else(|| format!("Invalid pad path in pack: {}", pad.path))?;
+        let mut target = directory.join(relative);
+        if target.exists() {
+            target = numbered_path(&target);
+        }
+        let mut entry = archive
+            .by_name(&pad.path)
+            .map_err(|_| format!("パックに {} がありません", pad.path))?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut output = File::create(&target).map_err(|e| e.to_string())?;
+        io::copy(&mut entry, &mut output).map_err(|e| e.to_string())?;
+
+        let target = target.to_string_lossy().to_string();
+        extracted.insert(pad.path.clone(), target.clone());
+        pad.path = target;
+    }
+    Ok(())
+}
+
+// パックを展開してプロファイルとして保存する（パッドのパスは展開先に書き換える）
+// 途中で失敗した場合は、それまでに展開したファイルを消す
+fn read_pack(zip_path: &Path, app: &AppHandle) -> Result<Profile, String> {
+    let file = File::open(zip_path).map_err(|e| e.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
+
+    let manifest: PackManifest = {
+        let mut entry = archive
+            .by_name(PACK_MANIFEST)
+            .map_err(|_| "パックにmanifest.jsonがありません".to_string())?;
+        let mut content = String::new();
+        entry
+            .read_to_string(&mut content)
+            .map_err(|e| e.to_string())?;
+        serde_json::from_str(&content)
+            .map_err(|e| format!("manifest.jsonが正しくありません: {}", e))?
+    };
+    if manifest.version > PACK_VERSION {
+        return Err("このパックは新しいバージョンで作られたため読み込めません".to_string());
+    }
+    validate_profile(&manifest.profile)?;
+
+    let profiles_path = get_profiles_file_path(app)?;
+    let mut profiles = Profiles::load(&profiles_path, app)?;
+    let mut profile = manifest.profile;
+    profile.name = unique_profile_name(&profiles, &profile.name);
+
+    // 展開先のフォルダは取り込みごとに新しく作るため、失敗したらフォルダごと消せばよい
+    let directory = get_board_directory(app, &profile.name)?;
+    let result = extract_pads(&mut archive, &mut profile, &directory).and_then(|()| {
+        profiles.profiles.push(profile.clone());
+        profiles.save(&profiles_path)
+    });
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&directory);
+        return Err(e);
+    }
+
+    osc::reload_profile(app, &profile.name, Some(&profile));
+    Ok(profile)
+}
+
+// プロファイルを、使っている音声ファイルごとzipに書き出す
+#[tauri::command]
+pub async fn export_board(
+    profile: String,
+    dest_zip: String,
+    app: AppHandle,
+) -> Result<(), String> {
+    let profiles_path = get_profiles_file_path(&app)?;
+    let profiles = Profiles::load(&profiles_path, &app)?;
+    let profile = profiles
+        .find(&profile)
+        .cloned()
+        .ok_or_else(|| format!("Profile not found: {}", profile))?;
+
+    // 音声ファイルのコピーに時間がかかるため、ブロッキングスレッドで実行する
+    tauri::async_runtime::spawn_blocking(move || write_pack(&profile, Path::new(&dest_zip)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// export_boardで作ったzipを読み込み、新しいプロファイルとして追加する
+// 同じ名前のプロファイルがある場合は名前に番号を付ける
+#[tauri::command]
+pub async fn import_board(zip_path: String, app: AppHandle) -> Result<Profile, String> {
+    tauri::async_runtime::spawn_blocking(move || read_pack(Path::new(&zip_path), &app))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pad(path: &str, osc_address: Option<&str>) -> Pad {
+        Pad {
+            path: path.to_string(),
+            label: None,
+            color: None,
+            hotkey: None,
+            volume: None,
+            mode: PadMode::default(),
+            osc_address: osc_address.map(|address| address.to_string()),
+        }
+    }
+
+    fn profile(pages: Vec<Vec<Pad>>) -> Profile {
+        Profile {
+            name: "Test".to_string(),
+            pages: pages
+                .into_iter()
+                .enumerate()
+                .map(|(i, pads)| Page {
+                    name: format!("Page {}", i + 1),
+                    pads,
+                })
+                .collect(),
+        }
+    }
+
+    // 指定したエントリだけを入れたzip（manifest.jsonは入れない）
+    fn archive(entries: &[(&str, &[u8])]) -> ZipArchive<io::Cursor<Vec<u8>>> {
+        let mut zip = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        ZipArchive::new(zip.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn pack_relative_path_stays_inside() {
+        assert_eq!(
+            pack_relative_path("audio/a/x.wav"),
+            Some(PathBuf::from("audio").join("a").join("x.wav"))
+        );
+        assert_eq!(pack_relative_path("./x.wav"), Some(PathBuf::from("x.wav")));
+        assert_eq!(pack_relative_path("../x.wav"), None);
+        assert_eq!(pack_relative_path("audio/../../x.wav"), None);
+        assert_eq!(pack_relative_path("/etc/x.wav"), None);
+        assert_eq!(pack_relative_path(""), None);
+    }
+
+    #[test]
+    fn extract_pads_keeps_folders_apart() {
+        let directory = crate::test_support::empty_dir("pack-extract");
+        let mut archive = archive(&[("audio/a/x.wav", b"a"), ("audio/b/x.wav", b"b")]);
+        let mut profile = profile(vec![vec![
+            pad("audio/a/x.wav", None),
+            pad("audio/b/x.wav", None),
+            pad("audio/a/x.wav", None),
+        ]]);
+
+        extract_pads(&mut archive, &mut profile, &directory).unwrap();
+
+        let paths: Vec<&str> = profile.pages[0].pads.iter().map(|pad| pad.path()).collect();
+        assert_eq!(fs::read(paths[0]).unwrap(), b"a");
+        assert_eq!(fs::read(paths[1]).unwrap(), b"b");
+        assert_eq!(paths[0], paths[2]);
+        assert!(Path::new(paths[0]).starts_with(&directory));
+    }
+
+    #[test]
+    fn extract_pads_rejects_paths_outside() {
+        let directory = crate::test_support::empty_dir("pack-slip");
+        let mut archive = archive(&[("../x.wav", b"x")]);
+        let mut profile = profile(vec![vec![pad("../x.wav", None)]]);
+
+        assert!(extract_pads(&mut archive, &mut profile, &directory).is_err());
+        assert!(!directory.parent().unwrap().join("x.wav").exists());
+    }
+
+    #[test]
+    fn osc_mappings_expand_short_addresses() {
+        let profile = profile(vec![
+            vec![
+                pad("/sounds/a.wav", Some("/custom/a")),
+                pad("/sounds/b.wav", Some(" b ")),
+            ],
+            vec![pad("/sounds/c.wav", Some("c"))],
+        ]);
+
+        let mappings = profile.osc_mappings();
+        assert_eq!(mappings.len(), 3);
+        assert_eq!(mappings["/custom/a"].path(), "/sounds/a.wav");
+        assert_eq!(mappings["/soundpad/play/b"].path(), "/sounds/b.wav");
+        assert_eq!(mappings["/soundpad/play/c"].path(), "/sounds/c.wav");
+    }
+
+    #[test]
+    fn osc_mappings_skip_pads_without_address() {
+        let profile = profile(vec![vec![
+            pad("/sounds/a.wav", None),
+            pad("/sounds/b.wav", Some("  ")),
+        ]]);
+        assert!(profile.osc_mappings().is_empty());
+    }
+
+    #[test]
+    fn pad_settings_become_play_options() {
+        let mut looped = pad("/sounds/a.wav", None);
+        looped.volume = Some(0.5);
+        looped.mode = PadMode::Loop;
+        let options = looped.play_options();
+        assert_eq!(options.volume, Some(0.5));
+        assert_eq!(options.mode, Some(PlayMode::Restart));
+        assert_eq!(options.looping, Some(true));
+
+        let mut toggle = pad("/sounds/b.wav", None);
+        toggle.mode = PadMode::Toggle;
+        let options = toggle.play_options();
+        assert_eq!(options.volume, None);
+        assert_eq!(options.mode, Some(PlayMode::Toggle));
+        assert_eq!(options.looping, Some(false));
+
+        let options = pad("/sounds/c.wav", None).play_options();
+        assert_eq!(options.mode, Some(PlayMode::Restart));
+        assert_eq!(options.looping, Some(false));
+    }
+
+    #[test]
+    fn pads_by_path_cover_every_page() {
+        let mut first = pad("/sounds/a.wav", None);
+        first.volume = Some(0.5);
+        let profile = profile(vec![
+            vec![first],
+            vec![pad("/sounds/b.wav", Some("b")), pad("/sounds/a.wav", None)],
+        ]);
+
+        let pads = profile.pads_by_path();
+        let mut paths: Vec<&str> = pads.keys().map(|path| path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, ["/sounds/a.wav", "/sounds/b.wav"]);
+        // 同じファイルのパッドは先のページのものを使う
+        assert_eq!(pads["/sounds/a.wav"].volume, Some(0.5));
+    }
+}
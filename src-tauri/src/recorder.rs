@@ -0,0 +1,341 @@
+use rodio::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rodio::cpal::{self, FromSample, Sample, SampleFormat, SizedSample};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter};
+
+use crate::scan::{audio_file_entry, probe_audio};
+use crate::watcher::emit_created;
+
+// recording-levelを通知する間隔
+const LEVEL_INTERVAL: Duration = Duration::from_millis(100);
+
+type Writer = hound::WavWriter<BufWriter<File>>;
+
+// 入力コールバックと録音スレッドで共有する状態
+struct RecordingShared {
+    writer: Mutex<Option<Writer>>,
+    // 前回通知してからのピーク（f32のビット列。0以上の値同士ならビット列の大小と値の大小が一致する）
+    peak: AtomicU32,
+}
+
+impl RecordingShared {
+    fn write(&self, samples: impl Iterator<Item = f32>) {
+        let mut peak: f32 = 0.0;
+        if let Some(writer) = self.writer.lock().unwrap().as_mut() {
+            for sample in samples {
+                peak = peak.max(sample.abs());
+                let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                if let Err(e) = writer.write_sample(value) {
+                    eprintln!("録音データを書き込めませんでした: {}", e);
+                    return;
+                }
+            }
+        }
+        self.peak.fetch_max(peak.to_bits(), Ordering::Relaxed);
+    }
+}
+
+struct ActiveRecording {
+    path: PathBuf,
+    stop_tx: mpsc::Sender<()>,
+    // 入力ストリームを閉じてWAVを書き終えると終了する
+    handle: JoinHandle<Result<(), String>>,
+}
+
+// 録音中の状態（同時に録音するのは1つだけ）
+pub struct Recorder {
+    active: Mutex<Option<ActiveRecording>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self {
+            active: Mutex::new(None),
+        }
+    }
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    shared: Arc<RecordingShared>,
+) -> Result<cpal::Stream, String>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                shared.write(data.iter().map(|sample| sample.to_sample::<f32>()));
+            },
+            |e| eprintln!("録音エラー: {}", e),
+            None,
+        )
+        .map_err(|e| e.to_string())
+}
+
+// 録音する音
+enum CaptureSource {
+    // 入力デバイス（Noneは既定）
+    Input(Option<String>),
+    // パソコンで再生中の音（既定の出力デバイスに出ている音）
+    Loopback,
+}
+
+fn find_input_device(name: Option<&str>) -> Result<cpal::Device, String> {
+    let host = cpal::default_host();
+    match name {
+        Some(name) => host
+            .input_devices()
+            .map_err(|e| e.to_string())?
+            .find(|device| device.name().is_ok_and(|n| n == name))
+            .ok_or_else(|| format!("入力デバイスが見つかりません: {}", name)),
+        None => host
+            .default_input_device()
+            .ok_or_else(|| "入力デバイス（マイク）が見つかりません".to_string()),
+    }
+}
+
+// WASAPIでは出力デバイスから入力ストリームを作るとループバック録音になる
+#[cfg(target_os = "windows")]
+fn loopback_device() -> Result<(cpal::Device, cpal::SupportedStreamConfig), String> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or("出力デバイスが見つかりません")?;
+    let supported = device
+        .default_output_config()
+        .map_err(|e| format!("出力デバイスの設定を取得できませんでした: {}", e))?;
+    Ok((device, supported))
+}
+
+// PulseAudio（PipeWire）のモニターが入力デバイスとして見えている場合はそれを使う
+#[cfg(target_os = "linux")]
+fn loopback_device() -> Result<(cpal::Device, cpal::SupportedStreamConfig), String> {
+    let device = cpal::default_host()
+        .input_devices()
+        .map_err(|e| e.to_string())?
+        .find(|device| {
+            device
+                .name()
+                .is_ok_and(|name| name.to_lowercase().contains("monitor"))
+        })
+        .ok_or("出力のモニター入力が見つかりません（PulseAudioのモニターを有効にしてください）")?;
+    let supported = device
+        .default_input_config()
+        .map_err(|e| format!("入力デバイスの設定を取得できませんでした: {}", e))?;
+    Ok((device, supported))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn loopback_device() -> Result<(cpal::Device, cpal::SupportedStreamConfig), String> {
+    Err("この環境ではパソコンの音の録音に対応していません".to_string())
+}
+
+fn capture_device(
+    source: &CaptureSource,
+) -> Result<(cpal::Device, cpal::SupportedStreamConfig), String> {
+    match source {
+        CaptureSource::Input(name) => {
+            let device = find_input_device(name.as_deref())?;
+            let supported = device
+                .default_input_config()
+                .map_err(|e| format!("入力デバイスの設定を取得できませんでした: {}", e))?;
+            Ok((device, supported))
+        }
+        CaptureSource::Loopback => loopback_device(),
+    }
+}
+
+// 録音するデバイスを開き、16bit PCMのWAVへ書き込むストリームを開始する
+fn open_input_stream(
+    path: &Path,
+    source: &CaptureSource,
+    shared: &Arc<RecordingShared>,
+) -> Result<cpal::Stream, String> {
+    let (device, supported) = capture_device(source)?;
+    let config = supported.config();
+
+    let spec = hound::WavSpec {
+        channels: config.channels,
+        sample_rate: config.sample_rate.0,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let writer = hound::WavWriter::create(path, spec).map_err(|e| e.to_string())?;
+    *shared.writer.lock().unwrap() = Some(writer);
+
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build_stream::<f32>(&device, &config, shared.clone()),
+        SampleFormat::I16 => build_stream::<i16>(&device, &config, shared.clone()),
+        SampleFormat::U16 => build_stream::<u16>(&device, &config, shared.clone()),
+        SampleFormat::I32 => build_stream::<i32>(&device, &config, shared.clone()),
+        other => Err(format!("対応していない入力形式です: {}", other)),
+    }?;
+    stream.play().map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+// 入力ストリームは作成したスレッドで保持し、停止の合図を受けたら閉じてWAVを書き終える
+fn spawn_recording(
+    path: PathBuf,
+    source: CaptureSource,
+    app: AppHandle,
+) -> Result<ActiveRecording, String> {
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let (stop_tx, stop_rx) = mpsc::channel::<()>();
+    let thread_path = path.clone();
+
+    let handle = thread::spawn(move || {
+        let shared = Arc::new(RecordingShared {
+            writer: Mutex::new(None),
+            peak: AtomicU32::new(0),
+        });
+
+        let stream = match open_input_stream(&thread_path, &source, &shared) {
+            Ok(stream) => stream,
+            Err(e) => {
+                let _ = std::fs::remove_file(&thread_path);
+                let _ = ready_tx.send(Err(e));
+                return Ok(());
+            }
+        };
+        let _ = ready_tx.send(Ok(()));
+
+        loop {
+            match stop_rx.recv_timeout(LEVEL_INTERVAL) {
+                Err(RecvTimeoutError::Timeout) => {
+                    let peak = f32::from_bits(shared.peak.swap(0, Ordering::Relaxed));
+                    let _ = app.emit("recording-level", peak);
+                }
+                // 停止の合図、またはRecorderが破棄された
+                _ => break,
+            }
+        }
+
+        drop(stream);
+        let writer = shared.writer.lock().unwrap().take();
+        match writer {
+            Some(writer) => writer.finalize().map_err(|e| e.to_string()),
+            None => Ok(()),
+        }
+    });
+
+    ready_rx.recv().map_err(|e| e.to_string())??;
+    Ok(ActiveRecording {
+        path,
+        stop_tx,
+        handle,
+    })
+}
+
+#[tauri::command]
+pub fn list_input_devices() -> Result<Vec<String>, String> {
+    let devices = cpal::default_host()
+        .input_devices()
+        .map_err(|e| e.to_string())?;
+    Ok(devices.filter_map(|device| device.name().ok()).collect())
+}
+
+// destinationはWAVファイルのパス。フォルダを指定した場合はその中に日時入りの名前で保存する
+// 戻り値は録音先のパス
+fn start(
+    destination: &str,
+    source: CaptureSource,
+    recorder: &Recorder,
+    app: AppHandle,
+) -> Result<String, String> {
+    let mut active = recorder.active.lock().unwrap();
+    if active.is_some() {
+        return Err("Already recording".to_string());
+    }
+
+    let mut path = PathBuf::from(destination);
+    if path.is_dir() {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        path = path.join(format!("recording-{}.wav", millis));
+    } else if path
+        .parent()
+        .is_some_and(|parent| !parent.as_os_str().is_empty() && !parent.is_dir())
+    {
+        return Err("保存先のフォルダが見つかりません".to_string());
+    }
+    if path.exists() {
+        return Err(format!("同名のファイルが既にあります: {}", path.display()));
+    }
+
+    let recording = spawn_recording(path, source, app)?;
+    let path = recording.path.to_string_lossy().to_string();
+    *active = Some(recording);
+    Ok(path)
+}
+
+// deviceはlist_input_devicesで返した名前（省略時は既定の入力デバイス）
+#[tauri::command]
+pub fn start_recording(
+    destination: String,
+    device: Option<String>,
+    state: tauri::State<Recorder>,
+    app: AppHandle,
+) -> Result<String, String> {
+    start(
+        &destination,
+        CaptureSource::Input(device),
+        state.inner(),
+        app,
+    )
+}
+
+// パソコンで再生中の音を録音する（WindowsはWASAPIのループバック、LinuxはPulseAudioのモニター）
+// 止めるときはstop_recordingを使う。録音中のレベル通知もマイク録音と同じ
+#[tauri::command]
+pub fn start_loopback_capture(
+    dest_path: String,
+    state: tauri::State<Recorder>,
+    app: AppHandle,
+) -> Result<String, String> {
+    start(&dest_path, CaptureSource::Loopback, state.inner(), app)
+}
+
+// 録音を止めてWAVを保存し、そのパスを返す
+// 保存したファイルはlibrary-changedで追加として通知する（一覧にすぐ表示できるように）
+#[tauri::command]
+pub fn stop_recording(state: tauri::State<Recorder>, app: AppHandle) -> Result<String, String> {
+    let recording = state
+        .inner()
+        .active
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or("Not recording")?;
+
+    let _ = recording.stop_tx.send(());
+    recording
+        .handle
+        .join()
+        .map_err(|_| "録音スレッドが異常終了しました".to_string())??;
+
+    let path = recording.path;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    emit_created(
+        &app,
+        audio_file_entry(
+            &path,
+            directory,
+            &directory.to_string_lossy(),
+            probe_audio(&path),
+        ),
+    );
+    Ok(path.to_string_lossy().to_string())
+}
@@ -0,0 +1,474 @@
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::{Message, WebSocket};
+
+use crate::player::AudioPlayer;
+use crate::{
+    emit_now_playing, find_favorite, get_settings_file_path, load_favorites, play_audio, Settings,
+};
+
+// 停止の合図を確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// ハンドシェイクを送ってこない接続を待ち続けないようにする
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// アプリ自身の画面のOrigin（ブラウザで開いた他のページからの接続は断る）
+const APP_ORIGINS: &[&str] = &[
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+];
+
+// リモートから受け付けるメッセージ（例: {"action": "play", "path": "..."}）
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum RemoteCommand {
+    // お気に入り（パッド）の一覧を返す
+    ListSounds,
+    // お気に入りにあるファイルだけを鳴らす
+    Play { path: String },
+    Stop,
+    SetVolume { volume: f32 },
+}
+
+#[derive(Debug, Serialize)]
+struct RemoteReply {
+    ok: bool,
+    error: Option<String>,
+    // list_soundsなど、結果を返すメッセージの結果
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
+}
+
+// 次回起動時にも待ち受けを再開するための設定（settings.jsonに保存する）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RemoteSettings {
+    port: u16,
+    allow_lan: bool,
+    // 指定した場合、接続時にURLの?token=またはAuthorization: Bearerで同じ値が必要
+    token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RemoteCommandEvent {
+    peer: String,
+    message: String,
+    // 処理に失敗した場合（不正なメッセージを含む）のエラー
+    error: Option<String>,
+}
+
+struct ActiveRemote {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+    // 接続ごとのスレッド（止めるときに終了を待つ）
+    connections: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+// リモート操作の待ち受け状態（待ち受けるのは1ポートだけ）
+pub struct RemoteServer {
+    active: Mutex<Option<ActiveRemote>>,
+}
+
+impl RemoteServer {
+    pub fn new() -> Self {
+        Self {
+            active: Mutex::new(None),
+        }
+    }
+}
+
+fn dispatch(app: &AppHandle, command: RemoteCommand) -> Result<Option<serde_json::Value>, String> {
+    let player = app.state::<AudioPlayer>();
+    match command {
+        RemoteCommand::ListSounds => {
+            let favorites = load_favorites(app)?;
+            let entries = serde_json::to_value(favorites.entries).map_err(|e| e.to_string())?;
+            return Ok(Some(entries));
+        }
+        RemoteCommand::Play { path } => {
+            // 接続してきたクライアントに任意のファイルを鳴らされないよう、list_soundsで返すものに限る
+            if find_favorite(app, &path).is_none() {
+                return Err(format!("Not a favorite: {}", path));
+            }
+            // お気に入りの音量・正規化・履歴・再生中の通知は、画面から鳴らしたときと同じにする
+            play_audio(path, None, player, app.clone())?;
+        }
+        RemoteCommand::Stop => {
+            player.inner().stop_all();
+            emit_now_playing(app, player.inner());
+        }
+        RemoteCommand::SetVolume { volume } => {
+            player.inner().set_volume(volume)?;
+            emit_now_playing(app, player.inner());
+        }
+    }
+    Ok(None)
+}
+
+fn handle_message(app: &AppHandle, peer: &SocketAddr, text: &str) -> RemoteReply {
+    let result = serde_json::from_str::<RemoteCommand>(text)
+        .map_err(|e| format!("Invalid message: {}", e))
+        .and_then(|command| dispatch(app, command));
+    let (data, error) = match result {
+        Ok(data) => (data, None),
+        Err(e) => (None, Some(e)),
+    };
+
+    let _ = app.emit(
+        "remote-command",
+        RemoteCommandEvent {
+            peer: peer.to_string(),
+            message: text.to_string(),
+            error: error.clone(),
+        },
+    );
+    RemoteReply {
+        ok: error.is_none(),
+        error,
+        data,
+    }
+}
+
+// 1つの接続を処理する。不正なメッセージにはエラーを返すだけで、接続は切らない
+fn serve_connection(
+    mut socket: WebSocket<TcpStream>,
+    peer: SocketAddr,
+    app: AppHandle,
+    stop: Arc<AtomicBool>,
+) {
+    while !stop.load(Ordering::Relaxed) {
+        let text = match socket.read() {
+            Ok(Message::Text(text)) => text.to_string(),
+            Ok(Message::Close(_)) => break,
+            // ping/pongはtungsteniteが処理する。バイナリは扱わない
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                continue
+            }
+            Err(_) => return,
+        };
+
+        let reply = handle_message(&app, &peer, &text);
+        let Ok(reply) = serde_json::to_string(&reply) else {
+            continue;
+        };
+        if socket.send(Message::Text(reply.into())).is_err() {
+            return;
+        }
+    }
+    let _ = socket.close(None);
+    let _ = socket.flush();
+}
+
+// URLの?token=、またはAuthorization: Bearerのトークンが一致するか
+// URLのトークンはパーセントエンコードを戻してから比べる
+fn has_token(request: &Request, token: &str) -> bool {
+    let in_query = request.uri().query().is_some_and(|query| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.strip_prefix("token="))
+            .filter_map(percent_decode)
+            .any(|value| constant_time_eq(value.as_bytes(), token.as_bytes()))
+    });
+    let in_header = request
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|value| constant_time_eq(value.as_bytes(), token.as_bytes()));
+    in_query || in_header
+}
+
+// ブラウザは接続元のページのOriginを必ず付けるため、Originがあればアプリ自身の画面からのものに限る
+// （Originを付けないのはブラウザ以外のクライアント）
+fn has_allowed_origin(request: &Request) -> bool {
+    match request.headers().get("origin") {
+        Some(origin) => origin
+            .to_str()
+            .is_ok_and(|origin| APP_ORIGINS.contains(&origin)),
+        None => true,
+    }
+}
+
+// %XXと+（空白）を元の文字に戻す。エスケープが不正、またはUTF-8でなければNone
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes.get(i + 1..i + 3)?;
+                if !hex.iter().all(u8::is_ascii_hexdigit) {
+                    return None;
+                }
+                decoded.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+                i += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// 何文字目まで一致したかで比較の時間が変わらないよう、全てのバイトを比べる
+// （長さが違う場合はすぐにfalseを返す）
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn reject(status: StatusCode, message: &str) -> ErrorResponse {
+    let mut error = ErrorResponse::new(Some(message.to_string()));
+    *error.status_mut() = status;
+    error
+}
+
+fn accept_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    token: Option<Arc<str>>,
+    app: AppHandle,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        // ハンドシェイクはブロッキングで行い、その後は停止の合図を確認できるようタイムアウトを付ける
+        if stream.set_nonblocking(false).is_err()
+            || stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).is_err()
+        {
+            return;
+        }
+        let authorize =
+            |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+                if !has_allowed_origin(request) {
+                    return Err(reject(StatusCode::FORBIDDEN, "Origin not allowed"));
+                }
+                match &token {
+                    Some(token) if !has_token(request, token) => {
+                        Err(reject(StatusCode::UNAUTHORIZED, "Invalid token"))
+                    }
+                    _ => Ok(response),
+                }
+            };
+        let socket = match tungstenite::accept_hdr(stream, authorize) {
+            Ok(socket) => socket,
+            Err(e) => {
+                eprintln!("リモート接続を受け付けられませんでした ({}): {}", peer, e);
+                return;
+            }
+        };
+        if socket.get_ref().set_read_timeout(Some(POLL_INTERVAL)).is_err() {
+            return;
+        }
+        serve_connection(socket, peer, app, stop);
+    })
+}
+
+fn start(
+    server: &RemoteServer,
+    settings: &RemoteSettings,
+    app: AppHandle,
+) -> Result<String, String> {
+    let mut active = server.active.lock().unwrap();
+    if active.is_some() {
+        return Err("Remote control is already running".to_string());
+    }
+
+    let host = if settings.allow_lan {
+        "0.0.0.0"
+    } else {
+        "127.0.0.1"
+    };
+    let token: Option<Arc<str>> = settings.token.as_deref().map(Arc::from);
+    let listener = TcpListener::bind((host, settings.port)).map_err(|e| e.to_string())?;
+    let address = listener.local_addr().map_err(|e| e.to_string())?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let connections: Arc<Mutex<Vec<JoinHandle<()>>>> = Arc::new(Mutex::new(Vec::new()));
+    let thread_stop = stop.clone();
+    let thread_connections = connections.clone();
+    let handle = thread::spawn(move || {
+        while !thread_stop.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    let connection = accept_connection(
+                        stream,
+                        peer,
+                        token.clone(),
+                        app.clone(),
+                        thread_stop.clone(),
+                    );
+                    let mut connections = thread_connections.lock().unwrap();
+                    connections.retain(|connection| !connection.is_finished());
+                    connections.push(connection);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                Err(e) => {
+                    eprintln!("リモート接続の待ち受けに失敗しました: {}", e);
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+        }
+    });
+
+    *active = Some(ActiveRemote {
+        stop,
+        handle,
+        connections,
+    });
+    Ok(address.to_string())
+}
+
+fn save_remote_settings(app: &AppHandle, remote: Option<RemoteSettings>) -> Result<(), String> {
+    let settings_path = get_settings_file_path(app)?;
+    let mut settings = Settings::load(&settings_path).unwrap_or_default();
+    settings.remote = remote;
+    settings.save(&settings_path)
+}
+
+// 前回待ち受けたまま終了していれば、同じ設定で待ち受けを再開する
+pub fn restore_remote(app: &AppHandle) {
+    let remote = get_settings_file_path(app)
+        .and_then(|path| Settings::load(&path))
+        .ok()
+        .and_then(|settings| settings.remote);
+    if let Some(remote) = remote {
+        if let Err(e) = start(app.state::<RemoteServer>().inner(), &remote, app.clone()) {
+            eprintln!("リモート操作の待ち受けを再開できませんでした: {}", e);
+        }
+    }
+}
+
+// allow_lanがtrueなら全てのインターフェースで待ち受ける（既定ではこのPCからの接続のみ）
+// LANから接続できるようにする場合はtokenが必要
+// 待ち受けの設定は保存し、stop_remoteで止めるまでは次回起動時にも再開する
+// 戻り値は待ち受けているアドレス
+#[tauri::command]
+pub fn start_remote(
+    port: u16,
+    allow_lan: Option<bool>,
+    token: Option<String>,
+    state: tauri::State<RemoteServer>,
+    app: AppHandle,
+) -> Result<String, String> {
+    let settings = RemoteSettings {
+        port,
+        allow_lan: allow_lan.unwrap_or(false),
+        token: token
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty()),
+    };
+    if settings.allow_lan && settings.token.is_none() {
+        return Err("A token is required to allow LAN connections".to_string());
+    }
+
+    let address = start(state.inner(), &settings, app.clone())?;
+    save_remote_settings(&app, Some(settings))?;
+    Ok(address)
+}
+
+// 待ち受けを止め、接続中のクライアントも切断する（次回起動時にも再開しない）
+#[tauri::command]
+pub fn stop_remote(state: tauri::State<RemoteServer>, app: AppHandle) -> Result<(), String> {
+    let remote = state
+        .inner()
+        .active
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or("Remote control is not running")?;
+
+    remote.stop.store(true, Ordering::Relaxed);
+    remote
+        .handle
+        .join()
+        .map_err(|_| "リモート操作のスレッドが異常終了しました".to_string())?;
+    // 待ち受けのスレッドが止まった後は接続が増えないため、ここで全ての接続の終了を待てる
+    let connections = std::mem::take(&mut *remote.connections.lock().unwrap());
+    for connection in connections {
+        let _ = connection.join();
+    }
+    save_remote_settings(&app, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_from(origin: Option<&str>) -> Request {
+        let mut builder = Request::builder().uri("/");
+        if let Some(origin) = origin {
+            builder = builder.header("origin", origin);
+        }
+        builder.body(()).unwrap()
+    }
+
+    fn request(uri: &str, authorization: Option<&str>) -> Request {
+        let mut builder = Request::builder().uri(uri);
+        if let Some(value) = authorization {
+            builder = builder.header("authorization", value);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn percent_decode_restores_escapes() {
+        assert_eq!(percent_decode("a%2Bb%2fc").as_deref(), Some("a+b/c"));
+        assert_eq!(percent_decode("a+b").as_deref(), Some("a b"));
+        assert_eq!(percent_decode("%E3%81%82").as_deref(), Some("あ"));
+        assert_eq!(percent_decode("%2"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%FF"), None);
+    }
+
+    #[test]
+    fn token_in_query_is_decoded() {
+        let token = "a+b/c d";
+        assert!(has_token(&request("/?x=1&token=a%2Bb%2Fc%20d", None), token));
+        assert!(!has_token(&request("/?token=a+b/c+d", None), token));
+        assert!(!has_token(&request("/?token=a%2Bb", None), token));
+        assert!(!has_token(&request("/", None), token));
+    }
+
+    #[test]
+    fn token_in_header_must_match_exactly() {
+        assert!(has_token(&request("/", Some("Bearer secret")), "secret"));
+        assert!(!has_token(&request("/", Some("Bearer secret2")), "secret"));
+        assert!(!has_token(&request("/", Some("secret")), "secret"));
+    }
+
+    #[test]
+    fn only_app_origin_is_allowed() {
+        assert!(has_allowed_origin(&request_from(None)));
+        for origin in ["tauri://localhost", "http://tauri.localhost"] {
+            assert!(has_allowed_origin(&request_from(Some(origin))));
+        }
+        for origin in ["https://example.com", "http://127.0.0.1:8080", "null"] {
+            assert!(!has_allowed_origin(&request_from(Some(origin))));
+        }
+    }
+
+    #[test]
+    fn constant_time_eq_compares_whole_value() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+        assert!(constant_time_eq(b"", b""));
+    }
+}
@@ -0,0 +1,645 @@
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::UNIX_EPOCH;
+use symphonia::core::codecs::{CodecParameters, DecoderOptions};
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::{Hint, ProbeResult};
+use walkdir::WalkDir;
+
+use crate::{silence, AudioFile};
+
+// 1回のプローブで取得する音声ファイルの情報
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AudioProbe {
+    duration_seconds: Option<f64>,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    sample_rate: Option<u32>,
+    channels: Option<u16>,
+    bitrate_kbps: Option<u32>,
+    // 先頭の無音の長さ（play_audioのskip_silenceで初めて再生したときに求める）
+    #[serde(default)]
+    leading_silence: Option<f64>,
+    // leading_silenceを求めたか（求めても無音が見つからなければNoneのまま）
+    #[serde(default)]
+    silence_checked: bool,
+}
+
+impl AudioProbe {
+    // タグからタイトル・アーティスト・アルバムを読み取る（先に見つかった値を優先）
+    fn read_tags(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let value = tag.value.to_string().trim().to_string();
+            if value.is_empty() {
+                continue;
+            }
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                _ => continue,
+            };
+            if field.is_none() {
+                *field = Some(value);
+            }
+        }
+    }
+}
+
+// symphoniaでファイルを開き、フォーマットをプローブする
+pub fn probe_format(path: &Path) -> Result<ProbeResult, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    // 拡張子からヒントを作成
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension() {
+        if let Some(ext_str) = extension.to_str() {
+            hint.with_extension(ext_str);
+        }
+    }
+
+    // フォーマットをプローブ
+    let format_opts = FormatOptions::default();
+    let metadata_opts = MetadataOptions::default();
+
+    symphonia::default::get_probe()
+        .format(&hint, mss, &format_opts, &metadata_opts)
+        .map_err(|e| e.to_string())
+}
+
+pub fn probe_audio(path: &Path) -> AudioProbe {
+    let mut result = AudioProbe::default();
+
+    // symphoniaを使用して音声ファイルの長さを取得
+    let Ok(mut probed) = probe_format(path) else {
+        return result;
+    };
+
+    let mut format_reader = probed.format;
+
+    // コンテナ内のタグ（Vorbis comment、MP4など）を優先し、なければ先頭のID3タグなどを使う
+    if let Some(revision) = format_reader.metadata().current() {
+        result.read_tags(revision);
+    }
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            result.read_tags(revision);
+        }
+    }
+
+    // デフォルトトラックを取得
+    let Some(track) = format_reader.default_track() else {
+        return result;
+    };
+
+    // フレーム数が分かればそこから計算し、分からなければ（VBRのMP3やm4aなど）パケットを読んで求める
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    result.sample_rate = params.sample_rate;
+    result.channels = params.channels.map(|channels| channels.count() as u16);
+    result.duration_seconds = match (params.n_frames, params.sample_rate) {
+        (Some(n_frames), Some(sample_rate)) if sample_rate > 0 => {
+            Some(n_frames as f64 / sample_rate as f64)
+        }
+        _ => scan_duration(format_reader.as_mut(), track_id, &params),
+    };
+
+    // コーデックからは分からないため、ファイルサイズと長さから平均を求める（タグの分も含む）
+    let size = fs::metadata(path).map(|metadata| metadata.len()).ok();
+    result.bitrate_kbps = match (size, result.duration_seconds) {
+        (Some(size), Some(duration)) if duration > 0.0 => {
+            Some((size as f64 * 8.0 / duration / 1000.0).round() as u32)
+        }
+        _ => None,
+    };
+
+    result
+}
+
+// ファイル全体を読んで長さを求める
+// time_baseがあれば最後のパケットの終了タイムスタンプから計算し（デコードはしない）、
+// なければデコードしてフレーム数を数える
+fn scan_duration(
+    format_reader: &mut dyn FormatReader,
+    track_id: u32,
+    params: &CodecParameters,
+) -> Option<f64> {
+    if let Some(time_base) = params.time_base {
+        let mut end_ts: u64 = 0;
+        while let Ok(packet) = format_reader.next_packet() {
+            if packet.track_id() == track_id {
+                end_ts = end_ts.max(packet.ts() + packet.dur());
+            }
+        }
+        if end_ts == 0 {
+            return None;
+        }
+        let time = time_base.calc_time(end_ts);
+        return Some(time.seconds as f64 + time.frac);
+    }
+
+    let mut decoder = symphonia::default::get_codecs()
+        .make(params, &DecoderOptions::default())
+        .ok()?;
+    let mut frames: u64 = 0;
+    let mut sample_rate = params.sample_rate.unwrap_or(0);
+    while let Ok(packet) = format_reader.next_packet() {
+        if packet.track_id() != track_id {
+            continue;
+        }
+        // 壊れたパケットは数えずに読み進める
+        if let Ok(decoded) = decoder.decode(&packet) {
+            frames += decoded.frames() as u64;
+            sample_rate = decoded.spec().rate;
+        }
+    }
+
+    if frames == 0 || sample_rate == 0 {
+        return None;
+    }
+    Some(frames as f64 / sample_rate as f64)
+}
+
+pub fn get_audio_duration(path: &Path) -> Option<f64> {
+    probe_audio(path).duration_seconds
+}
+
+// ファイルの更新日時（UNIXエポックからのミリ秒）
+pub fn file_modified_ms(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64)
+}
+
+// キャッシュが使えるかの判定に使う (更新日時, サイズ)
+pub fn file_signature(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?;
+    let modified_ms = modified.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64;
+    Some((modified_ms, metadata.len()))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct CachedProbe {
+    modified_ms: u64,
+    size_bytes: u64,
+    #[serde(flatten)]
+    probe: AudioProbe,
+}
+
+// プローブ結果の形式や求め方を変えたら上げる（古いキャッシュは破棄される）
+const PROBE_CACHE_VERSION: u32 = 7;
+
+// プローブ結果のキャッシュ（パス・更新日時・サイズが一致すれば再利用）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ProbeCache {
+    #[serde(default)]
+    version: u32,
+    entries: HashMap<String, CachedProbe>,
+}
+
+impl ProbeCache {
+    fn new() -> Self {
+        Self {
+            version: PROBE_CACHE_VERSION,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let cache: Self = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+        if cache.version != PROBE_CACHE_VERSION {
+            return Ok(Self::new());
+        }
+        Ok(cache)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let mut file = File::create(path).map_err(|e| e.to_string())?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // 更新日時とサイズが一致するキャッシュがあれば返す
+    fn lookup(&self, path: &Path) -> Option<AudioProbe> {
+        let cached = self.entries.get(&*path.to_string_lossy())?;
+        if Some((cached.modified_ms, cached.size_bytes)) == file_signature(path) {
+            Some(cached.probe.clone())
+        } else {
+            None
+        }
+    }
+
+    fn insert(&mut self, path: &Path, probe: AudioProbe) -> bool {
+        match file_signature(path) {
+            Some((modified_ms, size_bytes)) => {
+                self.entries.insert(
+                    path.to_string_lossy().to_string(),
+                    CachedProbe {
+                        modified_ms,
+                        size_bytes,
+                        probe,
+                    },
+                );
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 存在しなくなったファイルのエントリを削除
+    fn prune(&mut self) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|path, _| Path::new(path).exists());
+        self.entries.len() != before
+    }
+}
+
+// duration_cache.jsonの内容はアプリ全体で1つだけ持ち、読み書きはロックを取って行う
+// 同時に走るスキャンがそれぞれ読み込み・保存して、互いの結果を上書きしないようにする
+pub struct ProbeCacheState {
+    // 最初に使うときにファイルから読み込む
+    cache: Mutex<Option<ProbeCache>>,
+    // store_leading_silenceで無音の長さを求めているファイル
+    warming: Mutex<HashSet<PathBuf>>,
+}
+
+impl ProbeCacheState {
+    pub fn new() -> Self {
+        Self {
+            cache: Mutex::new(None),
+            warming: Mutex::new(HashSet::new()),
+        }
+    }
+
+    // ロックを取ったままfを呼ぶ（cache_pathはキャッシュの保存先）
+    fn with_cache<T>(&self, cache_path: &Path, f: impl FnOnce(&mut ProbeCache) -> T) -> T {
+        let mut cache = self.cache.lock().unwrap();
+        // キャッシュが壊れていてもスキャン自体は続行する
+        let cache = cache
+            .get_or_insert_with(|| ProbeCache::load(cache_path).unwrap_or_else(|_| ProbeCache::new()));
+        f(cache)
+    }
+
+    // 更新日時とサイズが一致するキャッシュがあれば返す
+    fn lookup(&self, cache_path: &Path, path: &Path) -> Option<AudioProbe> {
+        self.with_cache(cache_path, |cache| cache.lookup(path))
+    }
+
+    // キャッシュに入れた先頭の無音の長さ（まだ求めていなければNone）
+    pub fn leading_silence(&self, cache_path: &Path, path: &Path) -> Option<Option<f64>> {
+        self.lookup(cache_path, path)
+            .filter(|probe| probe.silence_checked)
+            .map(|probe| probe.leading_silence)
+    }
+
+    // 先頭の無音の長さを求めてキャッシュに入れる（キャッシュにないファイルはプローブも行う）
+    // 同じファイルを別のスレッドで求めている間は、デコードせずにすぐ戻る
+    pub fn store_leading_silence(&self, cache_path: &Path, path: &Path) -> Result<(), String> {
+        if !self.warming.lock().unwrap().insert(path.to_path_buf()) {
+            return Ok(());
+        }
+
+        // デコード中はロックを取らず、結果を書き込むときだけ取る
+        let probe = AudioProbe {
+            leading_silence: silence::leading_silence(path, None),
+            silence_checked: true,
+            ..self.lookup(cache_path, path).unwrap_or_else(|| probe_audio(path))
+        };
+        let saved = self.with_cache(cache_path, |cache| {
+            if cache.insert(path, probe) {
+                cache.save(cache_path)
+            } else {
+                Ok(())
+            }
+        });
+        self.warming.lock().unwrap().remove(path);
+        saved
+    }
+}
+
+// 複数ファイルを並列にプローブ（結果はpathsと同じ順序）
+// max_workersを指定すると、遅いディスクで読み込みが集中しないよう同時に読むファイル数を抑える
+pub fn probe_audio_parallel(paths: &[PathBuf], max_workers: Option<usize>) -> Vec<AudioProbe> {
+    map_parallel(paths, max_workers, probe_audio)
+}
+
+// 複数ファイルにfを並列に適用する（結果はpathsと同じ順序）
+// 壊れたファイルでパニックしても、そのファイルだけ既定値にする
+pub fn map_parallel<T: Clone + Default + Send>(
+    paths: &[PathBuf],
+    max_workers: Option<usize>,
+    f: impl Fn(&Path) -> T + Sync,
+) -> Vec<T> {
+    if paths.is_empty() {
+        return Vec::new();
+    }
+
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+        .min(max_workers.unwrap_or(usize::MAX).max(1))
+        .min(paths.len());
+    let chunk_size = paths.len().div_ceil(workers);
+    let f = &f;
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let handle = scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|path| {
+                            std::panic::catch_unwind(AssertUnwindSafe(|| f(path))).unwrap_or_default()
+                        })
+                        .collect::<Vec<_>>()
+                });
+                (chunk.len(), handle)
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|(len, handle)| handle.join().unwrap_or_else(|_| vec![T::default(); len]))
+            .collect()
+    })
+}
+
+// opusとwmaはデコーダーがないため、一覧には出るが再生はできない
+pub const AUDIO_EXTENSIONS: [&str; 13] = [
+    "mp3", "wav", "ogg", "flac", "m4a", "aac", "opus", "aiff", "aif", "wma", "mp4", "m4b", "caf",
+];
+
+pub fn has_audio_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.contains(&ext.to_lowercase()))
+}
+
+// 対応している拡張子のファイルか（存在しないパスはfalse）
+pub fn is_audio_file(path: &Path, extensions: &[String]) -> bool {
+    path.is_file() && has_audio_extension(path, extensions)
+}
+
+// 一度にプローブするファイル数（この単位で進捗を通知し、キャンセルを確認する）
+const PROBE_BATCH: usize = 64;
+
+pub fn audio_file_entry(path: &Path, root: &Path, directory: &str, probe: AudioProbe) -> AudioFile {
+    AudioFile {
+        name: path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string(),
+        path: path.to_string_lossy().to_string(),
+        duration_seconds: probe.duration_seconds,
+        source_directory: directory.to_string(),
+        subdirectory: path
+            .parent()
+            .and_then(|parent| parent.strip_prefix(root).ok())
+            .map(|parent| parent.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        title: probe.title,
+        artist: probe.artist,
+        album: probe.album,
+        modified_at: file_modified_ms(path),
+        size_bytes: fs::metadata(path).map(|metadata| metadata.len()).ok(),
+        sample_rate: probe.sample_rate,
+        channels: probe.channels,
+        bitrate_kbps: probe.bitrate_kbps,
+    }
+}
+
+// スキャンの設定（アプリは設定ファイルの内容とプローブのキャッシュを、CLIは既定の値を使う）
+pub struct ScanOptions<'a> {
+    extensions: Vec<String>,
+    max_workers: Option<usize>,
+    // キャッシュとその保存先。Noneならキャッシュを読み書きせず、全てのファイルをプローブする
+    cache: Option<(&'a ProbeCacheState, PathBuf)>,
+}
+
+impl<'a> ScanOptions<'a> {
+    // 設定やキャッシュを使わずに、既定の拡張子のファイルをスキャンする
+    pub fn uncached() -> Self {
+        Self {
+            extensions: AUDIO_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+            max_workers: None,
+            cache: None,
+        }
+    }
+
+    // プローブの結果をprobe_cacheに読み書きし、cache_pathに保存する
+    pub fn cached(
+        extensions: Vec<String>,
+        max_workers: Option<usize>,
+        probe_cache: &'a ProbeCacheState,
+        cache_path: PathBuf,
+    ) -> Self {
+        Self {
+            extensions,
+            max_workers,
+            cache: Some((probe_cache, cache_path)),
+        }
+    }
+}
+
+// on_progressには (今回情報が揃ったファイル, 揃ったファイル数, 見つかったファイル数) が渡される
+// cancelがtrueになったら、その時点で情報が揃っているファイルだけを返す
+pub fn scan_directory(
+    directory: &str,
+    max_depth: Option<usize>,
+    options: &ScanOptions,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(&[AudioFile], usize, usize),
+) -> Result<Vec<AudioFile>, String> {
+    let root = Path::new(directory);
+    if !root.exists() || !root.is_dir() {
+        return Err("Invalid directory".to_string());
+    }
+
+    let mut cache_changed = false;
+    let mut audio_files = Vec::new();
+
+    let walker = match max_depth.unwrap_or(1) {
+        0 => WalkDir::new(root),
+        depth => WalkDir::new(root).max_depth(depth),
+    };
+
+    // 先にファイル一覧を集め、キャッシュにない（または古い）ものだけを後でまとめてプローブする
+    let mut found = Vec::new();
+    for entry in walker.into_iter().filter_map(|e| e.ok()) {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        if is_audio_file(entry.path(), &options.extensions) {
+            found.push(entry.into_path());
+        }
+    }
+    let mut pending = Vec::new();
+    match &options.cache {
+        Some((probe_cache, cache_path)) => probe_cache.with_cache(cache_path, |cache| {
+            for path in found {
+                match cache.lookup(&path) {
+                    Some(probe) => audio_files.push(audio_file_entry(&path, root, directory, probe)),
+                    None => pending.push(path),
+                }
+            }
+        }),
+        None => pending = found,
+    }
+
+    // キャッシュから揃ったものを先に通知する
+    let total = audio_files.len() + pending.len();
+    on_progress(&audio_files, audio_files.len(), total);
+
+    // 長さとタグを並列に取得
+    for batch in pending.chunks(PROBE_BATCH) {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        // プローブ中はロックを取らず、結果を書き込むときだけ取る
+        let probes = probe_audio_parallel(batch, options.max_workers);
+        let start = audio_files.len();
+        audio_files.extend(
+            batch
+                .iter()
+                .zip(&probes)
+                .map(|(path, probe)| audio_file_entry(path, root, directory, probe.clone())),
+        );
+        if let Some((probe_cache, cache_path)) = &options.cache {
+            probe_cache.with_cache(cache_path, |cache| {
+                for (path, probe) in batch.iter().zip(probes) {
+                    if cache.insert(path, probe) {
+                        cache_changed = true;
+                    }
+                }
+            });
+        }
+        on_progress(&audio_files[start..], audio_files.len(), total);
+    }
+
+    // キャンセルした場合も、プローブ済みの結果はキャッシュに残す
+    if let Some((probe_cache, cache_path)) = &options.cache {
+        probe_cache.with_cache(cache_path, |cache| {
+            let pruned = cache.prune();
+            if pruned || cache_changed {
+                cache.save(cache_path)
+            } else {
+                Ok(())
+            }
+        })?;
+    }
+
+    // フォルダごとにまとまるよう、フォルダ→ファイル名の順で並べる
+    audio_files.sort_by(|a, b| {
+        a.subdirectory
+            .cmp(&b.subdirectory)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(audio_files)
+}
+
+// 値がないファイルは昇順・降順どちらでも末尾に置く
+fn compare_optional<T: Copy>(
+    a: Option<T>,
+    b: Option<T>,
+    descending: bool,
+    compare: impl Fn(T, T) -> cmp::Ordering,
+) -> cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if descending => compare(b, a),
+        (Some(a), Some(b)) => compare(a, b),
+        (Some(_), None) => cmp::Ordering::Less,
+        (None, Some(_)) => cmp::Ordering::Greater,
+        (None, None) => cmp::Ordering::Equal,
+    }
+}
+
+// sort_byは "name"（フォルダ→ファイル名）、"duration"、"modified" のいずれか
+pub fn sort_audio_files(audio_files: &mut [AudioFile], sort_by: &str, descending: bool) -> Result<(), String> {
+    let by_name = |a: &AudioFile, b: &AudioFile| {
+        a.subdirectory
+            .cmp(&b.subdirectory)
+            .then_with(|| a.name.cmp(&b.name))
+    };
+
+    match sort_by {
+        "name" if descending => audio_files.sort_by(|a, b| by_name(b, a)),
+        "name" => audio_files.sort_by(by_name),
+        "duration" => audio_files.sort_by(|a, b| {
+            compare_optional(a.duration_seconds, b.duration_seconds, descending, |a, b| a.total_cmp(&b))
+                .then_with(|| by_name(a, b))
+        }),
+        "modified" => audio_files.sort_by(|a, b| {
+            compare_optional(a.modified_at, b.modified_at, descending, |a, b| a.cmp(&b))
+                .then_with(|| by_name(a, b))
+        }),
+        other => return Err(format!("Unknown sort key: {}", other)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, write_wav};
+
+    // ヘッダーの長さ（n_frames）を消し、time_baseがある場合とない場合の両方で求める
+    fn assert_scanned_duration(path: &Path, expected: f64) {
+        for keep_time_base in [true, false] {
+            let mut format = probe_format(path).unwrap().format;
+            let track = format.default_track().unwrap();
+            let track_id = track.id;
+            let mut params = track.codec_params.clone();
+            params.n_frames = None;
+            if !keep_time_base {
+                params.time_base = None;
+            }
+
+            let duration = scan_duration(format.as_mut(), track_id, &params).unwrap();
+            assert!(
+                (duration - expected).abs() < 1e-6,
+                "{}: {} (time_base: {})",
+                path.display(),
+                duration,
+                keep_time_base
+            );
+        }
+    }
+
+    #[test]
+    fn scan_duration_without_frame_count() {
+        let samples = (0..12000).map(|index| (index % 64) as i16 * 100);
+        let path = write_wav("scan-duration", 1, 8000, samples);
+        assert_scanned_duration(&path, 1.5);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn scan_duration_of_vbr_mp3_and_m4a() {
+        // 128kbpsと64kbpsのフレームが混ざり、Xingヘッダーのない（長さを書いていない）MP3
+        // 1152フレーム × 20
+        assert_scanned_duration(&fixture("silence-vbr.mp3"), 23040.0 / 44100.0);
+        // 1024フレーム × 20
+        assert_scanned_duration(&fixture("silence.m4a"), 20480.0 / 44100.0);
+    }
+}
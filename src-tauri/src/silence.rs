@@ -0,0 +1,196 @@
+use rodio::Source;
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::decoder::SymphoniaSource;
+
+// threshold_dbを省略した場合の無音とみなす音量（dBFS）
+const DEFAULT_THRESHOLD_DB: f64 = -50.0;
+// leading_silenceで無音を探す範囲（これより長く無音が続くファイルは飛ばさない）
+const MAX_LEADING_SILENCE_SECONDS: f64 = 10.0;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SilenceInfo {
+    // 先頭・末尾の無音の長さ（全体が無音の場合は先頭に全体の長さが入る）
+    leading_seconds: f64,
+    trailing_seconds: f64,
+    duration_seconds: f64,
+}
+
+// 書き出し時に無音を除いた区間（元のファイルでの開始・終了秒）
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct TrimBounds {
+    start_seconds: f64,
+    end_seconds: f64,
+}
+
+impl TrimBounds {
+    pub fn range(&self) -> (f64, f64) {
+        (self.start_seconds, self.end_seconds)
+    }
+}
+
+fn threshold_amplitude(threshold_db: Option<f64>) -> f32 {
+    10f64.powf(threshold_db.unwrap_or(DEFAULT_THRESHOLD_DB) / 20.0) as f32
+}
+
+// フレーム（全チャンネルのサンプル）ごとに、しきい値を超える音があるかを順に返す
+fn frames_loud<S: Source>(mut source: S, threshold: f32) -> impl Iterator<Item = bool> {
+    let channels = source.channels().max(1) as usize;
+    std::iter::from_fn(move || {
+        let mut loud = false;
+        for _ in 0..channels {
+            loud |= source.next()?.abs() > threshold;
+        }
+        Some(loud)
+    })
+}
+
+// 先頭の無音の長さ（秒）。最初のMAX_LEADING_SILENCE_SECONDS秒に音がない、
+// またはデコードできない場合はNone
+// 最初の音が見つかった時点でデコードをやめるため、スキャン中に全てのファイルで求めても軽い
+pub fn leading_silence(path: &Path, threshold_db: Option<f64>) -> Option<f64> {
+    let source = SymphoniaSource::open(path).ok()?;
+    let sample_rate = source.sample_rate() as f64;
+    let max_frames = (MAX_LEADING_SILENCE_SECONDS * sample_rate) as usize;
+    let silent_frames = frames_loud(source, threshold_amplitude(threshold_db))
+        .take(max_frames)
+        .position(|loud| loud)?;
+    Some(silent_frames as f64 / sample_rate)
+}
+
+fn measure_silence(path: &Path, threshold_db: Option<f64>) -> Result<SilenceInfo, String> {
+    Ok(measure_source(SymphoniaSource::open(path)?, threshold_db))
+}
+
+fn measure_source<S: Source>(source: S, threshold_db: Option<f64>) -> SilenceInfo {
+    let sample_rate = source.sample_rate() as f64;
+
+    let mut total_frames: u64 = 0;
+    let mut first_loud = None;
+    let mut last_loud = None;
+    for (frame, loud) in frames_loud(source, threshold_amplitude(threshold_db)).enumerate() {
+        total_frames += 1;
+        if loud {
+            first_loud.get_or_insert(frame as u64);
+            last_loud = Some(frame as u64);
+        }
+    }
+
+    let seconds = |frames: u64| frames as f64 / sample_rate;
+    let (leading, trailing) = match (first_loud, last_loud) {
+        (Some(first), Some(last)) => (first, total_frames - last - 1),
+        _ => (total_frames, 0),
+    };
+    SilenceInfo {
+        leading_seconds: seconds(leading),
+        trailing_seconds: seconds(trailing),
+        duration_seconds: seconds(total_frames),
+    }
+}
+
+// range（開始・終了秒、省略時はファイル全体）から先頭・末尾の無音を除いた区間を返す
+// しきい値はleading_silenceと同じ既定値。区間全体が無音の場合はエラー
+pub fn trim_bounds(path: &Path, range: Option<(f64, f64)>) -> Result<TrimBounds, String> {
+    let mut source = SymphoniaSource::open(path)?;
+    let info = match range {
+        Some((start, end)) => {
+            source
+                .try_seek(Duration::from_secs_f64(start))
+                .map_err(|e| format!("シークできませんでした: {}", e))?;
+            let clip = source.take_duration(Duration::from_secs_f64(end - start));
+            measure_source(clip, None)
+        }
+        None => measure_source(source, None),
+    };
+
+    let offset = range.map_or(0.0, |(start, _)| start);
+    let bounds = TrimBounds {
+        start_seconds: offset + info.leading_seconds,
+        end_seconds: offset + info.duration_seconds - info.trailing_seconds,
+    };
+    if bounds.start_seconds >= bounds.end_seconds {
+        return Err("音のある部分が見つかりませんでした".to_string());
+    }
+    Ok(bounds)
+}
+
+// ファイル全体をデコードして、先頭・末尾の無音の長さを返す
+// threshold_db（dBFS、省略時は-50）以下の音が続く部分を無音とみなす
+#[tauri::command]
+pub async fn analyze_silence(
+    path: String,
+    threshold_db: Option<f64>,
+) -> Result<SilenceInfo, String> {
+    if threshold_db.is_some_and(|db| db.is_nan() || db > 0.0) {
+        return Err("threshold_db must be 0 or below".to_string());
+    }
+
+    tauri::async_runtime::spawn_blocking(move || measure_silence(Path::new(&path), threshold_db))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_wav;
+    use std::path::PathBuf;
+
+    // 8kHzモノラルで、無音・音・無音をそれぞれのフレーム数だけ並べる
+    fn silence_around_tone(name: &str, leading: usize, tone: usize, trailing: usize) -> PathBuf {
+        let samples = std::iter::repeat_n(0, leading)
+            .chain(std::iter::repeat_n(8000, tone))
+            .chain(std::iter::repeat_n(0, trailing));
+        write_wav(name, 1, 8000, samples)
+    }
+
+    #[test]
+    fn measures_leading_and_trailing_silence() {
+        let path = silence_around_tone("silence-measure", 4000, 8000, 2000);
+
+        let info = measure_silence(&path, None).unwrap();
+        assert_eq!(info.leading_seconds, 0.5);
+        assert_eq!(info.trailing_seconds, 0.25);
+        assert_eq!(info.duration_seconds, 1.75);
+        assert_eq!(leading_silence(&path, None), Some(0.5));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn silent_file_is_all_leading_silence() {
+        let path = silence_around_tone("silence-empty", 800, 0, 0);
+
+        let info = measure_silence(&path, None).unwrap();
+        assert_eq!(info.leading_seconds, 0.1);
+        assert_eq!(info.trailing_seconds, 0.0);
+        assert_eq!(leading_silence(&path, None), None);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn trim_bounds_skip_silence_at_both_ends() {
+        let path = silence_around_tone("silence-trim", 4000, 8000, 2000);
+
+        let whole = trim_bounds(&path, None).unwrap();
+        assert_eq!(whole.range(), (0.5, 1.5));
+        let clip = trim_bounds(&path, Some((1.0, 1.75))).unwrap();
+        assert_eq!(clip.range(), (1.0, 1.5));
+        assert!(trim_bounds(&path, Some((1.5, 1.75))).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn leading_silence_search_is_bounded() {
+        let path = silence_around_tone("silence-long", 8000 * 11, 800, 0);
+
+        assert_eq!(leading_silence(&path, None), None);
+        assert_eq!(measure_silence(&path, None).unwrap().leading_seconds, 11.0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+use rusqlite::{params, Connection};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+use crate::library::open_library;
+use crate::{get_app_data_file_path, normalize_search_text, scan_audio_files, AudioFile};
+
+// 以前の保存形式（tags.json）。library.dbへ取り込むときだけ読む
+#[derive(Debug, Deserialize)]
+struct TagStore {
+    tags: HashMap<String, Vec<String>>,
+}
+
+fn get_tags_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_app_data_file_path(app, "tags.json")
+}
+
+// tags.jsonのタグ（ファイルがなければ空）
+pub fn load_json_tags(app: &AppHandle) -> Result<HashMap<String, Vec<String>>, String> {
+    let tags_path = get_tags_file_path(app)?;
+    if !tags_path.exists() {
+        return Ok(HashMap::new());
+    }
+
+    let content = fs::read_to_string(&tags_path).map_err(|e| e.to_string())?;
+    let store: TagStore = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    Ok(store.tags)
+}
+
+// ファイルに付いているタグ（付けた順）
+fn file_tags(connection: &Connection, path: &str) -> Result<Vec<String>, String> {
+    let mut statement = connection
+        .prepare("SELECT tag FROM tags WHERE path = ?1 ORDER BY rowid")
+        .map_err(|e| e.to_string())?;
+    let tags = statement
+        .query_map(params![path], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<String>>>()
+        .map_err(|e| e.to_string())?;
+    Ok(tags)
+}
+
+// 全てのファイルのタグ（パスがキー）
+fn all_tags(connection: &Connection) -> Result<HashMap<String, Vec<String>>, String> {
+    let mut statement = connection
+        .prepare("SELECT path, tag FROM tags ORDER BY rowid")
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?;
+
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    for row in rows {
+        let (path, tag) = row.map_err(|e| e.to_string())?;
+        tags.entry(path).or_default().push(tag);
+    }
+    Ok(tags)
+}
+
+// 大文字・小文字や前後の空白が違うだけのタグは同じものとして扱う
+fn same_tag(a: &str, b: &str) -> bool {
+    normalize_search_text(a) == normalize_search_text(b)
+}
+
+// ファイルの移動・削除をタグに反映（新しいパスがNoneの場合は削除）
+pub fn update_tag_paths(
+    app: &AppHandle,
+    changes: &[(String, Option<String>)],
+) -> Result<(), String> {
+    let mut connection = open_library(app)?;
+    let tx = connection.transaction().map_err(|e| e.to_string())?;
+
+    for (old_path, new_path) in changes {
+        if file_tags(&tx, old_path)?.is_empty() {
+            continue;
+        }
+        // 移動先に付いていたタグは、移動してきたファイルのタグで置き換える
+        if let Some(new_path) = new_path {
+            tx.execute("DELETE FROM tags WHERE path = ?1", params![new_path])
+                .map_err(|e| e.to_string())?;
+            tx.execute(
+                "UPDATE tags SET path = ?2 WHERE path = ?1",
+                params![old_path, new_path],
+            )
+            .map_err(|e| e.to_string())?;
+        } else {
+            tx.execute("DELETE FROM tags WHERE path = ?1", params![old_path])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    tx.commit().map_err(|e| e.to_string())
+}
+
+// textにwordがどれだけ合っているか（合わなければNone）
+// 先頭・単語の先頭で一致するものを優先し、文字が飛び飛びに含まれるだけの場合は間が空くほど低くする
+fn fuzzy_score(text: &str, word: &str) -> Option<u32> {
+    if let Some(position) = text.find(word) {
+        let at_word_start = text[..position]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric());
+        return Some(match (position, at_word_start) {
+            (0, _) => 300,
+            (_, true) => 200,
+            _ => 100,
+        });
+    }
+
+    let mut chars = text.chars();
+    let mut gaps: u32 = 0;
+    for c in word.chars() {
+        loop {
+            match chars.next() {
+                Some(next) if next == c => break,
+                Some(_) => gaps += 1,
+                None => return None,
+            }
+        }
+    }
+    Some(50u32.saturating_sub(gaps).max(1))
+}
+
+// 全ての単語がどこかの項目に合ったファイルだけ点数を返す（各単語の最高点の合計）
+fn score_file(file: &AudioFile, tags: &[String], words: &[&str]) -> Option<u32> {
+    // 項目ごとの重み（ファイル名とタイトルを優先する）
+    let mut fields: Vec<(String, u32)> = vec![(normalize_search_text(&file.name), 3)];
+    for (value, weight) in [(&file.title, 3), (&file.artist, 2), (&file.album, 1)] {
+        if let Some(value) = value {
+            fields.push((normalize_search_text(value), weight));
+        }
+    }
+    for tag in tags {
+        fields.push((normalize_search_text(tag), 2));
+    }
+
+    words.iter().try_fold(0, |total, word| {
+        let best = fields
+            .iter()
+            .filter_map(|(text, weight)| fuzzy_score(text, word).map(|score| score * weight))
+            .max()?;
+        Some(total + best)
+    })
+}
+
+#[tauri::command]
+pub fn add_tag(path: String, tag: String, app: AppHandle) -> Result<(), String> {
+    let tag = tag.trim().to_string();
+    if tag.is_empty() {
+        return Err("Tag is empty".to_string());
+    }
+
+    let connection = open_library(&app)?;
+    let tags = file_tags(&connection, &path)?;
+    if tags.iter().any(|existing| same_tag(existing, &tag)) {
+        return Ok(());
+    }
+    connection
+        .execute(
+            "INSERT INTO tags (path, tag) VALUES (?1, ?2)",
+            params![path, tag],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn remove_tag(path: String, tag: String, app: AppHandle) -> Result<(), String> {
+    let connection = open_library(&app)?;
+    for existing in file_tags(&connection, &path)? {
+        if same_tag(&existing, &tag) {
+            connection
+                .execute(
+                    "DELETE FROM tags WHERE path = ?1 AND tag = ?2",
+                    params![path, existing],
+                )
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_tags(path: String, app: AppHandle) -> Result<Vec<String>, String> {
+    let connection = open_library(&app)?;
+    file_tags(&connection, &path)
+}
+
+// 使われている全てのタグを名前順に返す
+#[tauri::command]
+pub fn list_tags(app: AppHandle) -> Result<Vec<String>, String> {
+    let connection = open_library(&app)?;
+    let mut statement = connection
+        .prepare("SELECT DISTINCT tag FROM tags ORDER BY tag")
+        .map_err(|e| e.to_string())?;
+    let tags = statement
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<String>>>()
+        .map_err(|e| e.to_string())?;
+    Ok(tags)
+}
+
+// ファイル名・タイトル・アーティスト・アルバム・タグをあいまい検索し、合っている順に返す
+// tagsを指定した場合は、その全てのタグが付いたファイルだけを対象にする
+// queryが空ならtagsで絞り込むだけで、並びはget_audio_filesと同じ
+#[tauri::command]
+pub async fn search_library(
+    directory: String,
+    query: String,
+    tags: Option<Vec<String>>,
+    max_depth: Option<usize>,
+    app: AppHandle,
+) -> Result<Vec<AudioFile>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let audio_files = scan_audio_files(&directory, max_depth, &app)?;
+        let tag_map = all_tags(&open_library(&app)?)?;
+        let required = tags.unwrap_or_default();
+        let query = normalize_search_text(&query);
+        let words: Vec<&str> = query.split(' ').filter(|word| !word.is_empty()).collect();
+
+        let mut results: Vec<(u32, AudioFile)> = audio_files
+            .into_iter()
+            .filter_map(|file| {
+                let file_tags = tag_map
+                    .get(&file.path)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                let has_all = required
+                    .iter()
+                    .all(|tag| file_tags.iter().any(|existing| same_tag(existing, tag)));
+                if !has_all {
+                    return None;
+                }
+                let score = score_file(&file, file_tags, &words)?;
+                Some((score, file))
+            })
+            .collect();
+
+        // 点数が同じものはスキャン順（フォルダ→ファイル名）のまま
+        results.sort_by(|a, b| b.0.cmp(&a.0));
+        Ok(results.into_iter().map(|(_, file)| file).collect())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_score_prefers_earlier_and_closer_matches() {
+        // 先頭 > 単語の先頭 > 途中 > 飛び飛びの順に高い
+        assert_eq!(fuzzy_score("kick drum", "kick"), Some(300));
+        assert_eq!(fuzzy_score("big kick", "kick"), Some(200));
+        assert_eq!(fuzzy_score("sidekick", "kick"), Some(100));
+
+        // 文字の間の余分な文字が多いほど低い
+        assert_eq!(fuzzy_score("k_i_c_k", "kick"), Some(47));
+        assert_eq!(fuzzy_score("snare", "kick"), None);
+        assert_eq!(fuzzy_score(&"x".repeat(60), "xy"), None);
+        assert_eq!(fuzzy_score(&format!("a{}b", "-".repeat(60)), "ab"), Some(1));
+    }
+}
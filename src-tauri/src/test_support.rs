@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+// テスト用の16bit WAVを一時ディレクトリに書き出す（samplesは全チャンネル分を交互に並べたもの）
+pub fn write_wav(
+    name: &str,
+    channels: u16,
+    sample_rate: u32,
+    samples: impl IntoIterator<Item = i16>,
+) -> PathBuf {
+    let file_name = format!("sound-pad-{}-{}.wav", std::process::id(), name);
+    let path = std::env::temp_dir().join(file_name);
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for sample in samples {
+        writer.write_sample(sample).unwrap();
+    }
+    writer.finalize().unwrap();
+    path
+}
+
+// テスト用の空のフォルダを一時ディレクトリに作る（前回の実行で残っていれば作り直す）
+pub fn empty_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("sound-pad-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+// src-tauri/tests/fixtures にある、テストでは作れない形式（AAC・MP3など）のファイル
+pub fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
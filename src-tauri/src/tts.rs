@@ -0,0 +1,234 @@
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+
+use crate::player::AudioPlayer;
+use crate::scan::{audio_file_entry, probe_audio};
+use crate::watcher::emit_created;
+use crate::{get_app_data_file_path, play_polyphonic};
+
+const COMMAND_NOT_FOUND: &str = "音声合成のコマンドが見つかりません";
+// speakで残す音声の数（超えた分は最後に使ったのが古いものから消す）
+const MAX_CACHED_SPEECH: usize = 200;
+
+// コマンドを実行し、inputがあれば標準入力に渡す。失敗した場合は標準エラーの内容を返す
+fn run(command: &mut Command, input: Option<&str>) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => format!("{}: {}", COMMAND_NOT_FOUND, program),
+            _ => e.to_string(),
+        })?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Some(input) = input {
+            stdin
+                .write_all(input.as_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "音声合成に失敗しました: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+// System.Speechで合成する。文字列は引用符のエスケープを避けるため環境変数で渡す
+#[cfg(target_os = "windows")]
+fn render_speech(text: &str, voice: Option<&str>, dest: &Path) -> Result<(), String> {
+    let script = "Add-Type -AssemblyName System.Speech; \
+        $s = New-Object System.Speech.Synthesis.SpeechSynthesizer; \
+        if ($env:SOUND_PAD_TTS_VOICE) { $s.SelectVoice($env:SOUND_PAD_TTS_VOICE) }; \
+        $s.SetOutputToWaveFile($env:SOUND_PAD_TTS_DEST); \
+        $s.Speak($env:SOUND_PAD_TTS_TEXT); \
+        $s.Dispose()";
+    run(
+        Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", script])
+            .env("SOUND_PAD_TTS_TEXT", text)
+            .env("SOUND_PAD_TTS_VOICE", voice.unwrap_or(""))
+            .env("SOUND_PAD_TTS_DEST", dest),
+        None,
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn render_speech(text: &str, voice: Option<&str>, dest: &Path) -> Result<(), String> {
+    let mut command = Command::new("say");
+    command.arg("-o").arg(dest).arg("--data-format=LEI16@22050");
+    if let Some(voice) = voice {
+        command.args(["-v", voice]);
+    }
+    // 文字列は標準入力から読ませる
+    command.args(["-f", "-"]);
+    run(&mut command, Some(text))
+}
+
+// espeak-ng（なければespeak）で合成する
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn render_speech(text: &str, voice: Option<&str>, dest: &Path) -> Result<(), String> {
+    let espeak = |program: &str| {
+        let mut command = Command::new(program);
+        command.arg("-w").arg(dest);
+        if let Some(voice) = voice {
+            command.args(["-v", voice]);
+        }
+        command.arg("--stdin");
+        run(&mut command, Some(text))
+    };
+    match espeak("espeak-ng") {
+        Err(e) if e.starts_with(COMMAND_NOT_FOUND) => espeak("espeak"),
+        result => result,
+    }
+}
+
+fn validate(text: &str, voice: &Option<String>) -> Result<Option<String>, String> {
+    if text.trim().is_empty() {
+        return Err("Text is empty".to_string());
+    }
+    Ok(voice
+        .as_ref()
+        .map(|voice| voice.trim().to_string())
+        .filter(|voice| !voice.is_empty()))
+}
+
+// 読み上げた音声をWAVで保存し、そのパスを返す（パッドに割り当てられるよう一覧にも追加を通知する）
+// dest_pathにフォルダを指定した場合はその中に日時入りの名前で保存する
+// voiceはOSの音声の名前（省略時は既定の音声）
+#[tauri::command]
+pub async fn generate_tts(
+    text: String,
+    voice: Option<String>,
+    dest_path: String,
+    app: AppHandle,
+) -> Result<String, String> {
+    let voice = validate(&text, &voice)?;
+    let mut path = PathBuf::from(&dest_path);
+    if path.is_dir() {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        path = path.join(format!("tts-{}.wav", millis));
+    } else if path
+        .parent()
+        .is_some_and(|parent| !parent.as_os_str().is_empty() && !parent.is_dir())
+    {
+        return Err("保存先のフォルダが見つかりません".to_string());
+    }
+    if path.exists() {
+        return Err(format!("同名のファイルが既にあります: {}", path.display()));
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        render_speech(&text, voice.as_deref(), &path).inspect_err(|_| {
+            let _ = fs::remove_file(&path);
+        })?;
+
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        emit_created(
+            &app,
+            audio_file_entry(
+                &path,
+                directory,
+                &directory.to_string_lossy(),
+                probe_audio(&path),
+            ),
+        );
+        Ok(path.to_string_lossy().to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// 更新日時を最後に使った日時として、新しいものからkeep個を残して消す
+fn prune_cache(cache_dir: &Path, keep: usize) -> Result<(), String> {
+    let mut files: Vec<(SystemTime, PathBuf)> = fs::read_dir(cache_dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wav"))
+        .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .collect();
+    files.sort_by(|a, b| b.0.cmp(&a.0));
+    for (_, path) in files.into_iter().skip(keep) {
+        let _ = fs::remove_file(path);
+    }
+    Ok(())
+}
+
+// その場で読み上げる（他の音と重ねて再生し、停止用のIDを返す）
+// 合成した音声はapp_data_dirのttsフォルダに残し、同じ文と音声なら作り直さない
+// 残すのは最近使ったMAX_CACHED_SPEECH個まで
+#[tauri::command]
+pub async fn speak(
+    text: String,
+    voice: Option<String>,
+    state: tauri::State<'_, AudioPlayer>,
+    app: AppHandle,
+) -> Result<String, String> {
+    let voice = validate(&text, &voice)?;
+    let cache_dir = get_app_data_file_path(&app, "tts")?;
+    if !cache_dir.exists() {
+        fs::create_dir_all(&cache_dir).map_err(|e| e.to_string())?;
+    }
+    let key = format!("{}\n{}", voice.as_deref().unwrap_or(""), text);
+    let path = cache_dir.join(format!("{}.wav", blake3::hash(key.as_bytes()).to_hex()));
+
+    if path.exists() {
+        // 使った順に消せるよう、更新日時を今にする
+        let _ = File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+    } else {
+        let render_path = path.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            render_speech(&text, voice.as_deref(), &render_path).inspect_err(|_| {
+                let _ = fs::remove_file(&render_path);
+            })?;
+            prune_cache(&cache_dir, MAX_CACHED_SPEECH)
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+    }
+
+    play_polyphonic(path.to_string_lossy().to_string(), state, app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::empty_dir;
+    use std::time::Duration;
+
+    #[test]
+    fn prune_cache_keeps_recently_used() {
+        let dir = empty_dir("tts-prune");
+        for (name, age) in [("old", 30), ("newest", 0), ("recent", 10)] {
+            let file = File::create(dir.join(format!("{}.wav", name))).unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(age))
+                .unwrap();
+        }
+
+        prune_cache(&dir, 2).unwrap();
+
+        assert!(!dir.join("old.wav").exists());
+        assert!(dir.join("recent.wav").exists());
+        assert!(dir.join("newest.wav").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
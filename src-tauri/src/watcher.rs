@@ -0,0 +1,228 @@
+use notify::event::ModifyKind;
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+use crate::scan::{audio_file_entry, has_audio_extension, is_audio_file, probe_audio_parallel};
+use crate::{audio_extensions, scan_concurrency, AudioFile};
+
+// 連続したイベントをまとめる待ち時間
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DirectoryChange {
+    kind: String,
+    path: String,
+}
+
+// directory-changedのうち音声ファイルの変更だけを、一覧にそのまま使える形で通知する
+#[derive(Debug, Serialize, Clone)]
+pub struct LibraryChange {
+    kind: String,
+    path: String,
+    // 追加・名前変更後のファイルの情報（削除や名前変更前のパスではNone）
+    file: Option<AudioFile>,
+}
+
+struct ActiveWatch {
+    directory: String,
+    recursive: bool,
+    // ドロップすると監視が止まり、通知スレッドも終了する
+    _watcher: RecommendedWatcher,
+}
+
+// 監視中のフォルダ（同時に監視するのは1つだけ）
+pub struct DirectoryWatcher {
+    active: Mutex<Option<ActiveWatch>>,
+}
+
+impl DirectoryWatcher {
+    pub fn new() -> Self {
+        Self {
+            active: Mutex::new(None),
+        }
+    }
+
+    // フォルダの監視を開始（既に監視中のフォルダがあれば置き換える）
+    pub fn watch(&self, directory: String, recursive: bool, app: AppHandle) -> Result<(), String> {
+        let path = Path::new(&directory);
+        if !path.is_dir() {
+            return Err("Invalid directory".to_string());
+        }
+
+        let mut active = self.active.lock().unwrap();
+        // 先に古い監視を止めてスレッドを終了させる
+        *active = None;
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher =
+            RecommendedWatcher::new(tx, Config::default()).map_err(|e| e.to_string())?;
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        watcher.watch(path, mode).map_err(|e| e.to_string())?;
+
+        spawn_debouncer(rx, directory.clone(), app);
+
+        *active = Some(ActiveWatch {
+            directory,
+            recursive,
+            _watcher: watcher,
+        });
+        Ok(())
+    }
+
+    // 開いたフォルダを監視する（同じフォルダを同じ方法で監視中なら何もしない）
+    pub fn ensure_watching(
+        &self,
+        directory: &str,
+        recursive: bool,
+        app: &AppHandle,
+    ) -> Result<(), String> {
+        let watching = self
+            .active
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|watch| watch.directory == directory && watch.recursive == recursive);
+        if watching {
+            return Ok(());
+        }
+        self.watch(directory.to_string(), recursive, app.clone())
+    }
+}
+
+fn classify(event: Event) -> Vec<DirectoryChange> {
+    let kind = match event.kind {
+        EventKind::Create(_) => "created",
+        EventKind::Remove(_) => "removed",
+        EventKind::Modify(ModifyKind::Name(_)) => "renamed",
+        _ => return Vec::new(),
+    };
+
+    event
+        .paths
+        .into_iter()
+        .map(|path| DirectoryChange {
+            kind: kind.to_string(),
+            path: path.to_string_lossy().to_string(),
+        })
+        .collect()
+}
+
+// 音声ファイルの変更だけを取り出し、存在するファイルは改めてプローブする
+fn library_changes(
+    changes: &[DirectoryChange],
+    directory: &str,
+    app: &AppHandle,
+) -> Vec<LibraryChange> {
+    let root = Path::new(directory);
+    let extensions = audio_extensions(app);
+    let (existing, missing): (Vec<_>, Vec<_>) = changes
+        .iter()
+        .partition(|change| is_audio_file(Path::new(&change.path), &extensions));
+
+    let paths: Vec<PathBuf> = existing.iter().map(|change| PathBuf::from(&change.path)).collect();
+    let probes = probe_audio_parallel(&paths, scan_concurrency(app));
+    let mut library = Vec::new();
+    for ((change, path), probe) in existing.into_iter().zip(&paths).zip(probes) {
+        library.push(LibraryChange {
+            kind: change.kind.clone(),
+            path: change.path.clone(),
+            file: Some(audio_file_entry(path, root, directory, probe)),
+        });
+    }
+    // 削除されたファイルと名前変更前のパスは、拡張子だけで判断する
+    for change in missing
+        .into_iter()
+        .filter(|change| has_audio_extension(Path::new(&change.path), &extensions))
+    {
+        library.push(LibraryChange {
+            kind: change.kind.clone(),
+            path: change.path.clone(),
+            file: None,
+        });
+    }
+    library
+}
+
+// 監視とは別に、アプリ自身が書き終えたファイルをlibrary-changedで追加として通知する
+pub fn emit_created(app: &AppHandle, file: AudioFile) {
+    let change = LibraryChange {
+        kind: "created".to_string(),
+        path: file.path.clone(),
+        file: Some(file),
+    };
+    let _ = app.emit("library-changed", vec![change]);
+}
+
+// 変更をまとめて、一定時間イベントが途切れたら1回だけ通知する
+fn spawn_debouncer(rx: mpsc::Receiver<notify::Result<Event>>, directory: String, app: AppHandle) {
+    thread::spawn(move || {
+        let mut pending: Vec<DirectoryChange> = Vec::new();
+
+        loop {
+            let received = if pending.is_empty() {
+                rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                rx.recv_timeout(DEBOUNCE)
+            };
+
+            match received {
+                Ok(Ok(event)) => {
+                    for change in classify(event) {
+                        if !pending.contains(&change) {
+                            pending.push(change);
+                        }
+                    }
+                }
+                Ok(Err(e)) => eprintln!("フォルダ監視エラー: {}", e),
+                Err(RecvTimeoutError::Timeout) => {
+                    let changes = std::mem::take(&mut pending);
+                    let library = library_changes(&changes, &directory, &app);
+                    let _ = app.emit("directory-changed", changes);
+                    if !library.is_empty() {
+                        let _ = app.emit("library-changed", library);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn watch_directory(
+    directory: String,
+    recursive: Option<bool>,
+    state: tauri::State<DirectoryWatcher>,
+    app: AppHandle,
+) -> Result<(), String> {
+    state
+        .inner()
+        .watch(directory, recursive.unwrap_or(false), app)
+}
+
+#[tauri::command]
+pub fn unwatch_directory(state: tauri::State<DirectoryWatcher>) -> Result<(), String> {
+    *state.inner().active.lock().unwrap() = None;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_watched_directory(state: tauri::State<DirectoryWatcher>) -> Option<String> {
+    state
+        .inner()
+        .active
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|watch| watch.directory.clone())
+}
@@ -0,0 +1,128 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+use crate::decoder::SymphoniaSource;
+use crate::get_app_data_file_path;
+use crate::scan::{file_signature, get_audio_duration};
+
+// 区間数の上限（表示に使う幅より十分大きく、巨大なバッファを確保しない値）
+const MAX_BUCKETS: usize = 10_000;
+
+// 区間ごとの最小値・最大値（全チャンネルのサンプルから求める）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Waveform {
+    min: Vec<f32>,
+    max: Vec<f32>,
+}
+
+// 波形は1ファイル・1区間数ごとに別のファイルに保存する（更新日時とサイズが一致すれば再利用）
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedWaveform {
+    modified_ms: u64,
+    size_bytes: u64,
+    #[serde(flatten)]
+    waveform: Waveform,
+}
+
+fn get_waveform_cache_path(
+    app: &AppHandle,
+    path: &Path,
+    num_buckets: usize,
+) -> Result<PathBuf, String> {
+    let cache_dir = get_app_data_file_path(app, "waveforms")?;
+    if !cache_dir.exists() {
+        fs::create_dir_all(&cache_dir).map_err(|e| e.to_string())?;
+    }
+
+    let key = format!("{}:{}", num_buckets, path.to_string_lossy());
+    Ok(cache_dir.join(format!("{}.json", blake3::hash(key.as_bytes()).to_hex())))
+}
+
+fn load_cached(cache_path: &Path, path: &Path) -> Option<Waveform> {
+    let content = fs::read_to_string(cache_path).ok()?;
+    let cached: CachedWaveform = serde_json::from_str(&content).ok()?;
+    if Some((cached.modified_ms, cached.size_bytes)) == file_signature(path) {
+        Some(cached.waveform)
+    } else {
+        None
+    }
+}
+
+fn save_cached(cache_path: &Path, path: &Path, waveform: &Waveform) -> Result<(), String> {
+    let Some((modified_ms, size_bytes)) = file_signature(path) else {
+        return Ok(());
+    };
+    let cached = CachedWaveform {
+        modified_ms,
+        size_bytes,
+        waveform: waveform.clone(),
+    };
+    let content = serde_json::to_string(&cached).map_err(|e| e.to_string())?;
+    let mut file = File::create(cache_path).map_err(|e| e.to_string())?;
+    file.write_all(content.as_bytes())
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// ファイル全体をデコードし、長さをnum_buckets等分した区間ごとのピークを求める
+// フレーム数より多くは分けない（短いファイルではnum_bucketsより少なくなる）
+fn compute_waveform(path: &Path, num_buckets: usize) -> Result<Waveform, String> {
+    let source = SymphoniaSource::open(path)?;
+    let channels = source.channels().max(1) as usize;
+    let duration = get_audio_duration(path).ok_or("長さが取得できません")?;
+    let total_frames = (duration * source.sample_rate() as f64).ceil().max(1.0) as usize;
+    let num_buckets = num_buckets.min(total_frames);
+    let frames_per_bucket = total_frames.div_ceil(num_buckets).max(1);
+
+    let mut min = vec![0.0f32; num_buckets];
+    let mut max = vec![0.0f32; num_buckets];
+    for (index, sample) in source.enumerate() {
+        // 長さの見積もりより長かった分は最後の区間に入れる
+        let bucket = (index / channels / frames_per_bucket).min(num_buckets - 1);
+        min[bucket] = min[bucket].min(sample);
+        max[bucket] = max[bucket].max(sample);
+    }
+
+    Ok(Waveform { min, max })
+}
+
+// 波形表示用のピーク（num_buckets個の最小値・最大値）を返す（num_bucketsは1〜10000）
+// 初回はファイル全体をデコードするため時間がかかるが、結果はキャッシュする
+#[tauri::command]
+pub async fn get_waveform(
+    path: String,
+    num_buckets: usize,
+    app: AppHandle,
+) -> Result<Waveform, String> {
+    if num_buckets == 0 {
+        return Err("num_buckets must be at least 1".to_string());
+    }
+    if num_buckets > MAX_BUCKETS {
+        return Err(format!("num_buckets must be at most {}", MAX_BUCKETS));
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let path = Path::new(&path);
+        if !path.is_file() {
+            return Err("File not found".to_string());
+        }
+
+        let cache_path = get_waveform_cache_path(&app, path, num_buckets)?;
+        if let Some(waveform) = load_cached(&cache_path, path) {
+            return Ok(waveform);
+        }
+
+        let waveform = compute_waveform(path, num_buckets)?;
+        // キャッシュに書けなくても波形は返す
+        if let Err(e) = save_cached(&cache_path, path, &waveform) {
+            eprintln!("波形のキャッシュを保存できませんでした: {}", e);
+        }
+        Ok(waveform)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}